@@ -0,0 +1,531 @@
+// At-rest encryption for the workspace database.
+//
+// The webview works on a plain SQLite image (sql.js), so encryption happens at
+// the file boundary: `open_workspace` decrypts, `save_workspace` encrypts.
+// The on-disk format is a page cipher in the spirit of SQLCipher: the database
+// is split into fixed-size pages and each page is sealed with AES-256-GCM under
+// a key derived from the passphrase with PBKDF2-HMAC-SHA256.
+//
+// Layout (all integers little-endian):
+//   0..16   magic "RentikProDBenc1\0" (never looks like "SQLite format 3\0")
+//   16      format version (1)
+//   17      kdf id (1 = PBKDF2-HMAC-SHA256)
+//   18..20  reserved
+//   20..24  kdf iterations
+//   24..28  page size
+//   28..36  plaintext length
+//   36..52  kdf salt
+//   52..60  nonce prefix, fresh for every write
+//   60..64  reserved
+//   64..    pages: ciphertext || 16-byte tag, nonce = prefix || page index (BE)
+//
+// Each page is authenticated together with the header and its index, so pages
+// cannot be reordered, truncated or moved between files.
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, KeyInit, OsRng, Payload};
+use base64::Engine;
+
+const MAGIC: &[u8; 16] = b"RentikProDBenc1\0";
+const HEADER_LEN: usize = 64;
+const TAG_LEN: usize = 16;
+const PAGE_SIZE: u32 = 4096;
+const KDF_PBKDF2_SHA256: u8 = 1;
+const DEFAULT_ITERATIONS: u32 = 310_000;
+
+pub const FORMAT_NAME: &str = "rentikpro-page-aes256gcm-v1";
+pub const KEY_SECRET_ACCOUNT: &str = "db-key";
+
+#[derive(Clone)]
+pub struct DbKey {
+  key: [u8; 32],
+  salt: [u8; 16],
+  iterations: u32,
+}
+
+struct Header {
+  iterations: u32,
+  page_size: u32,
+  plain_len: u64,
+  salt: [u8; 16],
+}
+
+// Keys unlocked during this session, by workspace root. Shared by the
+// workspace commands, backups and the sync path.
+static UNLOCKED: std::sync::Mutex<Option<std::collections::HashMap<std::path::PathBuf, DbKey>>> = std::sync::Mutex::new(None);
+
+pub fn is_encrypted_db(bytes: &[u8]) -> bool {
+  bytes.len() >= HEADER_LEN && &bytes[0..16] == MAGIC
+}
+
+fn parse_header(bytes: &[u8]) -> Result<Header, String> {
+  if !is_encrypted_db(bytes) {
+    return Err("Not an encrypted RentikPro database".to_string());
+  }
+  if bytes[16] != 1 || bytes[17] != KDF_PBKDF2_SHA256 {
+    return Err("Unsupported encrypted database version".to_string());
+  }
+  let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+  let mut salt = [0u8; 16];
+  salt.copy_from_slice(&bytes[36..52]);
+  let page_size = u32_at(24);
+  if page_size == 0 {
+    return Err("Encrypted database header is corrupt".to_string());
+  }
+  Ok(Header {
+    iterations: u32_at(20),
+    page_size,
+    plain_len: u64::from_le_bytes(bytes[28..36].try_into().unwrap()),
+    salt,
+  })
+}
+
+fn derive(passphrase: &str, salt: [u8; 16], iterations: u32) -> DbKey {
+  let mut key = [0u8; 32];
+  pbkdf2::pbkdf2_hmac::<sha2::Sha256>(passphrase.as_bytes(), &salt, iterations, &mut key);
+  DbKey { key, salt, iterations }
+}
+
+/// Derives a key for an existing encrypted file (reusing its salt), or a fresh
+/// key with a new salt when `existing` is not encrypted.
+pub fn derive_key(passphrase: &str, existing: Option<&[u8]>) -> Result<DbKey, String> {
+  if passphrase.is_empty() {
+    return Err("Passphrase cannot be empty".to_string());
+  }
+  match existing.filter(|b| is_encrypted_db(b)) {
+    Some(bytes) => {
+      let h = parse_header(bytes)?;
+      Ok(derive(passphrase, h.salt, h.iterations))
+    }
+    None => {
+      let mut salt = [0u8; 16];
+      OsRng.fill_bytes(&mut salt);
+      Ok(derive(passphrase, salt, DEFAULT_ITERATIONS))
+    }
+  }
+}
+
+fn page_nonce(prefix: &[u8], index: u32) -> [u8; 12] {
+  let mut nonce = [0u8; 12];
+  nonce[..8].copy_from_slice(prefix);
+  nonce[8..].copy_from_slice(&index.to_be_bytes());
+  nonce
+}
+
+fn page_aad(header: &[u8], index: u32) -> Vec<u8> {
+  let mut aad = Vec::with_capacity(HEADER_LEN + 4);
+  aad.extend_from_slice(header);
+  aad.extend_from_slice(&index.to_le_bytes());
+  aad
+}
+
+pub fn encrypt_db(plain: &[u8], key: &DbKey) -> Result<Vec<u8>, String> {
+  let cipher = aes_gcm::Aes256Gcm::new_from_slice(&key.key).map_err(|e| format!("Cipher init failed: {e}"))?;
+  let mut prefix = [0u8; 8];
+  OsRng.fill_bytes(&mut prefix);
+
+  let mut header = [0u8; HEADER_LEN];
+  header[0..16].copy_from_slice(MAGIC);
+  header[16] = 1;
+  header[17] = KDF_PBKDF2_SHA256;
+  header[20..24].copy_from_slice(&key.iterations.to_le_bytes());
+  header[24..28].copy_from_slice(&PAGE_SIZE.to_le_bytes());
+  header[28..36].copy_from_slice(&(plain.len() as u64).to_le_bytes());
+  header[36..52].copy_from_slice(&key.salt);
+  header[52..60].copy_from_slice(&prefix);
+
+  let pages = plain.len().div_ceil(PAGE_SIZE as usize);
+  let mut out = Vec::with_capacity(HEADER_LEN + plain.len() + pages * TAG_LEN);
+  out.extend_from_slice(&header);
+  for (i, chunk) in plain.chunks(PAGE_SIZE as usize).enumerate() {
+    let index = u32::try_from(i).map_err(|_| "Database too large to encrypt".to_string())?;
+    let sealed = cipher
+      .encrypt(aes_gcm::Nonce::from_slice(&page_nonce(&prefix, index)), Payload { msg: chunk, aad: &page_aad(&header, index) })
+      .map_err(|_| "Page encryption failed".to_string())?;
+    out.extend_from_slice(&sealed);
+  }
+  Ok(out)
+}
+
+pub fn decrypt_db(bytes: &[u8], key: &DbKey) -> Result<Vec<u8>, String> {
+  let h = parse_header(bytes)?;
+  if h.salt != key.salt {
+    return Err("Wrong key for this database".to_string());
+  }
+  let cipher = aes_gcm::Aes256Gcm::new_from_slice(&key.key).map_err(|e| format!("Cipher init failed: {e}"))?;
+  let header = &bytes[..HEADER_LEN];
+  let prefix = &bytes[52..60];
+  let sealed_page = h.page_size as usize + TAG_LEN;
+  // The header is not authenticated until the first page is, so its length
+  // must agree with the pages actually present before it sizes anything.
+  let pages = (bytes.len() - HEADER_LEN).div_ceil(sealed_page) as u64;
+  if h.plain_len.div_ceil(h.page_size as u64) != pages {
+    return Err("Encrypted database is truncated".to_string());
+  }
+
+  let mut out = Vec::with_capacity(h.plain_len as usize);
+  for (i, sealed) in bytes[HEADER_LEN..].chunks(sealed_page).enumerate() {
+    let index = u32::try_from(i).map_err(|_| "Encrypted database is corrupt".to_string())?;
+    let page = cipher
+      .decrypt(aes_gcm::Nonce::from_slice(&page_nonce(prefix, index)), Payload { msg: sealed, aad: &page_aad(header, index) })
+      .map_err(|_| {
+        if i == 0 {
+          "Wrong passphrase or corrupted database".to_string()
+        } else {
+          format!("Encrypted database page {i} is corrupted")
+        }
+      })?;
+    out.extend_from_slice(&page);
+  }
+  if out.len() as u64 != h.plain_len {
+    return Err("Encrypted database is truncated".to_string());
+  }
+  if !super::is_sqlite_bytes(&out) {
+    return Err("Decrypted data is not a SQLite database".to_string());
+  }
+  Ok(out)
+}
+
+pub fn remember_key(root: &std::path::Path, key: DbKey) {
+  let mut guard = UNLOCKED.lock().unwrap();
+  guard.get_or_insert_with(Default::default).insert(root.to_path_buf(), key);
+}
+
+pub fn forget_key(root: &std::path::Path) {
+  let mut guard = UNLOCKED.lock().unwrap();
+  if let Some(map) = guard.as_mut() {
+    map.remove(root);
+  }
+}
+
+fn keychain_key(workspace_id: &str, encrypted: &[u8]) -> Option<DbKey> {
+  let h = parse_header(encrypted).ok()?;
+  let stored = super::secrets::get_secret(workspace_id, KEY_SECRET_ACCOUNT).ok()??;
+  let raw = base64::engine::general_purpose::STANDARD.decode(stored).ok()?;
+  let key: [u8; 32] = raw.try_into().ok()?;
+  Some(DbKey { key, salt: h.salt, iterations: h.iterations })
+}
+
+pub fn store_key_in_keychain(workspace_id: &str, key: &DbKey) -> Result<(), String> {
+  super::secrets::set_secret(workspace_id, KEY_SECRET_ACCOUNT, &base64::engine::general_purpose::STANDARD.encode(key.key))
+}
+
+pub fn session_key(root: &std::path::Path) -> Option<DbKey> {
+  UNLOCKED.lock().unwrap().as_ref().and_then(|m| m.get(root).cloned())
+}
+
+/// Decrypts `encrypted` with the session key for `root` or, failing that, the
+/// key remembered in the keychain for `workspace_id`. `None` means locked.
+pub fn decrypt_with_available_key(root: &std::path::Path, workspace_id: Option<&str>, encrypted: &[u8]) -> Option<Vec<u8>> {
+  if let Some(plain) = session_key(root).and_then(|k| decrypt_db(encrypted, &k).ok()) {
+    return Some(plain);
+  }
+  let key = workspace_id.and_then(|id| keychain_key(id, encrypted))?;
+  let plain = decrypt_db(encrypted, &key).ok()?;
+  remember_key(root, key);
+  Some(plain)
+}
+
+/// Reads just enough of `path` to tell whether it is an encrypted database.
+pub fn file_is_encrypted(path: &std::path::Path) -> bool {
+  use std::io::Read;
+  let mut head = [0u8; HEADER_LEN];
+  std::fs::File::open(path)
+    .and_then(|mut f| f.read_exact(&mut head))
+    .map(|_| is_encrypted_db(&head))
+    .unwrap_or(false)
+}
+
+pub const LOCKED_ERROR: &str = "WORKSPACE_LOCKED: database.sqlite is encrypted; unlock it with the passphrase";
+
+fn checked_root(path: &str) -> Result<std::path::PathBuf, String> {
+  let root = std::path::PathBuf::from(path);
+  if !root.exists() {
+    return Err("Workspace folder does not exist".to_string());
+  }
+  if !root.is_dir() {
+    return Err("Workspace path is not a folder".to_string());
+  }
+  Ok(root)
+}
+
+fn set_workspace_encryption_meta(wjson: &std::path::Path, key: Option<&DbKey>) -> Result<(), String> {
+  let mut meta = super::read_json_file::<serde_json::Value>(wjson).unwrap_or_else(|| serde_json::json!({}));
+  if !meta.is_object() {
+    meta = serde_json::json!({});
+  }
+  let obj = meta.as_object_mut().unwrap();
+  match key {
+    Some(k) => {
+      obj.insert("encryption".to_string(), serde_json::json!({
+        "format": FORMAT_NAME,
+        "kdf": "pbkdf2-sha256",
+        "iterations": k.iterations,
+      }));
+    }
+    None => {
+      obj.remove("encryption");
+    }
+  }
+  obj.insert("updatedAt".to_string(), serde_json::json!(chrono::Utc::now().timestamp_millis()));
+  super::write_json_file(wjson, &meta)
+}
+
+/// Re-seals a backup ZIP so every plain SQLite entry in it is encrypted.
+/// Returns false when there was nothing to encrypt.
+fn encrypt_backup_zip(path: &std::path::Path, key: &DbKey) -> Result<bool, String> {
+  use std::io::{Read, Write};
+  let f = std::fs::File::open(path).map_err(|e| format!("Failed opening {}: {e}", path.display()))?;
+  let mut archive = zip::ZipArchive::new(f).map_err(|e| format!("Invalid ZIP {}: {e}", path.display()))?;
+  let mut entries = vec![];
+  for i in 0..archive.len() {
+    let mut file = archive.by_index(i).map_err(|e| format!("Failed reading {}: {e}", path.display()))?;
+    let mut bytes = vec![];
+    file.read_to_end(&mut bytes).map_err(|e| format!("Failed reading {}: {e}", path.display()))?;
+    entries.push((file.name().to_string(), bytes));
+  }
+  if !entries.iter().any(|(_, b)| super::is_sqlite_bytes(b)) {
+    return Ok(false);
+  }
+
+  let mut out = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+  let opts = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+  for (name, bytes) in entries {
+    let bytes = if super::is_sqlite_bytes(&bytes) {
+      encrypt_db(&bytes, key)?
+    } else if name == "metadata.json" {
+      match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(serde_json::Value::Object(mut meta)) => {
+          meta.insert("encrypted".to_string(), serde_json::json!(true));
+          serde_json::to_vec_pretty(&meta).map_err(|e| format!("Metadata encode failed: {e}"))?
+        }
+        _ => bytes,
+      }
+    } else {
+      bytes
+    };
+    out.start_file(name.as_str(), opts).map_err(|e| format!("ZIP start {name} failed: {e}"))?;
+    out.write_all(&bytes).map_err(|e| format!("ZIP write {name} failed: {e}"))?;
+  }
+  let zip_bytes = out.finish().map_err(|e| format!("ZIP finalize failed: {e}"))?.into_inner();
+  super::atomic_write(path, &zip_bytes)?;
+  Ok(true)
+}
+
+fn collect_files(dir: &std::path::Path, out: &mut Vec<std::path::PathBuf>) {
+  if let Ok(entries) = std::fs::read_dir(dir) {
+    out.extend(entries.flatten().map(|e| e.path()).filter(|p| p.is_file()));
+  }
+}
+
+/// Encrypts the plain database copies older runs left in the workspace:
+/// backup ZIPs, sync download backups, conflict copies and the converted
+/// folder project. Returns the files that could not be encrypted.
+fn encrypt_existing_copies(root: &std::path::Path, key: &DbKey) -> Vec<String> {
+  let (_wjson, _db, backups, _media) = super::workspace_paths(root);
+  let mut files = vec![];
+  collect_files(&backups, &mut files);
+  // Not `sync/conflicts/media`: those are the user's own files.
+  collect_files(&root.join("sync").join("conflicts"), &mut files);
+  collect_files(&root.join("sync").join("legacy-project"), &mut files);
+
+  let mut failed = vec![];
+  for path in files {
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+    let result = if ext == "zip" || ext == "rentikpro" {
+      encrypt_backup_zip(&path, key).map(|_| ())
+    } else {
+      match std::fs::read(&path) {
+        Ok(bytes) if super::is_sqlite_bytes(&bytes) => encrypt_db(&bytes, key).and_then(|enc| super::atomic_write(&path, &enc)),
+        _ => Ok(()),
+      }
+    };
+    if let Err(e) = result {
+      failed.push(e);
+    }
+  }
+  failed
+}
+
+/// Encrypts a plain workspace database in place, together with the plain
+/// copies of it already sitting in `backups/` and `sync/`.
+#[tauri::command]
+pub fn workspace_enable_encryption(path: String, passphrase: String, remember: bool) -> Result<(), String> {
+  let root = checked_root(&path)?;
+  let (wjson, db, _backups, _media) = super::workspace_paths(&root);
+  // A save landing between the read and the write would be lost.
+  let guard = super::DB_WRITE_LOCK.lock().unwrap();
+  let bytes = std::fs::read(&db).map_err(|e| format!("Failed reading {}: {e}", db.display()))?;
+  if is_encrypted_db(&bytes) {
+    return Err("Workspace database is already encrypted".to_string());
+  }
+  if !super::is_sqlite_bytes(&bytes) {
+    return Err(format!("{} is not a valid SQLite database", super::WORKSPACE_DB_NAME));
+  }
+
+  let key = derive_key(&passphrase, None)?;
+  let encrypted = encrypt_db(&bytes, &key)?;
+  if decrypt_db(&encrypted, &key)? != bytes {
+    return Err("Encryption verification failed; database left unchanged".to_string());
+  }
+  super::atomic_write(&db, &encrypted)?;
+  super::workspace_watcher::record(&db, &encrypted);
+  drop(guard);
+  set_workspace_encryption_meta(&wjson, Some(&key))?;
+
+  if remember {
+    if let Some(id) = super::workspace_id_of(&root) {
+      store_key_in_keychain(&id, &key)?;
+    }
+  }
+  let failed = encrypt_existing_copies(&root, &key);
+  remember_key(&root, key);
+  if !failed.is_empty() {
+    return Err(format!(
+      "Database encrypted, but {} older copies are still unencrypted: {}",
+      failed.len(),
+      failed.join("; ")
+    ));
+  }
+  Ok(())
+}
+
+/// Writes the database back in plain SQLite form.
+#[tauri::command]
+pub fn workspace_disable_encryption(path: String, passphrase: String) -> Result<(), String> {
+  let root = checked_root(&path)?;
+  let (wjson, db, _backups, _media) = super::workspace_paths(&root);
+  let guard = super::DB_WRITE_LOCK.lock().unwrap();
+  let bytes = std::fs::read(&db).map_err(|e| format!("Failed reading {}: {e}", db.display()))?;
+  if !is_encrypted_db(&bytes) {
+    return Err("Workspace database is not encrypted".to_string());
+  }
+  let key = derive_key(&passphrase, Some(&bytes))?;
+  let plain = decrypt_db(&bytes, &key)?;
+  super::atomic_write(&db, &plain)?;
+  super::workspace_watcher::record(&db, &plain);
+  drop(guard);
+  set_workspace_encryption_meta(&wjson, None)?;
+
+  if let Some(id) = super::workspace_id_of(&root) {
+    super::secrets::delete_secret(&id, KEY_SECRET_ACCOUNT).ok();
+  }
+  forget_key(&root);
+  Ok(())
+}
+
+/// Checks the passphrase and keeps the key for this session (and optionally
+/// in the keychain, so the next launch opens without asking).
+#[tauri::command]
+pub fn workspace_unlock(path: String, passphrase: String, remember: bool) -> Result<(), String> {
+  let root = checked_root(&path)?;
+  let (_wjson, db, _backups, _media) = super::workspace_paths(&root);
+  let bytes = std::fs::read(&db).map_err(|e| format!("Failed reading {}: {e}", db.display()))?;
+  if !is_encrypted_db(&bytes) {
+    return Err("Workspace database is not encrypted".to_string());
+  }
+  let key = derive_key(&passphrase, Some(&bytes))?;
+  decrypt_db(&bytes, &key)?;
+  if remember {
+    if let Some(id) = super::workspace_id_of(&root) {
+      store_key_in_keychain(&id, &key)?;
+    }
+  }
+  remember_key(&root, key);
+  Ok(())
+}
+
+/// Drops the session key; with `forget`, also the keychain copy.
+#[tauri::command]
+pub fn workspace_lock(path: String, forget: bool) -> Result<(), String> {
+  let root = std::path::PathBuf::from(&path);
+  forget_key(&root);
+  if forget {
+    if let Some(id) = super::workspace_id_of(&root) {
+      super::secrets::delete_secret(&id, KEY_SECRET_ACCOUNT)?;
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Few iterations: the KDF cost is not what is under test.
+  fn key(passphrase: &str) -> DbKey {
+    derive(passphrase, [7u8; 16], 1000)
+  }
+
+  fn plain_db() -> Vec<u8> {
+    let mut plain = b"SQLite format 3\0".to_vec();
+    plain.extend((0..3 * PAGE_SIZE as usize).map(|i| (i % 251) as u8));
+    plain
+  }
+
+  fn sealed_page(enc: &[u8], i: usize) -> std::ops::Range<usize> {
+    let len = PAGE_SIZE as usize + TAG_LEN;
+    HEADER_LEN + i * len..(HEADER_LEN + (i + 1) * len).min(enc.len())
+  }
+
+  #[test]
+  fn round_trip() {
+    let plain = plain_db();
+    let k = key("secret");
+    let enc = encrypt_db(&plain, &k).unwrap();
+    assert!(is_encrypted_db(&enc));
+    assert!(!super::super::is_sqlite_bytes(&enc));
+    assert_eq!(decrypt_db(&enc, &k).unwrap(), plain);
+  }
+
+  #[test]
+  fn plain_sqlite_is_not_encrypted() {
+    assert!(!is_encrypted_db(&plain_db()));
+    assert!(!is_encrypted_db(MAGIC));
+  }
+
+  #[test]
+  fn wrong_passphrase_fails() {
+    let enc = encrypt_db(&plain_db(), &key("secret")).unwrap();
+    let wrong = derive_key("guess", Some(&enc)).unwrap();
+    assert_eq!(decrypt_db(&enc, &wrong).err().unwrap(), "Wrong passphrase or corrupted database");
+  }
+
+  #[test]
+  fn tampered_page_fails() {
+    let k = key("secret");
+    let mut enc = encrypt_db(&plain_db(), &k).unwrap();
+    let at = sealed_page(&enc, 1).start + 10;
+    enc[at] ^= 1;
+    assert!(decrypt_db(&enc, &k).err().unwrap().contains("page 1"));
+  }
+
+  #[test]
+  fn reordered_pages_fail() {
+    let k = key("secret");
+    let enc = encrypt_db(&plain_db(), &k).unwrap();
+    let (p1, p2) = (sealed_page(&enc, 1), sealed_page(&enc, 2));
+    let mut swapped = enc[..p1.start].to_vec();
+    swapped.extend_from_slice(&enc[p2.clone()]);
+    swapped.extend_from_slice(&enc[p1]);
+    swapped.extend_from_slice(&enc[p2.end..]);
+    assert!(decrypt_db(&swapped, &k).is_err());
+  }
+
+  #[test]
+  fn truncated_file_fails() {
+    let k = key("secret");
+    let enc = encrypt_db(&plain_db(), &k).unwrap();
+    let last = sealed_page(&enc, 3);
+    assert_eq!(decrypt_db(&enc[..last.start], &k).err().unwrap(), "Encrypted database is truncated");
+    assert!(decrypt_db(&enc[..last.end - 1], &k).is_err());
+  }
+
+  #[test]
+  fn oversized_length_is_rejected_before_allocating() {
+    let k = key("secret");
+    let mut enc = encrypt_db(&plain_db(), &k).unwrap();
+    enc[28..36].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert_eq!(decrypt_db(&enc, &k).err().unwrap(), "Encrypted database is truncated");
+  }
+}
@@ -0,0 +1,273 @@
+// MIME parsing shared by the IMAP fetcher and the file importer
+// (`import_emails`).
+//
+// The result is handed to `bookingEmailParser.ts`, which owns `email_ingest`;
+// Rust only decodes (charsets, quoted-printable, base64) and normalises.
+
+use base64::Engine;
+use mail_parser::MimeHeaders;
+
+/// Attachments above this size are listed without their contents.
+const MAX_INLINE_ATTACHMENT_BYTES: usize = 10 * 1024 * 1024;
+/// Messages returned by one `import_emails` call unless the caller asks for
+/// a different page size.
+const DEFAULT_IMPORT_PAGE: usize = 200;
+
+#[derive(serde::Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ParsedAttachment {
+  filename: Option<String>,
+  content_type: String,
+  content_id: Option<String>,
+  size: usize,
+  /// None when the attachment is larger than the inline limit, and always for
+  /// `import_emails`, which returns attachments by reference: fetch one with
+  /// `import_email_attachment` and its position in `attachments`.
+  data_base64: Option<String>,
+}
+
+#[derive(serde::Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ParsedEmail {
+  /// Message-ID, or `sha256:<hex>` of the raw message when the header is missing.
+  pub dedupe_key: String,
+  pub message_id: Option<String>,
+  pub in_reply_to: Option<String>,
+  pub from: Option<String>,
+  pub from_name: Option<String>,
+  pub to: Vec<String>,
+  pub cc: Vec<String>,
+  pub reply_to: Option<String>,
+  pub subject: Option<String>,
+  /// Date header in milliseconds since the epoch.
+  pub date: Option<i64>,
+  pub text_body: Option<String>,
+  pub html_body: Option<String>,
+  pub attachments: Vec<ParsedAttachment>,
+  pub size: usize,
+}
+
+fn addresses(addr: Option<&mail_parser::Address>) -> Vec<String> {
+  addr
+    .map(|a| a.iter().filter_map(|x| x.address.as_ref().map(|s| s.to_string())).collect())
+    .unwrap_or_default()
+}
+
+fn join_bodies<'x>(parts: impl Iterator<Item = &'x mail_parser::MessagePart<'x>>) -> Option<String> {
+  let text: Vec<&str> = parts.filter_map(|p| p.text_contents()).filter(|t| !t.trim().is_empty()).collect();
+  if text.is_empty() {
+    None
+  } else {
+    Some(text.join("\n"))
+  }
+}
+
+pub fn parse_email(raw: &[u8]) -> Result<ParsedEmail, String> {
+  parse_email_with(raw, true)
+}
+
+fn attachment_content_type(part: &mail_parser::MessagePart) -> String {
+  part
+    .content_type()
+    .map(|ct| match ct.subtype() {
+      Some(sub) => format!("{}/{}", ct.ctype(), sub),
+      None => ct.ctype().to_string(),
+    })
+    .unwrap_or_else(|| "application/octet-stream".to_string())
+}
+
+fn parse_email_with(raw: &[u8], inline_attachments: bool) -> Result<ParsedEmail, String> {
+  let msg = mail_parser::MessageParser::default()
+    .parse(raw)
+    .ok_or_else(|| "Not a valid MIME message".to_string())?;
+
+  let message_id = msg.message_id().map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+  let dedupe_key = match &message_id {
+    Some(id) => id.clone(),
+    None => format!("sha256:{}", super::sha256_hex(raw)),
+  };
+  let from_addr = msg.from().and_then(|a| a.first());
+
+  let attachments = msg
+    .attachments()
+    .map(|part| {
+      let contents = part.contents();
+      let inline = inline_attachments && contents.len() <= MAX_INLINE_ATTACHMENT_BYTES;
+      ParsedAttachment {
+        filename: part.attachment_name().map(String::from),
+        content_type: attachment_content_type(part),
+        content_id: part.content_id().map(String::from),
+        size: contents.len(),
+        data_base64: inline.then(|| base64::engine::general_purpose::STANDARD.encode(contents)),
+      }
+    })
+    .collect();
+
+  Ok(ParsedEmail {
+    dedupe_key,
+    message_id,
+    in_reply_to: msg.in_reply_to().as_text().map(String::from),
+    from: from_addr.and_then(|a| a.address.as_ref().map(|s| s.to_string())),
+    from_name: from_addr.and_then(|a| a.name.as_ref().map(|s| s.to_string())),
+    to: addresses(msg.to()),
+    cc: addresses(msg.cc()),
+    reply_to: msg.reply_to().and_then(|a| a.first()).and_then(|a| a.address.as_ref().map(|s| s.to_string())),
+    subject: msg.subject().map(String::from),
+    date: msg.date().map(|d| d.to_timestamp() * 1000),
+    text_body: join_bodies(msg.text_bodies()),
+    html_body: join_bodies(msg.html_bodies()),
+    attachments,
+    size: raw.len(),
+  })
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedEmail {
+  source: String,
+  /// Position inside an mbox file; 0 for .eml.
+  index: usize,
+  #[serde(flatten)]
+  email: ParsedEmail,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportFileError {
+  source: String,
+  index: Option<usize>,
+  error: String,
+}
+
+#[derive(serde::Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportEmailsResult {
+  emails: Vec<ImportedEmail>,
+  /// Messages skipped because their dedupe key was already seen.
+  duplicates: usize,
+  errors: Vec<ImportFileError>,
+  /// Pass as `offset` to get the next page; None once every file is done.
+  next_offset: Option<usize>,
+}
+
+fn read_head(path: &std::path::Path) -> std::io::Result<Vec<u8>> {
+  use std::io::Read;
+  let mut head = Vec::with_capacity(5);
+  std::fs::File::open(path)?.take(5).read_to_end(&mut head)?;
+  Ok(head)
+}
+
+/// mbox when the extension says so or the file starts with a "From " line.
+fn is_mbox(path: &std::path::Path, head: &[u8]) -> bool {
+  let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+  ext == "mbox" || ext == "mbx" || head.starts_with(b"From ")
+}
+
+/// Calls `f` with every raw message in `path` (one for .eml), streaming mbox
+/// files instead of loading them whole. `f` returns false to stop early.
+fn for_each_message(path: &std::path::Path, mut f: impl FnMut(usize, Result<Vec<u8>, String>) -> bool) -> Result<(), String> {
+  let head = read_head(path).map_err(|e| format!("Failed reading file: {e}"))?;
+  if !is_mbox(path, &head) {
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed reading file: {e}"))?;
+    f(0, Ok(bytes));
+    return Ok(());
+  }
+  let file = std::fs::File::open(path).map_err(|e| format!("Failed reading file: {e}"))?;
+  for (index, entry) in mail_parser::mailbox::mbox::MessageIterator::new(file).enumerate() {
+    let raw = entry.map(|m| m.unwrap_contents()).map_err(|_| "Malformed mbox entry".to_string());
+    if !f(index, raw) {
+      break;
+    }
+  }
+  Ok(())
+}
+
+fn import_page(files: Vec<String>, known_keys: Vec<String>, offset: usize, limit: usize) -> ImportEmailsResult {
+  let mut seen: std::collections::HashSet<String> = known_keys.into_iter().collect();
+  let mut result = ImportEmailsResult::default();
+  // Position across all files, so a page can resume where the last one ended.
+  let mut position = 0usize;
+  let mut returned = 0usize;
+
+  for file in files {
+    if result.next_offset.is_some() {
+      break;
+    }
+    let read = for_each_message(std::path::Path::new(&file), |index, raw| {
+      position += 1;
+      if position <= offset {
+        return true;
+      }
+      if returned == limit {
+        result.next_offset = Some(position - 1);
+        return false;
+      }
+      match raw.and_then(|raw| parse_email_with(&raw, false)) {
+        Ok(email) => {
+          if seen.insert(email.dedupe_key.clone()) {
+            returned += 1;
+            result.emails.push(ImportedEmail { source: file.clone(), index, email });
+          } else {
+            result.duplicates += 1;
+          }
+        }
+        Err(error) => result.errors.push(ImportFileError { source: file.clone(), index: Some(index), error }),
+      }
+      true
+    });
+    if let Err(error) = read {
+      position += 1;
+      if position > offset {
+        result.errors.push(ImportFileError { source: file, index: None, error });
+      }
+    }
+  }
+  result
+}
+
+/// Parses `.eml` and `.mbox` files for the email ingest pipeline, one page of
+/// at most `limit` messages at a time, starting at `offset` (the previous
+/// page's `nextOffset`). Attachments are returned by reference.
+///
+/// `known_keys` are dedupe keys already present in `email_ingest`; they are
+/// skipped along with duplicates inside the page. Add the keys of earlier
+/// pages when asking for the next one.
+#[tauri::command]
+pub async fn import_emails(
+  files: Vec<String>,
+  known_keys: Option<Vec<String>>,
+  offset: Option<usize>,
+  limit: Option<usize>,
+) -> Result<ImportEmailsResult, String> {
+  let limit = limit.unwrap_or(DEFAULT_IMPORT_PAGE).max(1);
+  tauri::async_runtime::spawn_blocking(move || import_page(files, known_keys.unwrap_or_default(), offset.unwrap_or(0), limit))
+    .await
+    .map_err(|e| format!("Import task failed: {e}"))
+}
+
+fn read_attachment(source: &str, index: usize, attachment: usize) -> Result<String, String> {
+  let mut raw = None;
+  for_each_message(std::path::Path::new(source), |i, entry| {
+    if i == index {
+      raw = Some(entry);
+      return false;
+    }
+    true
+  })?;
+  let raw = raw.ok_or_else(|| format!("No message {index} in {source}"))??;
+  let msg = mail_parser::MessageParser::default()
+    .parse(&raw)
+    .ok_or_else(|| "Not a valid MIME message".to_string())?;
+  let part = msg.attachments().nth(attachment).ok_or_else(|| format!("Message has no attachment {attachment}"))?;
+  Ok(base64::engine::general_purpose::STANDARD.encode(part.contents()))
+}
+
+/// Contents (base64) of one attachment of a message listed by
+/// `import_emails`: `source` and `index` as returned there, `attachment` its
+/// position in `attachments`.
+#[tauri::command]
+pub async fn import_email_attachment(source: String, index: usize, attachment: usize) -> Result<String, String> {
+  tauri::async_runtime::spawn_blocking(move || read_attachment(&source, index, attachment))
+    .await
+    .map_err(|e| format!("Import task failed: {e}"))?
+}
@@ -0,0 +1,506 @@
+// Outbound email queue persisted in the workspace (`sync/outbox/<id>.json`).
+//
+// Mail is queued by the webview and sent from Rust, so a campaign or a booking
+// confirmation survives the app closing mid-send. When an item finishes, the
+// worker records the outcome in the row its `logRef` points at
+// (`marketing_email_logs` or `messages`) straight in `database.sqlite`, so
+// delivery is logged even if the window is closed. The progress event carries
+// the new `dbSha256`; the webview applies the same status to its copy and
+// saves against that hash. Items stay listed until `outbox_ack`.
+//
+// SMTP passwords are never part of a queued item; they are set once with
+// `smtp_set_password` and live in the secret store under the workspace id.
+
+use tauri::Emitter;
+
+pub const PROGRESS_EVENT: &str = "email-outbox:progress";
+const OUTBOX_DIR: &str = "outbox";
+const DEFAULT_MAX_ATTEMPTS: u32 = 8;
+const BACKOFF_BASE_SECS: i64 = 60;
+const BACKOFF_MAX_SECS: i64 = 6 * 60 * 60;
+const IDLE_POLL_SECS: u64 = 60;
+
+// Serialises read-modify-write of outbox items between the worker and commands.
+static OUTBOX_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// Row the frontend should update once the item is finished.
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OutboxLogRef {
+  /// "marketing_email_logs" or "messages".
+  pub table: String,
+  pub id: String,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OutboxItemV1 {
+  version: u32,
+  id: String,
+  created_at: i64,
+  /// "pending" | "sending" | "sent" | "failed"
+  status: String,
+  attempts: u32,
+  max_attempts: u32,
+  next_attempt_at: i64,
+  last_attempt_at: Option<i64>,
+  last_error: Option<String>,
+  last_code: Option<u16>,
+  sent_at: Option<i64>,
+  message_id: Option<String>,
+  log_ref: Option<OutboxLogRef>,
+  /// When the outcome was written to the `log_ref` row.
+  #[serde(default)]
+  log_recorded_at: Option<i64>,
+  #[serde(default)]
+  log_error: Option<String>,
+  email: super::smtp::SendEmailArgs,
+}
+
+/// What the frontend sees: everything except the message body.
+#[derive(serde::Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OutboxItemSummary {
+  id: String,
+  created_at: i64,
+  status: String,
+  attempts: u32,
+  max_attempts: u32,
+  next_attempt_at: i64,
+  last_error: Option<String>,
+  last_code: Option<u16>,
+  sent_at: Option<i64>,
+  message_id: Option<String>,
+  log_ref: Option<OutboxLogRef>,
+  log_recorded_at: Option<i64>,
+  log_error: Option<String>,
+  /// Hash of `database.sqlite` right after the worker wrote the log row.
+  db_sha256: Option<String>,
+  to: Vec<String>,
+  subject: String,
+}
+
+impl From<&OutboxItemV1> for OutboxItemSummary {
+  fn from(item: &OutboxItemV1) -> Self {
+    OutboxItemSummary {
+      id: item.id.clone(),
+      created_at: item.created_at,
+      status: item.status.clone(),
+      attempts: item.attempts,
+      max_attempts: item.max_attempts,
+      next_attempt_at: item.next_attempt_at,
+      last_error: item.last_error.clone(),
+      last_code: item.last_code,
+      sent_at: item.sent_at,
+      message_id: item.message_id.clone(),
+      log_ref: item.log_ref.clone(),
+      log_recorded_at: item.log_recorded_at,
+      log_error: item.log_error.clone(),
+      db_sha256: None,
+      to: item.email.to.clone(),
+      subject: item.email.subject.clone(),
+    }
+  }
+}
+
+struct OutboxHandle {
+  root: std::path::PathBuf,
+  wake: std::sync::Arc<tokio::sync::Notify>,
+  stop: std::sync::Arc<tokio::sync::Notify>,
+  task: tauri::async_runtime::JoinHandle<()>,
+}
+
+#[derive(Default)]
+pub struct OutboxState {
+  handle: std::sync::Mutex<Option<OutboxHandle>>,
+}
+
+impl OutboxState {
+  fn wake_for(&self, root: &std::path::Path) {
+    if let Some(h) = self.handle.lock().unwrap().as_ref() {
+      if h.root == root {
+        h.wake.notify_one();
+      }
+    }
+  }
+
+  fn stop_current(&self) {
+    if let Some(h) = self.handle.lock().unwrap().take() {
+      h.stop.notify_one();
+      h.task.abort();
+    }
+  }
+}
+
+fn now_ms() -> i64 {
+  chrono::Utc::now().timestamp_millis()
+}
+
+fn backoff_secs(attempts: u32) -> i64 {
+  let exp = attempts.saturating_sub(1).min(16);
+  BACKOFF_BASE_SECS.saturating_mul(1i64 << exp).min(BACKOFF_MAX_SECS)
+}
+
+fn outbox_dir(root: &std::path::Path) -> std::path::PathBuf {
+  root.join("sync").join(OUTBOX_DIR)
+}
+
+fn item_path(root: &std::path::Path, id: &str) -> Result<std::path::PathBuf, String> {
+  if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+    return Err(format!("Invalid outbox id: {id}"));
+  }
+  Ok(outbox_dir(root).join(format!("{id}.json")))
+}
+
+fn write_item(root: &std::path::Path, item: &OutboxItemV1) -> Result<(), String> {
+  let txt = serde_json::to_vec_pretty(item).map_err(|e| format!("Outbox encode failed: {e}"))?;
+  super::atomic_write(&item_path(root, &item.id)?, &txt)
+}
+
+fn read_items(root: &std::path::Path) -> Vec<OutboxItemV1> {
+  let entries = match std::fs::read_dir(outbox_dir(root)) {
+    Ok(e) => e,
+    Err(_) => return vec![],
+  };
+  let mut items: Vec<OutboxItemV1> = entries
+    .filter_map(|e| e.ok())
+    .map(|e| e.path())
+    .filter(|p| p.extension().and_then(|x| x.to_str()) == Some("json"))
+    .filter_map(|p| super::read_json_file::<OutboxItemV1>(&p))
+    .collect();
+  items.sort_by_key(|i| i.created_at);
+  items
+}
+
+fn checked_root(path: &str) -> Result<std::path::PathBuf, String> {
+  let root = std::path::PathBuf::from(path);
+  if !root.is_dir() {
+    return Err("Workspace folder does not exist".to_string());
+  }
+  Ok(root)
+}
+
+fn emit_progress(app: &tauri::AppHandle, item: &OutboxItemV1, db_sha256: Option<String>) {
+  let _ = app.emit(PROGRESS_EVENT, OutboxItemSummary { db_sha256, ..OutboxItemSummary::from(item) });
+}
+
+/// Writes a finished item's outcome to its log row. `Ok(None)` when there is
+/// nothing to record.
+fn write_log_row(root: &std::path::Path, item: &OutboxItemV1) -> Result<Option<String>, String> {
+  let log_ref = match &item.log_ref {
+    Some(r) if item.status == "sent" || item.status == "failed" => r,
+    _ => return Ok(None),
+  };
+  let status = if item.status == "sent" { "SENT" } else { "FAILED" };
+  let error = if item.status == "sent" { None } else { item.last_error.clone() };
+  let (updated, sha) = super::workspace_db::modify(root, |conn| {
+    let sql = match log_ref.table.as_str() {
+      "marketing_email_logs" => "UPDATE marketing_email_logs SET status = ?1, error_message = ?2 WHERE id = ?3",
+      "messages" => "UPDATE messages SET status = ?1, error_message = ?2, sent_at = COALESCE(?4, sent_at), external_id = COALESCE(external_id, ?5) WHERE id = ?3",
+      other => return Err(format!("Unknown log table: {other}")),
+    };
+    let mut stmt = conn.prepare(sql).map_err(|e| format!("Log update failed: {e}"))?;
+    let updated = if log_ref.table == "messages" {
+      stmt.execute(rusqlite::params![status, error, log_ref.id, item.sent_at, item.message_id])
+    } else {
+      stmt.execute(rusqlite::params![status, error, log_ref.id])
+    };
+    updated.map_err(|e| format!("Log update failed: {e}"))
+  })?;
+  if updated == 0 {
+    return Err(format!("No {} row with id {}", log_ref.table, log_ref.id));
+  }
+  Ok(Some(sha))
+}
+
+/// Records the outcome of a finished item, at most once. A locked workspace
+/// is retried on a later pass; a missing row is reported and given up on.
+fn record_log(root: &std::path::Path, item: &mut OutboxItemV1) -> Option<String> {
+  if item.log_recorded_at.is_some() {
+    return None;
+  }
+  match write_log_row(root, item) {
+    Ok(sha) => {
+      if sha.is_some() {
+        item.log_recorded_at = Some(now_ms());
+        item.log_error = None;
+      }
+      sha
+    }
+    Err(e) => {
+      if e != super::db_crypto::LOCKED_ERROR {
+        item.log_recorded_at = Some(now_ms());
+      }
+      item.log_error = Some(e);
+      None
+    }
+  }
+}
+
+/// Claims the item for sending, so a concurrent `outbox_retry` or a second
+/// pass does not pick it up again.
+fn claim(root: &std::path::Path, id: &str) -> Option<OutboxItemV1> {
+  let _guard = OUTBOX_LOCK.lock().unwrap();
+  let mut item = super::read_json_file::<OutboxItemV1>(&item_path(root, id).ok()?)?;
+  if item.status != "pending" || item.next_attempt_at > now_ms() {
+    return None;
+  }
+  item.status = "sending".to_string();
+  item.attempts += 1;
+  item.last_attempt_at = Some(now_ms());
+  write_item(root, &item).ok()?;
+  Some(item)
+}
+
+async fn send_item(app: &tauri::AppHandle, root: &std::path::Path, mut item: OutboxItemV1) {
+  emit_progress(app, &item, None);
+  let result = super::smtp::send(&item.email).await;
+
+  item.last_code = result.code;
+  if result.success {
+    item.status = "sent".to_string();
+    item.sent_at = Some(now_ms());
+    item.message_id = result.message_id;
+    item.last_error = None;
+  } else {
+    item.last_error = result.error;
+    if result.retryable && item.attempts < item.max_attempts {
+      item.status = "pending".to_string();
+      item.next_attempt_at = now_ms() + backoff_secs(item.attempts) * 1000;
+    } else {
+      item.status = "failed".to_string();
+    }
+  }
+
+  let db_sha256 = record_log(root, &mut item);
+  {
+    let _guard = OUTBOX_LOCK.lock().unwrap();
+    if let Err(e) = write_item(root, &item) {
+      item.last_error = Some(e);
+    }
+  }
+  emit_progress(app, &item, db_sha256);
+}
+
+/// Retries log rows that could not be written earlier (workspace locked).
+fn record_pending_logs(app: &tauri::AppHandle, root: &std::path::Path) {
+  let _guard = OUTBOX_LOCK.lock().unwrap();
+  let unrecorded = read_items(root)
+    .into_iter()
+    .filter(|i| (i.status == "sent" || i.status == "failed") && i.log_ref.is_some() && i.log_recorded_at.is_none());
+  for mut item in unrecorded {
+    let db_sha256 = record_log(root, &mut item);
+    if item.log_recorded_at.is_some() && write_item(root, &item).is_ok() {
+      emit_progress(app, &item, db_sha256);
+    }
+  }
+}
+
+async fn outbox_loop(app: tauri::AppHandle, root: std::path::PathBuf, wake: std::sync::Arc<tokio::sync::Notify>, stop: std::sync::Arc<tokio::sync::Notify>) {
+  // An item left in "sending" was interrupted by a crash or a quit. The server
+  // may or may not have accepted it; sending again is the lesser evil.
+  {
+    let _guard = OUTBOX_LOCK.lock().unwrap();
+    for mut item in read_items(&root).into_iter().filter(|i| i.status == "sending") {
+      item.status = "pending".to_string();
+      let _ = write_item(&root, &item);
+    }
+  }
+
+  loop {
+    record_pending_logs(&app, &root);
+    let now = now_ms();
+    let items = read_items(&root);
+    for due in items.iter().filter(|i| i.status == "pending" && i.next_attempt_at <= now) {
+      if let Some(item) = claim(&root, &due.id) {
+        send_item(&app, &root, item).await;
+      }
+    }
+
+    let next_due = read_items(&root)
+      .iter()
+      .filter(|i| i.status == "pending")
+      .map(|i| i.next_attempt_at)
+      .min();
+    let wait_ms = next_due
+      .map(|at| (at - now_ms()).max(0) as u64)
+      .unwrap_or(IDLE_POLL_SECS * 1000)
+      .min(IDLE_POLL_SECS * 1000);
+
+    tokio::select! {
+      _ = stop.notified() => return,
+      _ = wake.notified() => {}
+      _ = tokio::time::sleep(std::time::Duration::from_millis(wait_ms)) => {}
+    }
+  }
+}
+
+/// Starts the send worker for a workspace, replacing any previous one.
+#[tauri::command]
+pub fn outbox_start(app: tauri::AppHandle, state: tauri::State<'_, OutboxState>, workspace_path: String) -> Result<(), String> {
+  let root = checked_root(&workspace_path)?;
+  state.stop_current();
+  let wake = std::sync::Arc::new(tokio::sync::Notify::new());
+  let stop = std::sync::Arc::new(tokio::sync::Notify::new());
+  let task = tauri::async_runtime::spawn(outbox_loop(app, root.clone(), wake.clone(), stop.clone()));
+  *state.handle.lock().unwrap() = Some(OutboxHandle { root, wake, stop, task });
+  Ok(())
+}
+
+#[tauri::command]
+pub fn outbox_stop(state: tauri::State<'_, OutboxState>) -> Result<(), String> {
+  state.stop_current();
+  Ok(())
+}
+
+/// Queues a message. The SMTP password must already be stored with
+/// `smtp_set_password`; an item carrying one is refused.
+#[tauri::command]
+pub fn outbox_enqueue(
+  state: tauri::State<'_, OutboxState>,
+  workspace_path: String,
+  mut email: super::smtp::SendEmailArgs,
+  log_ref: Option<OutboxLogRef>,
+  max_attempts: Option<u32>,
+) -> Result<OutboxItemSummary, String> {
+  let root = checked_root(&workspace_path)?;
+  if email.to.is_empty() {
+    return Err("At least one recipient is required".to_string());
+  }
+  if email.workspace_id.as_deref().map(|s| s.trim().is_empty()).unwrap_or(true) {
+    email.workspace_id = super::workspace_id_of(&root);
+  }
+  if email.smtp.pass.as_deref().is_some_and(|p| !p.is_empty()) {
+    return Err("Queued mail must not carry the SMTP password; store it with smtp_set_password".to_string());
+  }
+  if email.workspace_path.is_none() {
+    email.workspace_path = Some(workspace_path.clone());
+  }
+
+  let now = now_ms();
+  let item = OutboxItemV1 {
+    version: 1,
+    id: format!("{}-{:08x}", now, rand_u32()),
+    created_at: now,
+    status: "pending".to_string(),
+    attempts: 0,
+    max_attempts: max_attempts.unwrap_or(DEFAULT_MAX_ATTEMPTS).max(1),
+    next_attempt_at: now,
+    last_attempt_at: None,
+    last_error: None,
+    last_code: None,
+    sent_at: None,
+    message_id: None,
+    log_ref,
+    log_recorded_at: None,
+    log_error: None,
+    email,
+  };
+  {
+    let _guard = OUTBOX_LOCK.lock().unwrap();
+    write_item(&root, &item)?;
+  }
+  state.wake_for(&root);
+  Ok(OutboxItemSummary::from(&item))
+}
+
+fn rand_u32() -> u32 {
+  use aes_gcm::aead::rand_core::RngCore;
+  aes_gcm::aead::OsRng.next_u32()
+}
+
+#[tauri::command]
+pub fn outbox_list(workspace_path: String) -> Result<Vec<OutboxItemSummary>, String> {
+  let root = checked_root(&workspace_path)?;
+  Ok(read_items(&root).iter().map(OutboxItemSummary::from).collect())
+}
+
+/// Removes finished items the frontend no longer needs to list. Items that
+/// are still pending, being sent or waiting to have their log row written
+/// are left alone.
+#[tauri::command]
+pub fn outbox_ack(workspace_path: String, ids: Vec<String>) -> Result<Vec<String>, String> {
+  let root = checked_root(&workspace_path)?;
+  let _guard = OUTBOX_LOCK.lock().unwrap();
+  let mut removed = vec![];
+  for id in ids {
+    let path = item_path(&root, &id)?;
+    let done = super::read_json_file::<OutboxItemV1>(&path)
+      .map(|i| (i.status == "sent" || i.status == "failed") && (i.log_ref.is_none() || i.log_recorded_at.is_some()))
+      .unwrap_or(false);
+    if done {
+      std::fs::remove_file(&path).map_err(|e| format!("Failed removing {}: {e}", path.display()))?;
+      removed.push(id);
+    }
+  }
+  Ok(removed)
+}
+
+/// Puts a failed item back in the queue for an immediate attempt.
+/// Queues a failed item again. The FAILED outcome already logged must not
+/// stop the retry's own outcome from being recorded.
+fn reset_for_retry(item: &mut OutboxItemV1) {
+  item.status = "pending".to_string();
+  item.max_attempts = item.max_attempts.max(item.attempts + 1);
+  item.next_attempt_at = now_ms();
+  item.log_recorded_at = None;
+  item.log_error = None;
+}
+
+#[tauri::command]
+pub fn outbox_retry(state: tauri::State<'_, OutboxState>, workspace_path: String, id: String) -> Result<OutboxItemSummary, String> {
+  let root = checked_root(&workspace_path)?;
+  let item = {
+    let _guard = OUTBOX_LOCK.lock().unwrap();
+    let mut item = super::read_json_file::<OutboxItemV1>(&item_path(&root, &id)?).ok_or_else(|| format!("Outbox item {id} not found"))?;
+    if item.status != "failed" {
+      return Err(format!("Outbox item {id} is {}, not failed", item.status));
+    }
+    reset_for_retry(&mut item);
+    write_item(&root, &item)?;
+    item
+  };
+  state.wake_for(&root);
+  Ok(OutboxItemSummary::from(&item))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn log_status(root: &std::path::Path) -> String {
+    let bytes = std::fs::read(root.join(super::super::WORKSPACE_DB_NAME)).unwrap();
+    let conn = super::super::workspace_db::open_in_memory(&bytes, true).unwrap();
+    conn.query_row("SELECT status FROM marketing_email_logs WHERE id = 'log1'", [], |r| r.get(0)).unwrap()
+  }
+
+  #[test]
+  fn retried_item_records_its_new_outcome() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    conn
+      .execute_batch("CREATE TABLE marketing_email_logs (id TEXT PRIMARY KEY, status TEXT, error_message TEXT); INSERT INTO marketing_email_logs VALUES ('log1', 'PENDING', NULL);")
+      .unwrap();
+    std::fs::write(root.join(super::super::WORKSPACE_DB_NAME), super::super::workspace_db::to_bytes(&conn).unwrap()).unwrap();
+
+    let mut item: OutboxItemV1 = serde_json::from_value(serde_json::json!({
+      "version": 1, "id": "m1", "createdAt": 0, "status": "failed", "attempts": 8, "maxAttempts": 8, "nextAttemptAt": 0,
+      "lastAttemptAt": null, "lastError": "550 mailbox unavailable", "lastCode": 550, "sentAt": null, "messageId": null,
+      "logRef": { "table": "marketing_email_logs", "id": "log1" },
+      "email": { "smtp": { "host": "smtp.example.com" }, "from": "a@example.com", "to": ["b@example.com"], "subject": "Hi" }
+    }))
+    .unwrap();
+
+    assert!(record_log(root, &mut item).is_some());
+    assert_eq!(log_status(root), "FAILED");
+
+    reset_for_retry(&mut item);
+    assert_eq!((item.status.as_str(), item.log_recorded_at, item.max_attempts), ("pending", None, 9));
+    item.status = "sent".to_string();
+    item.sent_at = Some(now_ms());
+    assert!(record_log(root, &mut item).is_some());
+    assert_eq!(log_status(root), "SENT");
+    assert!(item.log_recorded_at.is_some() && item.log_error.is_none());
+  }
+}
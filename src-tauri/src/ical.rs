@@ -0,0 +1,988 @@
+// RFC 5545 parsing for calendar feeds (`fetch_and_parse_ical`).
+//
+// Turns an Airbnb / Booking.com / Vrbo feed into normalised events ready for
+// `calendar_events`: unfolded and unescaped text, DTSTART/DTEND resolved to a
+// real instant (TZID, UTC or floating), all-day ranges kept as dates, and
+// recurring events expanded inside a window with EXDATE and RECURRENCE-ID
+// applied. Every occurrence gets a UID that is stable across fetches.
+//
+// Time zones come from the IANA database (chrono-tz). VTIMEZONE blocks are
+// only used to map a non-IANA TZID to its standard offset when no IANA name
+// can be recognised in it.
+
+use chrono::{Datelike, TimeZone};
+
+const DEFAULT_WINDOW_PAST_DAYS: i64 = 365;
+const DEFAULT_WINDOW_FUTURE_DAYS: i64 = 2 * 365;
+// Guards against runaway rules such as FREQ=DAILY without COUNT or UNTIL.
+const MAX_OCCURRENCES_PER_EVENT: usize = 2000;
+const MAX_RRULE_ITERATIONS: usize = 100_000;
+
+#[derive(serde::Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct IcalEvent {
+  /// Source UID for single events; `<uid>_<YYYYMMDD[THHMMSS]>` for occurrences
+  /// of a recurring event, so every row in `calendar_events` keeps its key.
+  uid: String,
+  source_uid: String,
+  recurrence_id: Option<String>,
+  summary: String,
+  description: String,
+  location: Option<String>,
+  status: Option<String>,
+  /// First night, YYYY-MM-DD in the event's time zone.
+  start_date: String,
+  /// Checkout day (exclusive end), YYYY-MM-DD.
+  end_date: String,
+  /// RFC 3339 instants in UTC; all-day events use midnight in their zone.
+  start: String,
+  end: String,
+  is_all_day: bool,
+  timezone: Option<String>,
+  has_recurrence: bool,
+  event_kind: String,
+  sequence: Option<i64>,
+  created: Option<i64>,
+  last_modified: Option<i64>,
+  raw: String,
+}
+
+#[derive(serde::Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ParsedCalendar {
+  calendar_name: Option<String>,
+  calendar_timezone: Option<String>,
+  events: Vec<IcalEvent>,
+  warnings: Vec<String>,
+}
+
+#[derive(Clone)]
+struct Property {
+  name: String,
+  params: Vec<(String, String)>,
+  value: String,
+}
+
+impl Property {
+  fn param(&self, name: &str) -> Option<&str> {
+    self.params.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+  }
+}
+
+#[derive(Default)]
+struct Component {
+  name: String,
+  props: Vec<Property>,
+  children: Vec<Component>,
+  raw: Vec<String>,
+}
+
+impl Component {
+  fn prop(&self, name: &str) -> Option<&Property> {
+    self.props.iter().find(|p| p.name == name)
+  }
+
+  fn text(&self, name: &str) -> Option<String> {
+    self.prop(name).map(|p| unescape_text(&p.value)).filter(|s| !s.is_empty())
+  }
+}
+
+/// RFC 5545 §3.1: CRLF followed by a space or tab continues the previous line.
+fn unfold(text: &str) -> Vec<String> {
+  let mut out: Vec<String> = vec![];
+  for line in text.split('\n') {
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    if let Some(rest) = line.strip_prefix([' ', '\t']) {
+      if let Some(last) = out.last_mut() {
+        last.push_str(rest);
+        continue;
+      }
+    }
+    if !line.trim().is_empty() {
+      out.push(line.to_string());
+    }
+  }
+  out
+}
+
+/// Splits `NAME;P1=a;P2="b:c":value`, honouring quoted parameter values.
+fn parse_line(line: &str) -> Option<Property> {
+  let mut in_quotes = false;
+  let mut colon = None;
+  for (i, c) in line.char_indices() {
+    match c {
+      '"' => in_quotes = !in_quotes,
+      ':' if !in_quotes => {
+        colon = Some(i);
+        break;
+      }
+      _ => {}
+    }
+  }
+  let colon = colon?;
+  let (head, value) = (&line[..colon], &line[colon + 1..]);
+
+  let mut parts = vec![];
+  let mut current = String::new();
+  in_quotes = false;
+  for c in head.chars() {
+    match c {
+      '"' => in_quotes = !in_quotes,
+      ';' if !in_quotes => parts.push(std::mem::take(&mut current)),
+      _ => current.push(c),
+    }
+  }
+  parts.push(current);
+
+  let name = parts.first()?.trim().to_ascii_uppercase();
+  if name.is_empty() {
+    return None;
+  }
+  let params = parts[1..]
+    .iter()
+    .filter_map(|p| p.split_once('=').map(|(k, v)| (k.trim().to_ascii_uppercase(), v.trim().to_string())))
+    .collect();
+  Some(Property { name, params, value: value.to_string() })
+}
+
+/// Decodes TEXT escapes: `\n`, `\N`, `\,`, `\;` and `\\`.
+fn unescape_text(value: &str) -> String {
+  let mut out = String::with_capacity(value.len());
+  let mut chars = value.chars();
+  while let Some(c) = chars.next() {
+    if c != '\\' {
+      out.push(c);
+      continue;
+    }
+    match chars.next() {
+      Some('n') | Some('N') => out.push('\n'),
+      Some(other) => out.push(other),
+      None => out.push('\\'),
+    }
+  }
+  out.trim().to_string()
+}
+
+fn parse_components(lines: &[String]) -> Component {
+  let mut stack: Vec<Component> = vec![Component { name: "ROOT".to_string(), ..Default::default() }];
+  for line in lines {
+    let prop = match parse_line(line) {
+      Some(p) => p,
+      None => continue,
+    };
+    for c in stack.iter_mut().skip(1) {
+      c.raw.push(line.clone());
+    }
+    match prop.name.as_str() {
+      "BEGIN" => stack.push(Component {
+        name: prop.value.trim().to_ascii_uppercase(),
+        raw: vec![line.clone()],
+        ..Default::default()
+      }),
+      "END" if stack.len() > 1 => {
+        let done = stack.pop().unwrap();
+        stack.last_mut().unwrap().children.push(done);
+      }
+      _ => stack.last_mut().unwrap().props.push(prop),
+    }
+  }
+  // Feeds cut off mid-component still yield what was read.
+  while stack.len() > 1 {
+    let done = stack.pop().unwrap();
+    stack.last_mut().unwrap().children.push(done);
+  }
+  stack.pop().unwrap()
+}
+
+/// Resolved time zone of a DATE-TIME value.
+#[derive(Clone, Copy)]
+enum Zone {
+  Utc,
+  Tz(chrono_tz::Tz),
+  Fixed(chrono::FixedOffset),
+  /// No zone given; interpreted in the calendar zone or UTC.
+  Floating,
+}
+
+struct Zones {
+  calendar: Option<chrono_tz::Tz>,
+  /// Non-IANA TZIDs defined by VTIMEZONE, mapped to their standard offset.
+  custom: std::collections::HashMap<String, chrono::FixedOffset>,
+}
+
+fn parse_utc_offset(s: &str) -> Option<chrono::FixedOffset> {
+  let s = s.trim();
+  let (sign, rest) = match s.as_bytes().first()? {
+    b'+' => (1, &s[1..]),
+    b'-' => (-1, &s[1..]),
+    _ => return None,
+  };
+  let h: i32 = rest.get(0..2)?.parse().ok()?;
+  let m: i32 = rest.get(2..4)?.parse().ok()?;
+  let sec: i32 = rest.get(4..6).and_then(|x| x.parse().ok()).unwrap_or(0);
+  chrono::FixedOffset::east_opt(sign * (h * 3600 + m * 60 + sec))
+}
+
+/// Finds an IANA name in TZIDs such as `/citadel.org/20190101_1/Europe/Madrid`
+/// or `"Europe/Madrid"`.
+fn iana_zone(tzid: &str) -> Option<chrono_tz::Tz> {
+  let tzid = tzid.trim().trim_matches('"');
+  if let Ok(tz) = tzid.parse::<chrono_tz::Tz>() {
+    return Some(tz);
+  }
+  let segments: Vec<&str> = tzid.split('/').filter(|s| !s.is_empty()).collect();
+  (0..segments.len()).find_map(|i| segments[i..].join("/").parse::<chrono_tz::Tz>().ok())
+}
+
+impl Zones {
+  fn from_calendar(root: &Component, cal: &Component) -> Zones {
+    let mut custom = std::collections::HashMap::new();
+    for vtz in root.children.iter().chain(cal.children.iter()).filter(|c| c.name == "VTIMEZONE") {
+      let tzid = match vtz.prop("TZID") {
+        Some(p) => p.value.trim().to_string(),
+        None => continue,
+      };
+      if iana_zone(&tzid).is_some() {
+        continue;
+      }
+      let standard = vtz.children.iter().find(|c| c.name == "STANDARD").or_else(|| vtz.children.first());
+      if let Some(offset) = standard.and_then(|s| s.prop("TZOFFSETTO")).and_then(|p| parse_utc_offset(&p.value)) {
+        custom.insert(tzid, offset);
+      }
+    }
+    let calendar = cal.prop("X-WR-TIMEZONE").and_then(|p| iana_zone(&p.value));
+    Zones { calendar, custom }
+  }
+
+  fn resolve(&self, tzid: Option<&str>) -> Zone {
+    match tzid {
+      Some(id) => iana_zone(id)
+        .map(Zone::Tz)
+        .or_else(|| self.custom.get(id.trim().trim_matches('"')).copied().map(Zone::Fixed))
+        .unwrap_or(Zone::Floating),
+      None => Zone::Floating,
+    }
+  }
+
+  fn to_utc(&self, zone: Zone, local: chrono::NaiveDateTime) -> chrono::DateTime<chrono::Utc> {
+    // In a DST gap the wall time does not exist; RFC 5545 says to move it
+    // forward by the gap, which one hour covers for every real zone.
+    fn pick<T: TimeZone>(tz: &T, local: chrono::NaiveDateTime) -> chrono::DateTime<chrono::Utc> {
+      tz.from_local_datetime(&local)
+        .earliest()
+        .or_else(|| tz.from_local_datetime(&(local + chrono::Duration::hours(1))).earliest())
+        .map(|d| d.with_timezone(&chrono::Utc))
+        .unwrap_or_else(|| chrono::Utc.from_utc_datetime(&local))
+    }
+    match zone {
+      Zone::Utc => chrono::Utc.from_utc_datetime(&local),
+      Zone::Tz(tz) => pick(&tz, local),
+      Zone::Fixed(off) => pick(&off, local),
+      Zone::Floating => match self.calendar {
+        Some(tz) => pick(&tz, local),
+        None => chrono::Utc.from_utc_datetime(&local),
+      },
+    }
+  }
+
+  fn to_local(&self, zone: Zone, utc: chrono::DateTime<chrono::Utc>) -> chrono::NaiveDateTime {
+    match zone {
+      Zone::Utc => utc.naive_utc(),
+      Zone::Tz(tz) => utc.with_timezone(&tz).naive_local(),
+      Zone::Fixed(off) => utc.with_timezone(&off).naive_local(),
+      Zone::Floating => match self.calendar {
+        Some(tz) => utc.with_timezone(&tz).naive_local(),
+        None => utc.naive_utc(),
+      },
+    }
+  }
+
+  /// `value` as wall time in `target`, so EXDATE, RDATE and RECURRENCE-ID given
+  /// in another TZID (or in UTC) still line up with the DTSTART they refer to.
+  fn in_zone(&self, value: &DateValue, target: Zone) -> chrono::NaiveDateTime {
+    if value.all_day {
+      return value.local;
+    }
+    self.to_local(target, self.to_utc(value.zone, value.local))
+  }
+
+  fn zone_name(&self, zone: Zone, tzid: Option<&str>) -> Option<String> {
+    match zone {
+      Zone::Utc => Some("UTC".to_string()),
+      Zone::Tz(tz) => Some(tz.name().to_string()),
+      Zone::Fixed(_) => tzid.map(String::from),
+      Zone::Floating => self.calendar.map(|tz| tz.name().to_string()),
+    }
+  }
+}
+
+/// A DTSTART/DTEND/EXDATE/RECURRENCE-ID value.
+#[derive(Clone, Copy)]
+struct DateValue {
+  local: chrono::NaiveDateTime,
+  all_day: bool,
+  zone: Zone,
+}
+
+fn parse_date_value(raw: &str, value_param: Option<&str>, zone: Zone) -> Option<DateValue> {
+  let raw = raw.trim();
+  let digits: String = raw.chars().filter(|c| c.is_ascii_digit()).collect();
+  if digits.len() < 8 {
+    return None;
+  }
+  let date = chrono::NaiveDate::parse_from_str(&digits[0..8], "%Y%m%d").ok()?;
+  let is_date = value_param.map(|v| v.eq_ignore_ascii_case("DATE")).unwrap_or(false) || !raw.contains('T');
+  if is_date {
+    return Some(DateValue { local: date.and_hms_opt(0, 0, 0)?, all_day: true, zone });
+  }
+  let time = chrono::NaiveTime::parse_from_str(digits.get(8..14)?, "%H%M%S").ok()?;
+  let zone = if raw.ends_with('Z') || raw.ends_with('z') { Zone::Utc } else { zone };
+  Some(DateValue { local: date.and_time(time), all_day: false, zone })
+}
+
+fn prop_date(zones: &Zones, prop: &Property) -> Option<DateValue> {
+  parse_date_value(&prop.value, prop.param("VALUE"), zones.resolve(prop.param("TZID")))
+}
+
+/// Multi-valued EXDATE/RDATE lines.
+fn prop_dates(zones: &Zones, prop: &Property) -> Vec<DateValue> {
+  let zone = zones.resolve(prop.param("TZID"));
+  prop
+    .value
+    .split(',')
+    .filter_map(|v| parse_date_value(v, prop.param("VALUE"), zone))
+    .collect()
+}
+
+/// `P1D`, `PT12H`, `-P1W`, `P1DT2H30M` into a signed duration.
+fn parse_duration(s: &str) -> Option<chrono::Duration> {
+  let s = s.trim();
+  let (neg, s) = match s.strip_prefix('-') {
+    Some(rest) => (true, rest),
+    None => (false, s.strip_prefix('+').unwrap_or(s)),
+  };
+  let s = s.strip_prefix('P')?;
+  let mut total = chrono::Duration::zero();
+  let mut num = String::new();
+  let mut in_time = false;
+  for c in s.chars() {
+    match c {
+      '0'..='9' => num.push(c),
+      'T' => in_time = true,
+      unit => {
+        let n: i64 = num.parse().ok()?;
+        num.clear();
+        total += match (unit, in_time) {
+          ('W', _) => chrono::Duration::weeks(n),
+          ('D', _) => chrono::Duration::days(n),
+          ('H', true) => chrono::Duration::hours(n),
+          ('M', true) => chrono::Duration::minutes(n),
+          ('S', true) => chrono::Duration::seconds(n),
+          _ => return None,
+        };
+      }
+    }
+  }
+  Some(if neg { -total } else { total })
+}
+
+fn parse_stamp(value: &str) -> Option<i64> {
+  let v = parse_date_value(value, None, Zone::Utc)?;
+  Some(chrono::Utc.from_utc_datetime(&v.local).timestamp_millis())
+}
+
+#[derive(Default)]
+struct RRule {
+  freq: String,
+  interval: i64,
+  count: Option<usize>,
+  until: Option<DateValue>,
+  by_day: Vec<(Option<i64>, chrono::Weekday)>,
+  by_month_day: Vec<i64>,
+  by_month: Vec<u32>,
+}
+
+fn parse_weekday(s: &str) -> Option<chrono::Weekday> {
+  match s {
+    "MO" => Some(chrono::Weekday::Mon),
+    "TU" => Some(chrono::Weekday::Tue),
+    "WE" => Some(chrono::Weekday::Wed),
+    "TH" => Some(chrono::Weekday::Thu),
+    "FR" => Some(chrono::Weekday::Fri),
+    "SA" => Some(chrono::Weekday::Sat),
+    "SU" => Some(chrono::Weekday::Sun),
+    _ => None,
+  }
+}
+
+fn parse_rrule(value: &str, zone: Zone, warnings: &mut Vec<String>) -> Option<RRule> {
+  let mut rule = RRule { interval: 1, ..Default::default() };
+  for part in value.split(';') {
+    let (k, v) = match part.split_once('=') {
+      Some(kv) => kv,
+      None => continue,
+    };
+    let v = v.trim();
+    match k.trim().to_ascii_uppercase().as_str() {
+      "FREQ" => rule.freq = v.to_ascii_uppercase(),
+      "INTERVAL" => rule.interval = v.parse().unwrap_or(1).max(1),
+      "COUNT" => rule.count = v.parse().ok(),
+      "UNTIL" => rule.until = parse_date_value(v, None, zone),
+      "BYDAY" => {
+        for d in v.split(',') {
+          let d = d.trim().to_ascii_uppercase();
+          let split = d.len().saturating_sub(2);
+          if let (Some(ord), Some(wd)) = (d.get(..split), d.get(split..).and_then(parse_weekday)) {
+            rule.by_day.push((ord.parse().ok(), wd));
+          }
+        }
+      }
+      "BYMONTHDAY" => rule.by_month_day = v.split(',').filter_map(|x| x.trim().parse().ok()).collect(),
+      "BYMONTH" => rule.by_month = v.split(',').filter_map(|x| x.trim().parse().ok()).collect(),
+      "WKST" => {}
+      other => warnings.push(format!("RRULE part {other} is not supported and was ignored")),
+    }
+  }
+  if !["DAILY", "WEEKLY", "MONTHLY", "YEARLY"].contains(&rule.freq.as_str()) {
+    warnings.push(format!("RRULE frequency '{}' is not supported", rule.freq));
+    return None;
+  }
+  Some(rule)
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+  let next = if month == 12 { chrono::NaiveDate::from_ymd_opt(year + 1, 1, 1) } else { chrono::NaiveDate::from_ymd_opt(year, month + 1, 1) };
+  next.and_then(|n| n.pred_opt()).map(|d| d.day()).unwrap_or(28)
+}
+
+/// Days of one month matched by BYMONTHDAY / BYDAY, or `default_day`.
+fn month_days(year: i32, month: u32, rule: &RRule, default_day: u32) -> Vec<chrono::NaiveDate> {
+  let len = days_in_month(year, month) as i64;
+  let mut days: Vec<chrono::NaiveDate> = vec![];
+  if !rule.by_month_day.is_empty() {
+    for &d in &rule.by_month_day {
+      let day = if d < 0 { len + 1 + d } else { d };
+      if (1..=len).contains(&day) {
+        days.extend(chrono::NaiveDate::from_ymd_opt(year, month, day as u32));
+      }
+    }
+  } else if !rule.by_day.is_empty() {
+    let all: Vec<chrono::NaiveDate> = (1..=len as u32).filter_map(|d| chrono::NaiveDate::from_ymd_opt(year, month, d)).collect();
+    by_day_in(&all, rule, &mut days);
+  } else {
+    days.extend(chrono::NaiveDate::from_ymd_opt(year, month, default_day));
+  }
+  days.sort();
+  days.dedup();
+  days
+}
+
+/// Applies BYDAY to `period` (a month, or a whole year for YEARLY rules
+/// without BYMONTH): `MO` is every Monday, `2MO` the second, `-1MO` the last.
+fn by_day_in(period: &[chrono::NaiveDate], rule: &RRule, days: &mut Vec<chrono::NaiveDate>) {
+  for &(ord, wd) in &rule.by_day {
+    let matching: Vec<chrono::NaiveDate> = period.iter().copied().filter(|d| d.weekday() == wd).collect();
+    match ord {
+      Some(n) if n > 0 => days.extend(matching.get(n as usize - 1).copied()),
+      Some(n) if n < 0 => days.extend(matching.len().checked_sub(n.unsigned_abs() as usize).and_then(|i| matching.get(i)).copied()),
+      _ => days.extend(matching),
+    }
+  }
+}
+
+/// Days of one year for a YEARLY rule.
+fn year_days(year: i32, rule: &RRule, start_date: chrono::NaiveDate) -> Vec<chrono::NaiveDate> {
+  let mut days: Vec<chrono::NaiveDate> = if !rule.by_month.is_empty() {
+    rule.by_month.iter().flat_map(|&m| month_days(year, m, rule, start_date.day())).collect()
+  } else if !rule.by_month_day.is_empty() {
+    (1..=12).flat_map(|m| month_days(year, m, rule, start_date.day())).collect()
+  } else if !rule.by_day.is_empty() {
+    let all: Vec<chrono::NaiveDate> = match (chrono::NaiveDate::from_ymd_opt(year, 1, 1), chrono::NaiveDate::from_ymd_opt(year, 12, 31)) {
+      (Some(first), Some(last)) => first.iter_days().take_while(|d| *d <= last).collect(),
+      _ => vec![],
+    };
+    let mut days = vec![];
+    by_day_in(&all, rule, &mut days);
+    days
+  } else {
+    month_days(year, start_date.month(), rule, start_date.day())
+  };
+  days.sort();
+  days.dedup();
+  days
+}
+
+/// First period worth generating for rules without COUNT: everything before
+/// it ends before `window_start`, so long-running rules do not spend the
+/// iteration budget on years outside the window.
+fn first_period(rule: &RRule, start_date: chrono::NaiveDate, window_start: chrono::NaiveDate) -> i64 {
+  if rule.count.is_some() || window_start <= start_date {
+    return 0;
+  }
+  let units = match rule.freq.as_str() {
+    "DAILY" => (window_start - start_date).num_days(),
+    "WEEKLY" => (window_start - start_date).num_days() / 7,
+    "MONTHLY" => (window_start.year() as i64 - start_date.year() as i64) * 12 + window_start.month() as i64 - start_date.month() as i64,
+    _ => window_start.year() as i64 - start_date.year() as i64,
+  };
+  (units / rule.interval - 1).max(0)
+}
+
+/// Candidate days of the `period`-th period after DTSTART; `None` once the
+/// period lies beyond the dates chrono can represent.
+fn period_days(rule: &RRule, start_date: chrono::NaiveDate, period: i64, month_ok: &dyn Fn(&chrono::NaiveDate) -> bool) -> Option<Vec<chrono::NaiveDate>> {
+  let step = u64::try_from(period.checked_mul(rule.interval)?).ok()?;
+  Some(match rule.freq.as_str() {
+    "DAILY" => {
+      let d = start_date.checked_add_days(chrono::Days::new(step))?;
+      let day_ok = rule.by_day.is_empty() || rule.by_day.iter().any(|(_, wd)| *wd == d.weekday());
+      if day_ok && month_ok(&d) { vec![d] } else { vec![] }
+    }
+    "WEEKLY" => {
+      let monday = start_date.checked_sub_days(chrono::Days::new(start_date.weekday().num_days_from_monday() as u64))?;
+      let week_start = monday.checked_add_days(chrono::Days::new(step.checked_mul(7)?))?;
+      let mut days: Vec<chrono::NaiveDate> = if rule.by_day.is_empty() {
+        vec![week_start.checked_add_days(chrono::Days::new(start_date.weekday().num_days_from_monday() as u64))?]
+      } else {
+        rule.by_day.iter().filter_map(|(_, wd)| week_start.checked_add_days(chrono::Days::new(wd.num_days_from_monday() as u64))).collect()
+      };
+      days.sort();
+      days.dedup();
+      days.into_iter().filter(|d| month_ok(d)).collect()
+    }
+    "MONTHLY" => {
+      let months = (start_date.year() as i64 * 12 + start_date.month0() as i64).checked_add(i64::try_from(step).ok()?)?;
+      let (y, m) = (i32::try_from(months / 12).ok()?, (months % 12) as u32 + 1);
+      chrono::NaiveDate::from_ymd_opt(y, m, 1)?;
+      if rule.by_month.is_empty() || rule.by_month.contains(&m) { month_days(y, m, rule, start_date.day()) } else { vec![] }
+    }
+    _ => {
+      let year = start_date.year().checked_add(i32::try_from(step).ok()?)?;
+      chrono::NaiveDate::from_ymd_opt(year, 1, 1)?;
+      year_days(year, rule, start_date)
+    }
+  })
+}
+
+/// Local start times produced by `rule` (DTSTART included) that overlap the
+/// window, up to UNTIL or COUNT. COUNT is counted from DTSTART as RFC 5545
+/// requires; `MAX_OCCURRENCES_PER_EVENT` only counts occurrences in the window.
+fn expand_rrule(
+  dtstart: &DateValue,
+  length: chrono::Duration,
+  rule: &RRule,
+  zones: &Zones,
+  window_start: chrono::DateTime<chrono::Utc>,
+  window_end: chrono::DateTime<chrono::Utc>,
+) -> Vec<chrono::NaiveDateTime> {
+  let start = dtstart.local;
+  let time = start.time();
+  let start_date = start.date();
+  let until_utc = rule.until.map(|u| if u.all_day { zones.to_utc(u.zone, u.local + chrono::Duration::days(1)) - chrono::Duration::seconds(1) } else { zones.to_utc(u.zone, u.local) });
+  let month_ok = |d: &chrono::NaiveDate| rule.by_month.is_empty() || rule.by_month.contains(&d.month());
+  // A day of slack either side of the window absorbs any zone offset.
+  let skip_to = (window_start - length - chrono::Duration::days(1)).date_naive();
+  let first = first_period(rule, start_date, skip_to);
+
+  let mut out = vec![];
+  let mut emitted = 0usize;
+  for period in first..first + MAX_RRULE_ITERATIONS as i64 {
+    // A huge INTERVAL from a remote feed must end the rule, not overflow.
+    let candidates = match period_days(rule, start_date, period, &month_ok) {
+      Some(days) => days,
+      None => return out,
+    };
+    for d in candidates {
+      if d < start_date {
+        continue;
+      }
+      let local = d.and_time(time);
+      let utc = zones.to_utc(dtstart.zone, local);
+      if until_utc.map(|u| utc > u).unwrap_or(false) || utc > window_end {
+        return out;
+      }
+      emitted += 1;
+      if utc + length >= window_start {
+        out.push(local);
+      }
+      if rule.count.map(|c| emitted >= c).unwrap_or(false) || out.len() >= MAX_OCCURRENCES_PER_EVENT {
+        return out;
+      }
+    }
+  }
+  out
+}
+
+/// Stable key for one occurrence, also used to match RECURRENCE-ID.
+fn occurrence_key(local: chrono::NaiveDateTime, all_day: bool) -> String {
+  if all_day {
+    local.format("%Y%m%d").to_string()
+  } else {
+    local.format("%Y%m%dT%H%M%S").to_string()
+  }
+}
+
+fn is_block_summary(summary: &str) -> bool {
+  let s = summary.to_lowercase();
+  s.is_empty()
+    || ["not available", "unavailable", "blocked", "closed", "no disponible", "bloquead"]
+      .iter()
+      .any(|k| s.contains(k))
+}
+
+struct Occurrence {
+  start: DateValue,
+  end: DateValue,
+  recurrence_id: Option<String>,
+}
+
+fn build_event(zones: &Zones, ev: &Component, source_uid: &str, occ: &Occurrence, has_recurrence: bool) -> IcalEvent {
+  let start_utc = zones.to_utc(occ.start.zone, occ.start.local);
+  let end_utc = zones.to_utc(occ.end.zone, occ.end.local);
+  // Date ranges are read in the event's own zone, so a 15:00 check-in in
+  // Madrid stays on the same day whatever the machine's zone is.
+  let start_date = occ.start.local.date();
+  let mut end_date = occ.end.local.date();
+  if !occ.end.all_day && end_date <= start_date {
+    end_date = start_date + chrono::Duration::days(1);
+  }
+
+  let tzid = ev.prop("DTSTART").and_then(|p| p.param("TZID"));
+  let summary_raw = ev.text("SUMMARY").unwrap_or_default();
+  let is_block = is_block_summary(&summary_raw);
+  let summary = if summary_raw.is_empty() {
+    if is_block { "Bloqueo OTA".to_string() } else { "Reserva Externa".to_string() }
+  } else {
+    summary_raw
+  };
+  let status = ev.prop("STATUS").map(|p| p.value.trim().to_ascii_uppercase()).filter(|s| !s.is_empty());
+
+  IcalEvent {
+    uid: match &occ.recurrence_id {
+      Some(rid) => format!("{source_uid}_{rid}"),
+      None => source_uid.to_string(),
+    },
+    source_uid: source_uid.to_string(),
+    recurrence_id: occ.recurrence_id.clone(),
+    summary,
+    description: ev.text("DESCRIPTION").unwrap_or_default(),
+    location: ev.text("LOCATION"),
+    status,
+    start_date: start_date.format("%Y-%m-%d").to_string(),
+    end_date: end_date.format("%Y-%m-%d").to_string(),
+    start: start_utc.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+    end: end_utc.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+    is_all_day: occ.start.all_day,
+    timezone: zones.zone_name(occ.start.zone, tzid),
+    has_recurrence,
+    event_kind: if is_block { "BLOCK".to_string() } else { "BOOKING".to_string() },
+    sequence: ev.prop("SEQUENCE").and_then(|p| p.value.trim().parse().ok()),
+    created: ev.prop("CREATED").and_then(|p| parse_stamp(&p.value)),
+    last_modified: ev.prop("LAST-MODIFIED").and_then(|p| parse_stamp(&p.value)),
+    raw: ev.raw.join("\n"),
+  }
+}
+
+/// DTEND, or DTSTART + DURATION, or the RFC default (one day / zero length).
+fn event_end(zones: &Zones, ev: &Component, start: &DateValue) -> DateValue {
+  if let Some(end) = ev.prop("DTEND").and_then(|p| prop_date(zones, p)) {
+    if end.local >= start.local {
+      return end;
+    }
+  }
+  let duration = ev.prop("DURATION").and_then(|p| parse_duration(&p.value));
+  let local = match (duration, start.all_day) {
+    (Some(d), true) => start.local + chrono::Duration::days(((d.num_seconds() + 86_399) / 86_400).max(1)),
+    (Some(d), false) => start.local + d,
+    // Feeds omitting both are nightly blocks in practice.
+    (None, _) => start.local + chrono::Duration::days(1),
+  };
+  DateValue { local, ..*start }
+}
+
+pub fn parse_calendar(text: &str, window_start: chrono::DateTime<chrono::Utc>, window_end: chrono::DateTime<chrono::Utc>) -> ParsedCalendar {
+  let root = parse_components(&unfold(text));
+  let empty = Component::default();
+  let cal = root.children.iter().find(|c| c.name == "VCALENDAR").unwrap_or(&empty);
+  let zones = Zones::from_calendar(&root, cal);
+  let mut out = ParsedCalendar {
+    calendar_name: cal.text("X-WR-CALNAME"),
+    calendar_timezone: zones.calendar.map(|tz| tz.name().to_string()),
+    ..Default::default()
+  };
+  // Some exports put VEVENTs at the top level without a VCALENDAR wrapper.
+  let vevents: Vec<&Component> = cal.children.iter().chain(root.children.iter()).filter(|c| c.name == "VEVENT").collect();
+
+  // RECURRENCE-ID overrides by UID; matched against occurrences in the
+  // master's DTSTART zone below.
+  let mut overrides: std::collections::HashMap<String, Vec<(DateValue, &Component)>> = std::collections::HashMap::new();
+  for ev in &vevents {
+    if let (Some(uid), Some(rid)) = (ev.prop("UID"), ev.prop("RECURRENCE-ID").and_then(|p| prop_date(&zones, p))) {
+      overrides.entry(uid.value.trim().to_string()).or_default().push((rid, ev));
+    }
+  }
+
+  for ev in &vevents {
+    if ev.prop("RECURRENCE-ID").is_some() {
+      continue; // emitted with their master below
+    }
+    let start = match ev.prop("DTSTART").and_then(|p| prop_date(&zones, p)) {
+      Some(s) => s,
+      None => {
+        out.warnings.push(format!("Event without a valid DTSTART skipped: {}", ev.text("UID").unwrap_or_default()));
+        continue;
+      }
+    };
+    let end = event_end(&zones, ev, &start);
+    let source_uid = match ev.prop("UID").map(|p| p.value.trim().to_string()).filter(|u| !u.is_empty()) {
+      Some(uid) => uid,
+      None => {
+        let basis = format!("{}|{}|{}", occurrence_key(start.local, start.all_day), occurrence_key(end.local, end.all_day), ev.text("SUMMARY").unwrap_or_default());
+        format!("rpc-{}", &super::sha256_hex(basis.as_bytes())[..16])
+      }
+    };
+
+    let rule = match ev.prop("RRULE").and_then(|p| parse_rrule(&p.value, start.zone, &mut out.warnings)) {
+      Some(rule) => rule,
+      None => {
+        out.events.push(build_event(&zones, ev, &source_uid, &Occurrence { start, end, recurrence_id: None }, ev.prop("RRULE").is_some()));
+        continue;
+      }
+    };
+
+    let length = end.local - start.local;
+    let mut starts = expand_rrule(&start, length, &rule, &zones, window_start, window_end);
+    for rdate in ev.props.iter().filter(|p| p.name == "RDATE").flat_map(|p| prop_dates(&zones, p)) {
+      starts.push(zones.in_zone(&rdate, start.zone));
+    }
+    let excluded: std::collections::HashSet<String> = ev
+      .props
+      .iter()
+      .filter(|p| p.name == "EXDATE")
+      .flat_map(|p| prop_dates(&zones, p))
+      .map(|d| occurrence_key(zones.in_zone(&d, start.zone), start.all_day))
+      .collect();
+    let own_overrides: std::collections::HashMap<String, &Component> = overrides
+      .get(&source_uid)
+      .map(|list| list.iter().map(|(rid, ov)| (occurrence_key(zones.in_zone(rid, start.zone), start.all_day), *ov)).collect())
+      .unwrap_or_default();
+    starts.sort();
+    starts.dedup();
+
+    for local in starts {
+      let key = occurrence_key(local, start.all_day);
+      if excluded.contains(&key) {
+        continue;
+      }
+      let occ_start = DateValue { local, ..start };
+      let occ = match own_overrides.get(&key) {
+        Some(ov) => {
+          let s = ov.prop("DTSTART").and_then(|p| prop_date(&zones, p)).unwrap_or(occ_start);
+          let e = event_end(&zones, ov, &s);
+          (ov, Occurrence { start: s, end: e, recurrence_id: Some(key) })
+        }
+        None => (ev, Occurrence { start: occ_start, end: DateValue { local: local + length, ..end }, recurrence_id: Some(key) }),
+      };
+      let end_utc = zones.to_utc(occ.1.end.zone, occ.1.end.local);
+      if end_utc < window_start {
+        continue;
+      }
+      out.events.push(build_event(&zones, occ.0, &source_uid, &occ.1, true));
+    }
+  }
+
+  // Feeds that reuse one UID for several blocks would overwrite each other in
+  // `calendar_events`; suffix those with their date range, as iCalParser.ts does.
+  let mut counts: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
+  for e in &out.events {
+    *counts.entry(e.uid.clone()).or_default() += 1;
+  }
+  let mut seen: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
+  for e in out.events.iter_mut().filter(|e| counts[&e.uid] > 1) {
+    let mut composite = format!("{}_{}_{}", e.uid, e.start_date, e.end_date);
+    let n = seen.entry(composite.clone()).or_default();
+    if *n > 0 {
+      composite = format!("{composite}_{n}");
+    }
+    *n += 1;
+    e.uid = composite;
+  }
+  out
+}
+
+fn window_bound(value: Option<&str>, default_days: i64) -> Result<chrono::DateTime<chrono::Utc>, String> {
+  match value.filter(|v| !v.trim().is_empty()) {
+    Some(v) => chrono::NaiveDate::parse_from_str(v.trim(), "%Y-%m-%d")
+      .map(|d| chrono::Utc.from_utc_datetime(&d.and_hms_opt(0, 0, 0).unwrap()))
+      .map_err(|e| format!("Invalid window date {v}: {e}")),
+    None => Ok(chrono::Utc::now() + chrono::Duration::days(default_days)),
+  }
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IcalFetchParseResult {
+  status: u16,
+  not_modified: bool,
+  etag: Option<String>,
+  last_modified: Option<String>,
+  content_type: Option<String>,
+  final_url: String,
+  redirects: Vec<super::ical_feeds::RedirectHop>,
+  #[serde(flatten)]
+  calendar: ParsedCalendar,
+}
+
+/// Fetches a feed like `fetch_ical_url` and returns normalised events.
+///
+/// Recurring events are expanded between `window_start` and `window_end`
+/// (YYYY-MM-DD; defaults one year back and two years ahead).
+#[tauri::command]
+pub async fn fetch_and_parse_ical(
+  url: String,
+  etag: Option<String>,
+  last_modified: Option<String>,
+  window_start: Option<String>,
+  window_end: Option<String>,
+  options: Option<super::ical_feeds::FetchOptions>,
+) -> Result<IcalFetchParseResult, String> {
+  let from = window_bound(window_start.as_deref(), -DEFAULT_WINDOW_PAST_DAYS)?;
+  let to = window_bound(window_end.as_deref(), DEFAULT_WINDOW_FUTURE_DAYS)?;
+  let fetched = super::ical_feeds::fetch(&url, etag, last_modified, &options.unwrap_or_default()).await?;
+  let not_modified = fetched.status == 304;
+  if !not_modified && !(200..300).contains(&fetched.status) {
+    return Err(format!("Calendar fetch failed: HTTP {}", fetched.status));
+  }
+  let calendar = if not_modified { ParsedCalendar::default() } else { parse_calendar(&fetched.body, from, to) };
+  Ok(IcalFetchParseResult {
+    status: fetched.status,
+    not_modified,
+    etag: fetched.etag,
+    last_modified: fetched.last_modified,
+    content_type: fetched.content_type,
+    final_url: fetched.final_url,
+    redirects: fetched.redirects,
+    calendar,
+  })
+}
+
+/// Same normalisation for feed text that is already at hand (imports, tests
+/// from the UI with a pasted calendar).
+#[tauri::command]
+pub fn parse_ical_text(text: String, window_start: Option<String>, window_end: Option<String>) -> Result<ParsedCalendar, String> {
+  let from = window_bound(window_start.as_deref(), -DEFAULT_WINDOW_PAST_DAYS)?;
+  let to = window_bound(window_end.as_deref(), DEFAULT_WINDOW_FUTURE_DAYS)?;
+  Ok(parse_calendar(&text, from, to))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn utc(date: &str) -> chrono::DateTime<chrono::Utc> {
+    window_bound(Some(date), 0).unwrap()
+  }
+
+  fn parse(body: &str, from: &str, to: &str) -> ParsedCalendar {
+    let text = format!("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n{body}END:VCALENDAR\r\n");
+    parse_calendar(&text, utc(from), utc(to))
+  }
+
+  fn starts(cal: &ParsedCalendar) -> Vec<&str> {
+    cal.events.iter().map(|e| e.start.as_str()).collect()
+  }
+
+  #[test]
+  fn unfolds_lines_and_decodes_escapes() {
+    let cal = parse(
+      "BEGIN:VEVENT\r\nUID:a\r\nDTSTART;VALUE=DATE:20250301\r\nDTEND;VALUE=DATE:20250304\r\nSUMMARY:Reserva de\r\n  Ana\\, López\r\nDESCRIPTION:line one\\nline two\\; end\\\\\r\nEND:VEVENT\r\n",
+      "2025-01-01",
+      "2026-01-01",
+    );
+    let ev = &cal.events[0];
+    assert_eq!(ev.summary, "Reserva de Ana, López");
+    assert_eq!(ev.description, "line one\nline two; end\\");
+  }
+
+  #[test]
+  fn all_day_events_keep_their_dates() {
+    let cal = parse("BEGIN:VEVENT\r\nUID:a\r\nDTSTART;VALUE=DATE:20250301\r\nDTEND;VALUE=DATE:20250304\r\nSUMMARY:Not available\r\nEND:VEVENT\r\n", "2025-01-01", "2026-01-01");
+    let ev = &cal.events[0];
+    assert!(ev.is_all_day);
+    assert_eq!((ev.start_date.as_str(), ev.end_date.as_str()), ("2025-03-01", "2025-03-04"));
+    assert_eq!(ev.event_kind, "BLOCK");
+  }
+
+  #[test]
+  fn tzid_is_resolved_to_utc() {
+    let cal = parse(
+      "BEGIN:VEVENT\r\nUID:a\r\nDTSTART;TZID=/citadel.org/20190101_1/Europe/Madrid:20250701T150000\r\nDTEND;TZID=Europe/Madrid:20250703T110000\r\nEND:VEVENT\r\n",
+      "2025-01-01",
+      "2026-01-01",
+    );
+    let ev = &cal.events[0];
+    assert_eq!(ev.start, "2025-07-01T13:00:00Z");
+    assert_eq!(ev.end, "2025-07-03T09:00:00Z");
+    assert_eq!(ev.timezone.as_deref(), Some("Europe/Madrid"));
+    assert_eq!(ev.start_date, "2025-07-01");
+  }
+
+  #[test]
+  fn malformed_offsets_and_byday_do_not_panic() {
+    assert!(parse_utc_offset("+0é00").is_none());
+    assert!(parse_utc_offset("+01").is_none());
+    assert_eq!(parse_utc_offset("-0130").map(|o| o.local_minus_utc()), Some(-5400));
+    let mut warnings = vec![];
+    let rule = parse_rrule("FREQ=WEEKLY;BYDAY=é,2MO,Ü", Zone::Utc, &mut warnings).unwrap();
+    assert_eq!(rule.by_day, vec![(Some(2), chrono::Weekday::Mon)]);
+  }
+
+  #[test]
+  fn rrule_with_exdate_in_another_zone() {
+    let cal = parse(
+      "BEGIN:VEVENT\r\nUID:r\r\nDTSTART;TZID=Europe/Madrid:20250106T100000\r\nDTEND;TZID=Europe/Madrid:20250106T110000\r\nRRULE:FREQ=WEEKLY;COUNT=3\r\nEXDATE:20250113T090000Z\r\nEND:VEVENT\r\n",
+      "2025-01-01",
+      "2026-01-01",
+    );
+    assert_eq!(starts(&cal), vec!["2025-01-06T09:00:00Z", "2025-01-20T09:00:00Z"]);
+    assert_eq!(cal.events[1].uid, "r_20250120T100000");
+  }
+
+  #[test]
+  fn recurrence_id_replaces_the_occurrence() {
+    let cal = parse(
+      "BEGIN:VEVENT\r\nUID:r\r\nDTSTART;VALUE=DATE:20250301\r\nDTEND;VALUE=DATE:20250302\r\nRRULE:FREQ=DAILY;COUNT=3\r\nSUMMARY:Base\r\nEND:VEVENT\r\nBEGIN:VEVENT\r\nUID:r\r\nRECURRENCE-ID;VALUE=DATE:20250302\r\nDTSTART;VALUE=DATE:20250310\r\nDTEND;VALUE=DATE:20250312\r\nSUMMARY:Moved\r\nEND:VEVENT\r\n",
+      "2025-01-01",
+      "2026-01-01",
+    );
+    assert_eq!(cal.events.len(), 3);
+    let moved = cal.events.iter().find(|e| e.uid == "r_20250302").unwrap();
+    assert_eq!((moved.summary.as_str(), moved.start_date.as_str(), moved.end_date.as_str()), ("Moved", "2025-03-10", "2025-03-12"));
+  }
+
+  #[test]
+  fn occurrence_cap_counts_only_the_window() {
+    // Started decades ago: occurrences before the window must not use up the cap.
+    let cal = parse("BEGIN:VEVENT\r\nUID:d\r\nDTSTART;VALUE=DATE:19900101\r\nRRULE:FREQ=DAILY\r\nEND:VEVENT\r\n", "2025-01-01", "2025-01-11");
+    assert_eq!(cal.events.first().map(|e| e.start_date.as_str()), Some("2024-12-31"));
+    assert_eq!(cal.events.last().map(|e| e.start_date.as_str()), Some("2025-01-11"));
+  }
+
+  #[test]
+  fn huge_interval_ends_the_rule() {
+    for freq in ["DAILY", "WEEKLY", "MONTHLY", "YEARLY"] {
+      for interval in ["100000000", "9223372036854775807"] {
+        let body = format!("BEGIN:VEVENT\r\nUID:h\r\nDTSTART;VALUE=DATE:20250301\r\nRRULE:FREQ={freq};INTERVAL={interval}\r\nEND:VEVENT\r\n");
+        let cal = parse(&body, "2025-01-01", "2026-01-01");
+        assert_eq!(starts(&cal), vec!["2025-03-01T00:00:00Z"], "{freq} {interval}");
+      }
+    }
+  }
+
+  #[test]
+  fn yearly_byday_without_bymonth_scans_the_year() {
+    let cal = parse("BEGIN:VEVENT\r\nUID:y\r\nDTSTART;VALUE=DATE:20250106\r\nRRULE:FREQ=YEARLY;BYDAY=-1MO;COUNT=2\r\nEND:VEVENT\r\n", "2025-01-01", "2027-01-01");
+    let dates: Vec<&str> = cal.events.iter().map(|e| e.start_date.as_str()).collect();
+    assert_eq!(dates, vec!["2025-12-29", "2026-12-28"]);
+  }
+}
@@ -0,0 +1,616 @@
+// Calendar feed HTTP: the fetch behind `fetch_ical_url`, and batch fetching
+// (`fetch_ical_batch`).
+//
+// All feeds share one pooled HTTP client, and requests to the same host are
+// limited so a 30-apartment setup does not hammer airbnb.com with 30 parallel
+// connections. ETag, Last-Modified and the body hash of every feed are kept in
+// `sync/ical-cache.json`, so unchanged feeds cost a 304 or at most a download
+// without re-parsing on the frontend. A new body only becomes the cached one
+// once the frontend confirms it applied it (`ical_cache_ack`); until then it
+// is handed out again on every batch.
+//
+// A single fetch retries 429/5xx and network errors with jittered backoff
+// (honouring Retry-After), follows redirects itself so the chain can be shown
+// to the user, caps the body size and decodes the charset the server declares.
+
+const CACHE_FILE_NAME: &str = "ical-cache.json";
+const DEFAULT_PER_HOST_CONCURRENCY: usize = 2;
+const MAX_PER_HOST_CONCURRENCY: usize = 8;
+const DEFAULT_RETRIES: u32 = 2;
+const DEFAULT_TIMEOUT_SECS: u64 = 20;
+const DEFAULT_MAX_BYTES: usize = 10 * 1024 * 1024;
+const MAX_REDIRECTS: usize = 10;
+const RETRY_BASE_MS: u64 = 1000;
+// A Retry-After longer than this is not worth blocking a sync for.
+const MAX_RETRY_AFTER_SECS: u64 = 60;
+
+// Serialises read-modify-write of `ical-cache.json` across batches, acks and
+// forgets. Batches hold it only to read and to merge, never while fetching.
+static CACHE_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Shared by `fetch_ical_url`, `fetch_and_parse_ical` and the batch command.
+pub fn http_client() -> Result<&'static reqwest::Client, String> {
+  static CLIENT: std::sync::OnceLock<reqwest::Client> = std::sync::OnceLock::new();
+  if let Some(c) = CLIENT.get() {
+    return Ok(c);
+  }
+  let client = reqwest::Client::builder()
+    .user_agent("RentikPro/2 (calendar-sync)")
+    .pool_idle_timeout(std::time::Duration::from_secs(90))
+    // Redirects are followed in `fetch` so the chain can be reported.
+    .redirect(reqwest::redirect::Policy::none())
+    .build()
+    .map_err(|e| e.to_string())?;
+  Ok(CLIENT.get_or_init(|| client))
+}
+
+#[derive(serde::Deserialize, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FetchOptions {
+  /// Extra attempts after the first one, for 429, 5xx and network errors.
+  pub retries: u32,
+  pub timeout_secs: u64,
+  /// Limit on the decoded body; larger feeds fail instead of being read.
+  pub max_bytes: usize,
+}
+
+impl Default for FetchOptions {
+  fn default() -> Self {
+    FetchOptions { retries: DEFAULT_RETRIES, timeout_secs: DEFAULT_TIMEOUT_SECS, max_bytes: DEFAULT_MAX_BYTES }
+  }
+}
+
+#[derive(serde::Serialize, Clone)]
+pub struct RedirectHop {
+  status: u16,
+  from: String,
+  to: String,
+  /// 301/308: the feed URL should be updated.
+  permanent: bool,
+}
+
+fn is_retryable_status(status: u16) -> bool {
+  status == 429 || (500..600).contains(&status)
+}
+
+/// Retry-After as seconds or an HTTP date.
+fn retry_after(resp: &reqwest::Response) -> Option<std::time::Duration> {
+  parse_retry_after(resp.headers().get("retry-after")?.to_str().ok()?, chrono::Utc::now())
+}
+
+fn parse_retry_after(value: &str, now: chrono::DateTime<chrono::Utc>) -> Option<std::time::Duration> {
+  let value = value.trim();
+  let secs = match value.parse::<u64>() {
+    Ok(s) => s,
+    Err(_) => {
+      let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+      (at.timestamp() - now.timestamp()).max(0) as u64
+    }
+  };
+  Some(std::time::Duration::from_secs(secs.min(MAX_RETRY_AFTER_SECS)))
+}
+
+/// Exponential backoff with up to 50% jitter, so feeds that failed together do
+/// not retry in lockstep.
+fn backoff(attempt: u32) -> std::time::Duration {
+  let base = RETRY_BASE_MS.saturating_mul(1u64 << attempt.saturating_sub(1).min(6));
+  let nanos = std::time::SystemTime::now()
+    .duration_since(std::time::UNIX_EPOCH)
+    .map(|d| d.subsec_nanos() as u64)
+    .unwrap_or(0);
+  std::time::Duration::from_millis(base + nanos % (base / 2 + 1))
+}
+
+fn header(resp: &reqwest::Response, name: &str) -> Option<String> {
+  resp.headers().get(name).and_then(|v| v.to_str().ok()).map(String::from)
+}
+
+/// Decodes the body using, in order: a BOM, the Content-Type charset, UTF-8,
+/// and windows-1252 for the legacy feeds that send Latin-1 without saying so.
+fn decode_body(bytes: &[u8], content_type: Option<&str>) -> (String, String) {
+  if let Some((enc, bom_len)) = encoding_rs::Encoding::for_bom(bytes) {
+    let (text, _) = enc.decode_without_bom_handling(&bytes[bom_len..]);
+    return (text.into_owned(), enc.name().to_string());
+  }
+  let declared = content_type
+    .and_then(|ct| {
+      ct.split(';').find_map(|p| {
+        let (k, v) = p.split_once('=')?;
+        k.trim().eq_ignore_ascii_case("charset").then(|| v.trim().trim_matches('"').to_string())
+      })
+    })
+    .and_then(|label| encoding_rs::Encoding::for_label(label.as_bytes()));
+  if let Some(enc) = declared {
+    let (text, _) = enc.decode_without_bom_handling(bytes);
+    return (text.into_owned(), enc.name().to_string());
+  }
+  match std::str::from_utf8(bytes) {
+    Ok(text) => (text.to_string(), "UTF-8".to_string()),
+    Err(_) => {
+      let (text, _) = encoding_rs::WINDOWS_1252.decode_without_bom_handling(bytes);
+      (text.into_owned(), "windows-1252".to_string())
+    }
+  }
+}
+
+/// Reads the body in chunks, failing as soon as it exceeds `max_bytes`.
+async fn read_capped(mut resp: reqwest::Response, max_bytes: usize) -> Result<Vec<u8>, String> {
+  if let Some(len) = resp.content_length() {
+    if len as usize > max_bytes {
+      return Err(format!("Feed is {len} bytes, larger than the {max_bytes} byte limit"));
+    }
+  }
+  let mut out = vec![];
+  while let Some(chunk) = resp.chunk().await.map_err(|e| format!("Read error: {e}"))? {
+    if out.len() + chunk.len() > max_bytes {
+      return Err(format!("Feed is larger than the {max_bytes} byte limit"));
+    }
+    out.extend_from_slice(&chunk);
+  }
+  Ok(out)
+}
+
+/// Where a response redirects to, or `None` when it is not a redirect to
+/// follow. Fails on a loop or past `MAX_REDIRECTS` hops.
+fn next_hop(current: &str, status: u16, location: Option<&str>, hops: &[RedirectHop]) -> Result<Option<String>, String> {
+  if !(300..400).contains(&status) || status == 304 {
+    return Ok(None);
+  }
+  let next = match location.and_then(|loc| reqwest::Url::parse(current).ok()?.join(loc).ok()) {
+    Some(n) => n.to_string(),
+    None => return Ok(None),
+  };
+  if next == current || hops.iter().any(|h| h.from == next) {
+    return Err(format!("Redirect loop at {next}"));
+  }
+  if hops.len() >= MAX_REDIRECTS {
+    return Err(format!("Too many redirects (more than {MAX_REDIRECTS})"));
+  }
+  Ok(Some(next))
+}
+
+/// One GET that follows redirects itself; returns the final response and hops.
+/// Errors carry whether they are worth retrying.
+async fn get_following(
+  client: &reqwest::Client,
+  url: &str,
+  etag: Option<&str>,
+  last_modified: Option<&str>,
+  opts: &FetchOptions,
+) -> Result<(reqwest::Response, Vec<RedirectHop>), (String, bool)> {
+  let mut current = url.to_string();
+  let mut hops: Vec<RedirectHop> = vec![];
+  loop {
+    let mut req = client
+      .get(&current)
+      .timeout(std::time::Duration::from_secs(opts.timeout_secs.max(1)))
+      .header("Accept", "text/calendar, text/plain, */*");
+    if let Some(e) = etag {
+      req = req.header("If-None-Match", e);
+    }
+    if let Some(lm) = last_modified {
+      req = req.header("If-Modified-Since", lm);
+    }
+    let resp = req
+      .send()
+      .await
+      .map_err(|e| (format!("Fetch error: {e}"), e.is_timeout() || e.is_connect() || e.is_request()))?;
+    let status = resp.status().as_u16();
+    let next = match next_hop(&current, status, header(&resp, "location").as_deref(), &hops).map_err(|e| (e, false))? {
+      Some(n) => n,
+      None => return Ok((resp, hops)),
+    };
+    hops.push(RedirectHop { status, from: current.clone(), to: next.clone(), permanent: status == 301 || status == 308 });
+    current = next;
+  }
+}
+
+pub async fn fetch(url: &str, etag: Option<String>, last_modified: Option<String>, opts: &FetchOptions) -> Result<super::IcalFetchResult, String> {
+  let client = http_client()?;
+  let mut attempts = 0u32;
+  loop {
+    attempts += 1;
+    let can_retry = attempts <= opts.retries;
+    let (resp, redirects) = match get_following(client, url, etag.as_deref(), last_modified.as_deref(), opts).await {
+      Ok(r) => r,
+      Err((_, true)) if can_retry => {
+        tokio::time::sleep(backoff(attempts)).await;
+        continue;
+      }
+      Err((e, _)) => return Err(e),
+    };
+
+    let status = resp.status().as_u16();
+    if can_retry && is_retryable_status(status) {
+      let wait = retry_after(&resp).unwrap_or_else(|| backoff(attempts));
+      tokio::time::sleep(wait).await;
+      continue;
+    }
+
+    let final_url = resp.url().to_string();
+    let etag_out = header(&resp, "etag");
+    let lm_out = header(&resp, "last-modified");
+    let ct_out = header(&resp, "content-type");
+    let bytes = read_capped(resp, opts.max_bytes).await?;
+    let (body, charset) = decode_body(&bytes, ct_out.as_deref());
+    return Ok(super::IcalFetchResult {
+      status,
+      body,
+      etag: etag_out,
+      last_modified: lm_out,
+      content_type: ct_out,
+      final_url,
+      redirects,
+      attempts,
+      charset,
+    });
+  }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct FeedCacheEntry {
+  url: String,
+  /// Validators and hash of the body the frontend last acknowledged.
+  etag: Option<String>,
+  last_modified: Option<String>,
+  body_sha256: Option<String>,
+  /// Newer body handed out but not acknowledged yet.
+  pending: Option<PendingBody>,
+  last_status: Option<u16>,
+  last_fetched_at: Option<i64>,
+  last_changed_at: Option<i64>,
+  last_error: Option<String>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct PendingBody {
+  body_sha256: String,
+  etag: Option<String>,
+  last_modified: Option<String>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct FeedCacheFileV1 {
+  version: u32,
+  feeds: std::collections::BTreeMap<String, FeedCacheEntry>,
+}
+
+#[derive(serde::Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FeedRequest {
+  /// Channel connection id; keys the cache entry.
+  pub id: String,
+  pub url: String,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeedResult {
+  id: String,
+  url: String,
+  /// "unchanged" | "updated" | "error"
+  status: String,
+  http_status: Option<u16>,
+  /// Only set for "updated". Pass `body_sha256` to `ical_cache_ack` once the
+  /// events are stored, or the same body comes back on the next batch.
+  body: Option<String>,
+  body_sha256: Option<String>,
+  etag: Option<String>,
+  last_modified: Option<String>,
+  error: Option<String>,
+  /// Set when the feed answered from a different URL.
+  final_url: Option<String>,
+  redirects: Vec<RedirectHop>,
+  attempts: u32,
+  elapsed_ms: u64,
+}
+
+fn cache_path(root: &std::path::Path) -> std::path::PathBuf {
+  root.join("sync").join(CACHE_FILE_NAME)
+}
+
+fn host_of(url: &str) -> String {
+  reqwest::Url::parse(url)
+    .ok()
+    .and_then(|u| u.host_str().map(|h| h.to_ascii_lowercase()))
+    .unwrap_or_default()
+}
+
+async fn fetch_one(feed: FeedRequest, cached: Option<FeedCacheEntry>, gate: std::sync::Arc<tokio::sync::Semaphore>, opts: &FetchOptions) -> (FeedResult, FeedCacheEntry) {
+  let started = std::time::Instant::now();
+  // Conditional headers only make sense for the URL they were issued for.
+  let cached = cached.filter(|c| c.url == feed.url).unwrap_or_default();
+  let mut entry = FeedCacheEntry { url: feed.url.clone(), last_fetched_at: Some(chrono::Utc::now().timestamp_millis()), ..cached.clone() };
+
+  let fetched = {
+    let _permit = gate.acquire().await;
+    fetch(&feed.url, cached.etag.clone(), cached.last_modified.clone(), opts).await
+  };
+  let elapsed_ms = started.elapsed().as_millis() as u64;
+
+  let mut result = FeedResult {
+    id: feed.id,
+    url: feed.url,
+    status: "error".to_string(),
+    http_status: None,
+    body: None,
+    body_sha256: cached.body_sha256.clone(),
+    etag: cached.etag.clone(),
+    last_modified: cached.last_modified.clone(),
+    error: None,
+    final_url: None,
+    redirects: vec![],
+    attempts: 0,
+    elapsed_ms,
+  };
+
+  match fetched {
+    Err(e) => result.error = Some(e),
+    Ok(resp) => {
+      result.http_status = Some(resp.status);
+      result.attempts = resp.attempts;
+      if !resp.redirects.is_empty() {
+        result.final_url = Some(resp.final_url.clone());
+        result.redirects = resp.redirects.clone();
+      }
+      entry.last_status = Some(resp.status);
+      if resp.status == 304 {
+        // Validators belong to the acknowledged body, so the feed is back to it.
+        result.status = "unchanged".to_string();
+        entry.pending = None;
+      } else if (200..300).contains(&resp.status) {
+        let sha = super::sha256_hex(resp.body.as_bytes());
+        result.etag = resp.etag.clone();
+        result.last_modified = resp.last_modified.clone();
+        if cached.body_sha256.as_deref() == Some(sha.as_str()) {
+          result.status = "unchanged".to_string();
+          entry.etag = resp.etag.clone();
+          entry.last_modified = resp.last_modified.clone();
+          entry.pending = None;
+        } else {
+          result.status = "updated".to_string();
+          result.body = Some(resp.body);
+          if entry.pending.as_ref().map(|p| p.body_sha256 != sha).unwrap_or(true) {
+            entry.last_changed_at = entry.last_fetched_at;
+          }
+          // Validators stay on the acknowledged body, so a 304 never hides
+          // a body the frontend has not applied.
+          entry.pending = Some(PendingBody { body_sha256: sha.clone(), etag: resp.etag.clone(), last_modified: resp.last_modified.clone() });
+        }
+        result.body_sha256 = Some(sha);
+      } else {
+        result.error = Some(format!("HTTP {}", resp.status));
+      }
+    }
+  }
+  entry.last_error = result.error.clone();
+  (result, entry)
+}
+
+/// Fetches every feed with one pooled client, at most `per_host_concurrency`
+/// requests per host at a time.
+///
+/// With `force`, cached validators and hashes are ignored and every feed is
+/// reported as "updated".
+///
+/// "updated" feeds keep being reported as such until `ical_cache_ack`
+/// confirms their body was applied.
+#[tauri::command]
+pub async fn fetch_ical_batch(
+  workspace_path: String,
+  feeds: Vec<FeedRequest>,
+  per_host_concurrency: Option<usize>,
+  force: Option<bool>,
+  options: Option<FetchOptions>,
+) -> Result<Vec<FeedResult>, String> {
+  let opts = options.unwrap_or_default();
+  let root = std::path::PathBuf::from(&workspace_path);
+  if !root.is_dir() {
+    return Err("Workspace folder does not exist".to_string());
+  }
+  let path = cache_path(&root);
+  let snapshot = {
+    let _cache_guard = CACHE_LOCK.lock().await;
+    super::read_json_file::<FeedCacheFileV1>(&path).unwrap_or_default()
+  };
+  let force = force.unwrap_or(false);
+  let limit = per_host_concurrency.unwrap_or(DEFAULT_PER_HOST_CONCURRENCY).clamp(1, MAX_PER_HOST_CONCURRENCY);
+
+  let mut gates: std::collections::HashMap<String, std::sync::Arc<tokio::sync::Semaphore>> = std::collections::HashMap::new();
+  let jobs = feeds.into_iter().map(|feed| {
+    let gate = gates
+      .entry(host_of(&feed.url))
+      .or_insert_with(|| std::sync::Arc::new(tokio::sync::Semaphore::new(limit)))
+      .clone();
+    let cached = if force { None } else { snapshot.feeds.get(&feed.id).cloned() };
+    fetch_one(feed, cached, gate, &opts)
+  });
+  let done = futures_util::future::join_all(jobs).await;
+
+  let _cache_guard = CACHE_LOCK.lock().await;
+  let mut cache = super::read_json_file::<FeedCacheFileV1>(&path).unwrap_or_default();
+  let mut results = Vec::with_capacity(done.len());
+  for (result, entry) in done {
+    match merge_fetched(snapshot.feeds.get(&result.id), cache.feeds.get(&result.id), entry) {
+      Some(entry) => cache.feeds.insert(result.id.clone(), entry),
+      None => cache.feeds.remove(&result.id),
+    };
+    results.push(result);
+  }
+  write_cache(&path, &mut cache)?;
+  Ok(results)
+}
+
+/// Folds a fetched entry into the cache as it is now. A feed forgotten while
+/// it was fetched stays gone, and an ack that landed meanwhile is kept; if it
+/// acknowledged the very body just fetched, that body is no longer pending.
+fn merge_fetched(before: Option<&FeedCacheEntry>, now: Option<&FeedCacheEntry>, mut fetched: FeedCacheEntry) -> Option<FeedCacheEntry> {
+  let acked = |e: &FeedCacheEntry| (e.body_sha256.clone(), e.etag.clone(), e.last_modified.clone());
+  match (before, now) {
+    (Some(_), None) => None,
+    (Some(before), Some(now)) if acked(before) != acked(now) => {
+      fetched.body_sha256 = now.body_sha256.clone();
+      fetched.etag = now.etag.clone();
+      fetched.last_modified = now.last_modified.clone();
+      if fetched.pending.as_ref().is_some_and(|p| now.body_sha256.as_deref() == Some(p.body_sha256.as_str())) {
+        fetched.pending = None;
+      }
+      Some(fetched)
+    }
+    _ => Some(fetched),
+  }
+}
+
+fn write_cache(path: &std::path::Path, cache: &mut FeedCacheFileV1) -> Result<(), String> {
+  cache.version = 1;
+  let txt = serde_json::to_vec_pretty(cache).map_err(|e| format!("Feed cache encode failed: {e}"))?;
+  super::atomic_write(path, &txt)
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeedAck {
+  pub id: String,
+  pub body_sha256: String,
+}
+
+/// Marks bodies from `fetch_ical_batch` as applied, so later batches report
+/// them as unchanged. Returns the ids whose ack matched the pending body; an
+/// ack for a body that a newer batch already replaced is ignored.
+#[tauri::command]
+pub async fn ical_cache_ack(workspace_path: String, acks: Vec<FeedAck>) -> Result<Vec<String>, String> {
+  let path = cache_path(std::path::Path::new(&workspace_path));
+  let _cache_guard = CACHE_LOCK.lock().await;
+  let mut cache = match super::read_json_file::<FeedCacheFileV1>(&path) {
+    Some(c) => c,
+    None => return Ok(vec![]),
+  };
+  let mut applied = vec![];
+  for ack in acks {
+    let entry = match cache.feeds.get_mut(&ack.id) {
+      Some(e) => e,
+      None => continue,
+    };
+    match entry.pending.take() {
+      Some(p) if p.body_sha256 == ack.body_sha256 => {
+        entry.body_sha256 = Some(p.body_sha256);
+        entry.etag = p.etag;
+        entry.last_modified = p.last_modified;
+        applied.push(ack.id);
+      }
+      other => entry.pending = other,
+    }
+  }
+  if !applied.is_empty() {
+    write_cache(&path, &mut cache)?;
+  }
+  Ok(applied)
+}
+
+/// Drops cache entries, e.g. after a channel connection was deleted.
+#[tauri::command]
+pub async fn ical_cache_forget(workspace_path: String, ids: Vec<String>) -> Result<(), String> {
+  let path = cache_path(std::path::Path::new(&workspace_path));
+  let _cache_guard = CACHE_LOCK.lock().await;
+  let mut cache = match super::read_json_file::<FeedCacheFileV1>(&path) {
+    Some(c) => c,
+    None => return Ok(()),
+  };
+  for id in ids {
+    cache.feeds.remove(&id);
+  }
+  write_cache(&path, &mut cache)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn entry(body: &str, pending: Option<&str>) -> FeedCacheEntry {
+    FeedCacheEntry {
+      url: "https://example.com/a.ics".to_string(),
+      body_sha256: Some(body.to_string()),
+      etag: Some(format!("\"{body}\"")),
+      pending: pending.map(|p| PendingBody { body_sha256: p.to_string(), etag: Some(format!("\"{p}\"")), last_modified: None }),
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn decode_body_prefers_bom_then_charset_then_utf8() {
+    let mut utf8_bom = vec![0xEF, 0xBB, 0xBF];
+    utf8_bom.extend_from_slice("Café".as_bytes());
+    assert_eq!(decode_body(&utf8_bom, Some("text/calendar; charset=iso-8859-1")), ("Café".to_string(), "UTF-8".to_string()));
+
+    let utf16: Vec<u8> = [0xFF, 0xFE].into_iter().chain("Añ".encode_utf16().flat_map(|u| u.to_le_bytes())).collect();
+    assert_eq!(decode_body(&utf16, None), ("Añ".to_string(), "UTF-16LE".to_string()));
+
+    let latin1 = [b'C', b'a', b'f', 0xE9];
+    assert_eq!(decode_body(&latin1, Some("text/calendar; Charset=\"ISO-8859-1\"")).0, "Café");
+    assert_eq!(decode_body("Café".as_bytes(), Some("text/calendar")), ("Café".to_string(), "UTF-8".to_string()));
+  }
+
+  #[test]
+  fn decode_body_falls_back_to_windows_1252() {
+    let bytes = [b'a', 0x80, b'b'];
+    assert_eq!(decode_body(&bytes, None), ("a€b".to_string(), "windows-1252".to_string()));
+  }
+
+  #[test]
+  fn retry_after_accepts_seconds_and_http_dates() {
+    let now = chrono::DateTime::parse_from_rfc3339("2015-10-21T07:28:00Z").unwrap().with_timezone(&chrono::Utc);
+    let secs = std::time::Duration::from_secs;
+    assert_eq!(parse_retry_after(" 12 ", now), Some(secs(12)));
+    assert_eq!(parse_retry_after("3600", now), Some(secs(MAX_RETRY_AFTER_SECS)));
+    assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now), Some(secs(30)));
+    assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:00:00 GMT", now), Some(secs(0)));
+    assert_eq!(parse_retry_after("soon", now), None);
+  }
+
+  #[test]
+  fn redirects_stop_at_the_limit_and_on_loops() {
+    let hop = |i: usize| RedirectHop { status: 302, from: format!("https://a.test/{i}"), to: format!("https://a.test/{}", i + 1), permanent: false };
+    let hops: Vec<RedirectHop> = (0..MAX_REDIRECTS).map(hop).collect();
+    let current = format!("https://a.test/{MAX_REDIRECTS}");
+    let err = next_hop(&current, 302, Some("/next"), &hops).unwrap_err();
+    assert!(err.starts_with("Too many redirects"), "{err}");
+    assert_eq!(next_hop(&current, 302, Some("/next"), &hops[..1]), Ok(Some("https://a.test/next".to_string())));
+
+    assert!(next_hop("https://a.test/1", 301, Some("/0"), &hops[..1]).unwrap_err().starts_with("Redirect loop"));
+    assert_eq!(next_hop("https://a.test/1", 304, Some("/x"), &[]), Ok(None));
+    assert_eq!(next_hop("https://a.test/1", 302, None, &[]), Ok(None));
+  }
+
+  #[test]
+  fn merge_keeps_a_feed_forgotten_during_the_fetch_gone() {
+    let before = entry("a", None);
+    assert!(merge_fetched(Some(&before), None, entry("a", Some("b"))).is_none());
+    // A feed that was not cached before is new, not forgotten.
+    assert!(merge_fetched(None, None, entry("a", None)).is_some());
+  }
+
+  #[test]
+  fn merge_keeps_an_ack_that_landed_during_the_fetch() {
+    let before = entry("a", Some("b"));
+    let now = entry("b", None);
+    // The fetch saw body "b" again: it was just acknowledged, so not pending.
+    let merged = merge_fetched(Some(&before), Some(&now), entry("a", Some("b"))).unwrap();
+    assert_eq!(merged.body_sha256.as_deref(), Some("b"));
+    assert_eq!(merged.etag.as_deref(), Some("\"b\""));
+    assert!(merged.pending.is_none());
+    // A newer body "c" stays pending on top of the ack.
+    let merged = merge_fetched(Some(&before), Some(&now), entry("a", Some("c"))).unwrap();
+    assert_eq!(merged.body_sha256.as_deref(), Some("b"));
+    assert_eq!(merged.pending.map(|p| p.body_sha256).as_deref(), Some("c"));
+  }
+
+  #[test]
+  fn merge_without_concurrent_changes_takes_the_fetched_entry() {
+    let before = entry("a", None);
+    let merged = merge_fetched(Some(&before), Some(&before), entry("a", Some("b"))).unwrap();
+    assert_eq!(merged.pending.map(|p| p.body_sha256).as_deref(), Some("b"));
+  }
+}
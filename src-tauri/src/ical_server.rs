@@ -0,0 +1,683 @@
+// Embedded HTTP server publishing availability as iCal feeds.
+//
+// Serves `GET /ical/<apartment_id>.ics?token=<token>` straight from the
+// workspace database, so channel managers can pull our calendar over the LAN
+// or through a tunnel without the Cloudflare worker. Every apartment has its
+// own random token in `sync/ical-server.json`; a feed without a token is not
+// served at all. Output matches `iCalGenerator.ts`: confirmed bookings plus
+// blocks from `calendar_events`, all as "CLOSED - Not available".
+//
+// The server thread keeps one decrypted copy of the database and only reloads
+// it when the file's size or modification time changes.
+
+use chrono::Datelike;
+
+const TOKENS_FILE_NAME: &str = "ical-server.json";
+const DEFAULT_PORT: u16 = 8765;
+const WINDOW_PAST_DAYS: i64 = 30;
+const WINDOW_FUTURE_MONTHS: u32 = 24;
+// Used when neither the apartment's property nor the user settings name one.
+const DEFAULT_TIMEZONE: &str = "Europe/Madrid";
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct FeedTokenEntry {
+  token: String,
+  created_at: i64,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct FeedTokensFileV1 {
+  version: u32,
+  feeds: std::collections::BTreeMap<String, FeedTokenEntry>,
+}
+
+#[derive(serde::Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct IcalServerStatus {
+  running: bool,
+  workspace_path: Option<String>,
+  bind: Option<String>,
+  port: Option<u16>,
+  requests_served: u64,
+  last_request_at: Option<i64>,
+  last_error: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IcalFeedInfo {
+  apartment_id: String,
+  token: String,
+  /// Path and query to append to the server's base URL.
+  path: String,
+  created_at: i64,
+}
+
+/// Decrypted database kept by the server thread until the file changes.
+struct Snapshot {
+  modified: std::time::SystemTime,
+  len: u64,
+  conn: rusqlite::Connection,
+}
+
+struct ServerHandle {
+  server: std::sync::Arc<tiny_http::Server>,
+  thread: Option<std::thread::JoinHandle<()>>,
+  status: std::sync::Arc<std::sync::Mutex<IcalServerStatus>>,
+}
+
+#[derive(Default)]
+pub struct IcalServerState {
+  handle: std::sync::Mutex<Option<ServerHandle>>,
+}
+
+impl ServerHandle {
+  fn shutdown(mut self) {
+    self.server.unblock();
+    if let Some(t) = self.thread.take() {
+      let _ = t.join();
+    }
+  }
+}
+
+impl IcalServerState {
+  fn stop_current(&self) {
+    let handle = self.handle.lock().unwrap().take();
+    if let Some(h) = handle {
+      h.shutdown();
+    }
+  }
+
+  fn running_port(&self) -> Option<u16> {
+    self.handle.lock().unwrap().as_ref().and_then(|h| h.status.lock().unwrap().port)
+  }
+
+  /// Stops the server when it serves the folder at `root` and returns the
+  /// port and whether it listened on the LAN, for restarting it elsewhere.
+  pub fn take_for(&self, root: &std::path::Path) -> Option<(Option<u16>, bool)> {
+    let listen = match self.handle.lock().unwrap().as_ref() {
+      Some(h) => {
+        let status = h.status.lock().unwrap();
+        match status.workspace_path.as_deref() {
+          Some(p) if super::workspace_registry::same_path(p, &root.to_string_lossy()) => (status.port, status.bind.as_deref() == Some("0.0.0.0")),
+          _ => return None,
+        }
+      }
+      None => return None,
+    };
+    self.stop_current();
+    Some(listen)
+  }
+
+  /// Binds the new server before the running one is replaced, so a port that
+  /// is taken leaves the current server up. Only when the current server holds
+  /// the very port asked for does it have to stop first.
+  pub fn start(&self, workspace_path: String, port: Option<u16>, lan: bool) -> Result<IcalServerStatus, String> {
+    let root = workspace_root(&workspace_path)?;
+
+    let bind = if lan { "0.0.0.0" } else { "127.0.0.1" };
+    let port = port.unwrap_or(DEFAULT_PORT);
+    let listen = || tiny_http::Server::http((bind, port)).map_err(|e| format!("Cannot listen on {bind}: {e}"));
+    let server = match listen() {
+      Ok(server) => server,
+      Err(_) if self.running_port() == Some(port) => {
+        self.stop_current();
+        // tiny_http releases the socket on its accept thread after the drop.
+        let mut attempt = 0;
+        loop {
+          match listen() {
+            Ok(server) => break server,
+            Err(e) if attempt >= 20 => return Err(e),
+            Err(_) => attempt += 1,
+          }
+          std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+      }
+      Err(e) => return Err(e),
+    };
+    let actual_port = server.server_addr().to_ip().map(|a| a.port());
+    let server = std::sync::Arc::new(server);
+    let status = std::sync::Arc::new(std::sync::Mutex::new(IcalServerStatus {
+      running: true,
+      workspace_path: Some(workspace_path),
+      bind: Some(bind.to_string()),
+      port: actual_port,
+      ..Default::default()
+    }));
+    let thread = {
+      let (server, status) = (server.clone(), status.clone());
+      std::thread::Builder::new()
+        .name("ical-server".to_string())
+        .spawn(move || serve(server, root, status))
+        .map_err(|e| format!("Failed starting server thread: {e}"))?
+    };
+    let snapshot = status.lock().unwrap().clone();
+    let old = self.handle.lock().unwrap().replace(ServerHandle { server, thread: Some(thread), status });
+    if let Some(old) = old {
+      old.shutdown();
+    }
+    Ok(snapshot)
+  }
+}
+
+/// Rejects paths that are not a workspace, so tokens are never written into
+/// an arbitrary folder.
+fn workspace_root(workspace_path: &str) -> Result<std::path::PathBuf, String> {
+  let root = std::path::PathBuf::from(workspace_path);
+  if !root.is_dir() {
+    return Err("Workspace folder does not exist".to_string());
+  }
+  let (_wjson, db, _backups, _media) = super::workspace_paths(&root);
+  if !db.exists() {
+    return Err(format!("Missing {} in workspace", super::WORKSPACE_DB_NAME));
+  }
+  Ok(root)
+}
+
+fn tokens_path(root: &std::path::Path) -> std::path::PathBuf {
+  root.join("sync").join(TOKENS_FILE_NAME)
+}
+
+fn read_tokens(root: &std::path::Path) -> FeedTokensFileV1 {
+  super::read_json_file(&tokens_path(root)).unwrap_or_default()
+}
+
+fn write_tokens(root: &std::path::Path, file: &mut FeedTokensFileV1) -> Result<(), String> {
+  file.version = 1;
+  let txt = serde_json::to_vec_pretty(file).map_err(|e| format!("Feed tokens encode failed: {e}"))?;
+  super::atomic_write(&tokens_path(root), &txt)
+}
+
+fn new_token() -> String {
+  use aes_gcm::aead::rand_core::RngCore;
+  let mut bytes = [0u8; 24];
+  aes_gcm::aead::OsRng.fill_bytes(&mut bytes);
+  hex::encode(bytes)
+}
+
+fn is_valid_id(id: &str) -> bool {
+  !id.is_empty() && id.len() <= 128 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+  a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn feed_path(apartment_id: &str, token: &str) -> String {
+  format!("/ical/{apartment_id}.ics?token={token}")
+}
+
+/// RFC 5545 §3.1: lines longer than 75 octets are folded, never inside a
+/// UTF-8 sequence.
+fn fold_line(line: &str, out: &mut String) {
+  let mut width = 0;
+  for c in line.chars() {
+    let len = c.len_utf8();
+    if width + len > 75 {
+      out.push_str("\r\n ");
+      width = 1;
+    }
+    out.push(c);
+    width += len;
+  }
+  out.push_str("\r\n");
+}
+
+fn escape_text(s: &str) -> String {
+  s.replace('\\', "\\\\").replace(';', "\\;").replace(',', "\\,").replace('\n', "\\n")
+}
+
+fn parse_day(s: &str) -> Option<chrono::NaiveDate> {
+  chrono::NaiveDate::parse_from_str(s.get(0..10)?, "%Y-%m-%d").ok()
+}
+
+struct Block {
+  uid: String,
+  start: chrono::NaiveDate,
+  end: chrono::NaiveDate,
+}
+
+fn column_or_null(conn: &rusqlite::Connection, table: &str, column: &str) -> String {
+  if super::workspace_db::has_column(conn, table, column) {
+    column.to_string()
+  } else {
+    "NULL".to_string()
+  }
+}
+
+/// Same UID rules as `iCalGenerator.ts`, so switching from the worker does not
+/// make OTAs see every block as new.
+fn booking_uid(id: &str, source: Option<&str>, ical_uid: Option<&str>) -> String {
+  if matches!(source, Some("DIRECT_WEB") | Some("WEB_CHECKOUT")) {
+    return format!("rp-web-{id}");
+  }
+  match ical_uid.filter(|u| !u.is_empty() && !u.starts_with("IMP-") && !u.starts_with("rp-")) {
+    Some(u) => u.to_string(),
+    None => format!("rp-{id}"),
+  }
+}
+
+fn load_blocks(conn: &rusqlite::Connection, apartment_id: &str) -> Result<Vec<Block>, String> {
+  let sql_err = |e: rusqlite::Error| format!("Query failed: {e}");
+  let mut blocks = vec![];
+  let mut booking_ids = std::collections::HashSet::new();
+
+  let deleted = if super::workspace_db::has_column(conn, "bookings", "deleted_at") { " AND deleted_at IS NULL" } else { "" };
+  let sql = format!(
+    "SELECT id, check_in, check_out, status, source, {} FROM bookings WHERE apartment_id = ?1{deleted}",
+    column_or_null(conn, "bookings", "ical_uid")
+  );
+  let mut stmt = conn.prepare(&sql).map_err(sql_err)?;
+  let rows = stmt
+    .query_map([apartment_id], |r| {
+      Ok((
+        r.get::<_, String>(0)?,
+        r.get::<_, Option<String>>(1)?,
+        r.get::<_, Option<String>>(2)?,
+        r.get::<_, Option<String>>(3)?,
+        r.get::<_, Option<String>>(4)?,
+        r.get::<_, Option<String>>(5)?,
+      ))
+    })
+    .map_err(sql_err)?;
+  for row in rows {
+    let (id, check_in, check_out, status, source, ical_uid) = row.map_err(sql_err)?;
+    if !status.as_deref().map(|s| s.eq_ignore_ascii_case("confirmed")).unwrap_or(false) {
+      continue;
+    }
+    let (start, end) = match (check_in.as_deref().and_then(parse_day), check_out.as_deref().and_then(parse_day)) {
+      (Some(s), Some(e)) if e > s => (s, e),
+      _ => continue,
+    };
+    blocks.push(Block { uid: booking_uid(&id, source.as_deref(), ical_uid.as_deref()), start, end });
+    booking_ids.insert(id);
+  }
+
+  if super::workspace_db::has_column(conn, "calendar_events", "apartment_id") {
+    let sql = format!(
+      "SELECT id, start_date, end_date, status, {}, {} FROM calendar_events WHERE apartment_id = ?1",
+      column_or_null(conn, "calendar_events", "booking_id"),
+      column_or_null(conn, "calendar_events", "event_state")
+    );
+    let mut stmt = conn.prepare(&sql).map_err(sql_err)?;
+    let rows = stmt
+      .query_map([apartment_id], |r| {
+        Ok((
+          r.get::<_, String>(0)?,
+          r.get::<_, Option<String>>(1)?,
+          r.get::<_, Option<String>>(2)?,
+          r.get::<_, Option<String>>(3)?,
+          r.get::<_, Option<String>>(4)?,
+          r.get::<_, Option<String>>(5)?,
+        ))
+      })
+      .map_err(sql_err)?;
+    for row in rows {
+      let (id, start, end, status, booking_id, state) = row.map_err(sql_err)?;
+      let cancelled = |v: &Option<String>| v.as_deref().map(|s| s.eq_ignore_ascii_case("cancelled")).unwrap_or(false);
+      if cancelled(&status) || cancelled(&state) {
+        continue;
+      }
+      // Already exported through its booking.
+      if booking_id.as_ref().map(|b| booking_ids.contains(b)).unwrap_or(false) {
+        continue;
+      }
+      if let (Some(s), Some(e)) = (start.as_deref().and_then(parse_day), end.as_deref().and_then(parse_day)) {
+        if e > s {
+          blocks.push(Block { uid: format!("rp-ev-{id}"), start: s, end: e });
+        }
+      }
+    }
+  }
+  Ok(blocks)
+}
+
+/// The apartment's property time zone, else the workspace default.
+fn feed_timezone(conn: &rusqlite::Connection, apartment_id: &str) -> String {
+  let mut candidates: Vec<Option<String>> = vec![];
+  if super::workspace_db::has_column(conn, "apartments", "property_id") && super::workspace_db::has_column(conn, "properties", "timezone") {
+    candidates.push(
+      conn
+        .query_row(
+          "SELECT p.timezone FROM apartments a JOIN properties p ON p.id = a.property_id WHERE a.id = ?1",
+          [apartment_id],
+          |r| r.get::<_, Option<String>>(0),
+        )
+        .ok()
+        .flatten(),
+    );
+  }
+  if super::workspace_db::has_column(conn, "user_settings", "default_timezone") {
+    candidates.push(conn.query_row("SELECT default_timezone FROM user_settings LIMIT 1", [], |r| r.get::<_, Option<String>>(0)).ok().flatten());
+  }
+  candidates
+    .into_iter()
+    .flatten()
+    .find(|tz| tz.trim().parse::<chrono_tz::Tz>().is_ok())
+    .map(|tz| tz.trim().to_string())
+    .unwrap_or_else(|| DEFAULT_TIMEZONE.to_string())
+}
+
+/// Returns the cached connection, reloading it when `database.sqlite` changed.
+fn snapshot<'a>(root: &std::path::Path, cache: &'a mut Option<Snapshot>) -> Result<&'a Snapshot, String> {
+  let (_wjson, db, _backups, _media) = super::workspace_paths(root);
+  let meta = std::fs::metadata(&db).map_err(|e| format!("Failed reading {}: {e}", db.display()))?;
+  let modified = meta.modified().unwrap_or(std::time::UNIX_EPOCH);
+  let fresh = cache.as_ref().map(|s| s.modified == modified && s.len == meta.len()).unwrap_or(false);
+  if !fresh {
+    *cache = None;
+    let conn = super::workspace_db::open_snapshot(root)?;
+    *cache = Some(Snapshot { modified, len: meta.len(), conn });
+  }
+  cache.as_ref().ok_or_else(|| "Snapshot unavailable".to_string())
+}
+
+/// Returns None when the apartment does not exist.
+fn build_feed(root: &std::path::Path, cache: &mut Option<Snapshot>, apartment_id: &str) -> Result<Option<String>, String> {
+  let snap = snapshot(root, cache)?;
+  let conn = &snap.conn;
+  let name: Option<String> = match conn.query_row("SELECT name FROM apartments WHERE id = ?1", [apartment_id], |r| r.get::<_, Option<String>>(0)) {
+    Ok(n) => n,
+    Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+    Err(e) => return Err(format!("Query failed: {e}")),
+  };
+  let mut blocks = load_blocks(conn, apartment_id)?;
+  blocks.sort_by(|a, b| a.start.cmp(&b.start).then_with(|| a.uid.cmp(&b.uid)));
+
+  let today = chrono::Utc::now().date_naive();
+  let window_start = today - chrono::Duration::days(WINDOW_PAST_DAYS);
+  let window_end = today
+    .checked_add_months(chrono::Months::new(WINDOW_FUTURE_MONTHS))
+    .unwrap_or_else(|| today.with_year(today.year() + 2).unwrap_or(today));
+
+  // DTSTAMP follows the database file, so the body (and its ETag) only
+  // changes when the data does.
+  let stamp: chrono::DateTime<chrono::Utc> = snap.modified.into();
+  let dtstamp = stamp.format("%Y%m%dT%H%M%SZ").to_string();
+
+  let mut out = String::new();
+  for line in [
+    "BEGIN:VCALENDAR".to_string(),
+    "VERSION:2.0".to_string(),
+    "PRODID:-//RentikPro//iCal Export 2.0//EN".to_string(),
+    "CALSCALE:GREGORIAN".to_string(),
+    "METHOD:PUBLISH".to_string(),
+    format!("X-WR-CALNAME:{}", escape_text(&format!("Unit Feed {}", name.unwrap_or_else(|| apartment_id.to_string())))),
+    format!("X-WR-TIMEZONE:{}", feed_timezone(conn, apartment_id)),
+  ] {
+    fold_line(&line, &mut out);
+  }
+  for b in blocks.iter().filter(|b| b.end > window_start && b.start < window_end) {
+    for line in [
+      "BEGIN:VEVENT".to_string(),
+      format!("DTSTAMP:{dtstamp}"),
+      format!("UID:{}@rentikpro.app", escape_text(&b.uid)),
+      format!("DTSTART;VALUE=DATE:{}", b.start.format("%Y%m%d")),
+      format!("DTEND;VALUE=DATE:{}", b.end.format("%Y%m%d")),
+      "SUMMARY:CLOSED - Not available".to_string(),
+      "DESCRIPTION:RentikPro blocked dates".to_string(),
+      "STATUS:CONFIRMED".to_string(),
+      "TRANSP:OPAQUE".to_string(),
+      "END:VEVENT".to_string(),
+    ] {
+      fold_line(&line, &mut out);
+    }
+  }
+  fold_line("END:VCALENDAR", &mut out);
+  Ok(Some(out))
+}
+
+fn text_response(status: u16, body: &str) -> tiny_http::Response<std::io::Cursor<Vec<u8>>> {
+  tiny_http::Response::from_string(body).with_status_code(status)
+}
+
+fn header(name: &str, value: &str) -> tiny_http::Header {
+  tiny_http::Header::from_bytes(name.as_bytes(), value.as_bytes()).unwrap()
+}
+
+fn handle_request(
+  root: &std::path::Path,
+  cache: &mut Option<Snapshot>,
+  status: &std::sync::Mutex<IcalServerStatus>,
+  req: &tiny_http::Request,
+) -> tiny_http::Response<std::io::Cursor<Vec<u8>>> {
+  if !matches!(req.method(), tiny_http::Method::Get | tiny_http::Method::Head) {
+    return text_response(405, "Method not allowed");
+  }
+  let (path, query) = req.url().split_once('?').unwrap_or((req.url(), ""));
+  let apartment_id = match path.strip_prefix("/ical/").and_then(|p| p.strip_suffix(".ics")) {
+    Some(id) if is_valid_id(id) => id,
+    _ => return text_response(404, "Not found"),
+  };
+  let token = query.split('&').find_map(|kv| kv.strip_prefix("token=")).unwrap_or("");
+
+  // Unknown apartment and wrong token look the same from outside.
+  let expected = read_tokens(root).feeds.get(apartment_id).map(|e| e.token.clone());
+  match expected {
+    Some(t) if !token.is_empty() && constant_time_eq(t.as_bytes(), token.as_bytes()) => {}
+    _ => return text_response(404, "Not found"),
+  }
+
+  let body = match build_feed(root, cache, apartment_id) {
+    Ok(Some(b)) => b,
+    Ok(None) => return text_response(404, "Not found"),
+    Err(e) if e == super::db_crypto::LOCKED_ERROR => return text_response(503, "Workspace is locked"),
+    Err(e) => {
+      // Only reachable with a valid token, so the reason can go to the caller;
+      // `ical_server_status` shows it to the user as well.
+      let msg = format!("Feed {apartment_id} failed: {e}");
+      status.lock().unwrap().last_error = Some(msg.clone());
+      return text_response(500, &msg);
+    }
+  };
+  let etag = format!("\"{}\"", &super::sha256_hex(body.as_bytes())[..32]);
+  let not_modified = req
+    .headers()
+    .iter()
+    .any(|h| h.field.equiv("If-None-Match") && h.value.as_str().split(',').any(|v| v.trim() == etag));
+
+  let resp = if not_modified { text_response(304, "") } else { text_response(200, &body) };
+  resp
+    .with_header(header("Content-Type", "text/calendar; charset=utf-8"))
+    .with_header(header("Content-Disposition", &format!("inline; filename=\"{apartment_id}.ics\"")))
+    .with_header(header("Cache-Control", "no-cache"))
+    .with_header(header("ETag", &etag))
+}
+
+fn serve(server: std::sync::Arc<tiny_http::Server>, root: std::path::PathBuf, status: std::sync::Arc<std::sync::Mutex<IcalServerStatus>>) {
+  let mut cache: Option<Snapshot> = None;
+  for req in server.incoming_requests() {
+    let resp = handle_request(&root, &mut cache, &status, &req);
+    {
+      let mut s = status.lock().unwrap();
+      s.requests_served += 1;
+      s.last_request_at = Some(chrono::Utc::now().timestamp_millis());
+    }
+    if let Err(e) = req.respond(resp) {
+      status.lock().unwrap().last_error = Some(format!("Response failed: {e}"));
+    }
+  }
+}
+
+/// Starts serving the workspace's feeds, replacing a running server.
+///
+/// Binds to 127.0.0.1 unless `lan` is set; use `lan` (or a tunnel pointed at
+/// the local port) for channel managers outside this machine.
+#[tauri::command]
+pub fn ical_server_start(state: tauri::State<'_, IcalServerState>, workspace_path: String, port: Option<u16>, lan: Option<bool>) -> Result<IcalServerStatus, String> {
+  state.start(workspace_path, port, lan.unwrap_or(false))
+}
+
+#[tauri::command]
+pub fn ical_server_stop(state: tauri::State<'_, IcalServerState>) -> Result<(), String> {
+  state.stop_current();
+  Ok(())
+}
+
+#[tauri::command]
+pub fn ical_server_status(state: tauri::State<'_, IcalServerState>) -> Result<IcalServerStatus, String> {
+  Ok(match state.handle.lock().unwrap().as_ref() {
+    Some(h) => h.status.lock().unwrap().clone(),
+    None => IcalServerStatus::default(),
+  })
+}
+
+/// Returns the apartment's feed token, creating it on first use. `rotate`
+/// replaces it, which cuts off every channel manager using the old URL.
+#[tauri::command]
+pub fn ical_server_feed_token(workspace_path: String, apartment_id: String, rotate: Option<bool>) -> Result<IcalFeedInfo, String> {
+  if !is_valid_id(&apartment_id) {
+    return Err("Invalid apartment id".to_string());
+  }
+  let root = workspace_root(&workspace_path)?;
+  let mut file = read_tokens(&root);
+  let needs_new = rotate.unwrap_or(false) || !file.feeds.contains_key(&apartment_id);
+  if needs_new {
+    file.feeds.insert(apartment_id.clone(), FeedTokenEntry { token: new_token(), created_at: chrono::Utc::now().timestamp_millis() });
+    write_tokens(&root, &mut file)?;
+  }
+  let entry = file.feeds.get(&apartment_id).cloned().unwrap_or_default();
+  Ok(IcalFeedInfo { path: feed_path(&apartment_id, &entry.token), apartment_id, token: entry.token, created_at: entry.created_at })
+}
+
+#[tauri::command]
+pub fn ical_server_feeds(workspace_path: String) -> Result<Vec<IcalFeedInfo>, String> {
+  let file = read_tokens(&workspace_root(&workspace_path)?);
+  Ok(
+    file
+      .feeds
+      .into_iter()
+      .map(|(id, e)| IcalFeedInfo { path: feed_path(&id, &e.token), apartment_id: id, token: e.token, created_at: e.created_at })
+      .collect(),
+  )
+}
+
+/// Stops publishing an apartment's feed.
+#[tauri::command]
+pub fn ical_server_revoke_feed(workspace_path: String, apartment_id: String) -> Result<(), String> {
+  let root = workspace_root(&workspace_path)?;
+  let mut file = read_tokens(&root);
+  if file.feeds.remove(&apartment_id).is_some() {
+    write_tokens(&root, &mut file)?;
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn fold_line_never_splits_a_utf8_sequence() {
+    let line = format!("{}é€{}", "X".repeat(73), "Y".repeat(80));
+    let mut out = String::new();
+    fold_line(&line, &mut out);
+    let physical: Vec<&str> = out.strip_suffix("\r\n").unwrap().split("\r\n").collect();
+    assert!(physical.iter().all(|l| l.len() <= 75), "{physical:?}");
+    // 73 + "é" (2 bytes) fits, "€" (3 bytes) would not.
+    assert_eq!(physical[0], format!("{}é", "X".repeat(73)));
+    assert!(physical[1].starts_with(" €"));
+    assert_eq!(out.replace("\r\n ", "").trim_end(), line);
+
+    let mut short = String::new();
+    fold_line("END:VCALENDAR", &mut short);
+    assert_eq!(short, "END:VCALENDAR\r\n");
+  }
+
+  #[test]
+  fn escape_text_escapes_rfc_5545_specials() {
+    assert_eq!(escape_text("a;b,c\\d\ne"), "a\\;b\\,c\\\\d\\ne");
+    assert_eq!(escape_text("Unit Feed Casa"), "Unit Feed Casa");
+  }
+
+  // Mirrors tests/iCalGenerator.test.ts.
+  #[test]
+  fn booking_uid_matches_ical_generator() {
+    assert_eq!(booking_uid("b-ota", Some("Booking"), Some("abc123-booking-uid")), "abc123-booking-uid");
+    assert_eq!(booking_uid("b-imp", Some("Booking"), Some("IMP-669f")), "rp-b-imp");
+    assert_eq!(booking_uid("b-rp", Some("Booking"), Some("rp-other")), "rp-b-rp");
+    assert_eq!(booking_uid("b-none", Some("Booking"), None), "rp-b-none");
+    assert_eq!(booking_uid("b-empty", None, Some("")), "rp-b-empty");
+    assert_eq!(booking_uid("b-direct", Some("DIRECT_WEB"), Some("some-uid")), "rp-web-b-direct");
+    assert_eq!(booking_uid("b-checkout", Some("WEB_CHECKOUT"), None), "rp-web-b-checkout");
+  }
+
+  fn workspace() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    let (_wjson, db, _backups, _media) = super::super::workspace_paths(dir.path());
+    let conn = rusqlite::Connection::open(&db).unwrap();
+    let check_in = chrono::Utc::now().date_naive() + chrono::Duration::days(10);
+    let check_out = check_in + chrono::Duration::days(3);
+    conn
+      .execute_batch(&format!(
+        "CREATE TABLE apartments (id TEXT PRIMARY KEY, name TEXT);
+         CREATE TABLE bookings (id TEXT PRIMARY KEY, apartment_id TEXT, check_in TEXT, check_out TEXT, status TEXT, source TEXT, ical_uid TEXT);
+         INSERT INTO apartments VALUES ('apt-1', 'Casa'), ('apt-2', 'Piso');
+         INSERT INTO bookings VALUES ('b-1', 'apt-1', '{check_in}', '{check_out}', 'confirmed', 'Booking', NULL);"
+      ))
+      .unwrap();
+    dir
+  }
+
+  /// Sends one GET and returns the status code, the ETag and the body.
+  fn get(port: u16, path: &str, if_none_match: Option<&str>) -> (u16, Option<String>, String) {
+    use std::io::{Read, Write};
+    let mut stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+    let extra = if_none_match.map(|e| format!("If-None-Match: {e}\r\n")).unwrap_or_default();
+    write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n{extra}Connection: close\r\n\r\n").unwrap();
+    let mut raw = String::new();
+    stream.read_to_string(&mut raw).unwrap();
+    let (head, body) = raw.split_once("\r\n\r\n").unwrap();
+    let code = head.split(' ').nth(1).unwrap().parse().unwrap();
+    let etag = head.lines().find_map(|l| l.strip_prefix("ETag: ").or_else(|| l.strip_prefix("etag: "))).map(String::from);
+    (code, etag, body.to_string())
+  }
+
+  #[test]
+  fn feeds_need_the_apartments_own_token_and_honour_etags() {
+    let dir = workspace();
+    let ws = dir.path().to_string_lossy().to_string();
+    let token = ical_server_feed_token(ws.clone(), "apt-1".to_string(), None).unwrap().token;
+    let other = ical_server_feed_token(ws.clone(), "apt-2".to_string(), None).unwrap().token;
+    let state = IcalServerState::default();
+    let port = state.start(ws, Some(0), false).unwrap().port.unwrap();
+
+    assert_eq!(get(port, "/ical/apt-1.ics", None).0, 404);
+    assert_eq!(get(port, "/ical/apt-1.ics?token=", None).0, 404);
+    assert_eq!(get(port, &format!("/ical/apt-1.ics?token={}", "0".repeat(token.len())), None).0, 404);
+    assert_eq!(get(port, &format!("/ical/apt-1.ics?token={other}"), None).0, 404);
+    assert_eq!(get(port, &format!("/ical/apt-9.ics?token={token}"), None).0, 404);
+
+    let (code, etag, body) = get(port, &format!("/ical/apt-1.ics?token={token}"), None);
+    assert_eq!(code, 200);
+    assert!(body.contains("UID:rp-b-1@rentikpro.app\r\n"), "{body}");
+    assert!(body.contains("X-WR-CALNAME:Unit Feed Casa\r\n"));
+    let etag = etag.unwrap();
+
+    let (code, again, body) = get(port, &format!("/ical/apt-1.ics?token={token}"), Some(&etag));
+    assert_eq!((code, again.as_deref(), body.as_str()), (304, Some(etag.as_str()), ""));
+    assert_eq!(get(port, &format!("/ical/apt-1.ics?token={token}"), Some("\"stale\"")).0, 200);
+    state.stop_current();
+  }
+
+  #[test]
+  fn restart_on_a_taken_port_keeps_the_running_server() {
+    let dir = workspace();
+    let ws = dir.path().to_string_lossy().to_string();
+    let token = ical_server_feed_token(ws.clone(), "apt-1".to_string(), None).unwrap().token;
+    let state = IcalServerState::default();
+    let port = state.start(ws.clone(), Some(0), false).unwrap().port.unwrap();
+
+    let taken = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let taken_port = taken.local_addr().unwrap().port();
+    assert!(state.start(ws.clone(), Some(taken_port), false).is_err());
+    assert_eq!(get(port, &format!("/ical/apt-1.ics?token={token}"), None).0, 200);
+
+    // Its own port can be reused: the old server steps aside for it.
+    assert_eq!(state.start(ws, Some(port), false).unwrap().port, Some(port));
+    assert_eq!(get(port, &format!("/ical/apt-1.ics?token={token}"), None).0, 200);
+    state.stop_current();
+  }
+}
@@ -0,0 +1,380 @@
+// Inbound mail over IMAP for `EmailSyncService.syncInbound`.
+//
+// The webview cannot open raw sockets, so Rust fetches and parses; the frontend
+// keeps the (UIDVALIDITY, last UID) cursor next to the account and passes it
+// back on the next call. A cursor is only advanced by the caller after the
+// messages were stored, so nothing is lost if the app quits in between.
+//
+// Plain-text IMAP is not supported: either implicit TLS (993) or STARTTLS.
+
+use futures_util::TryStreamExt;
+use tauri::Emitter;
+
+pub const NEW_MAIL_EVENT: &str = "imap:new-mail";
+pub const IDLE_ERROR_EVENT: &str = "imap:idle-error";
+const DEFAULT_MAX_MESSAGES: usize = 50;
+const DEFAULT_INITIAL_DAYS: i64 = 30;
+// RFC 2177 asks clients to re-issue IDLE at least every 29 minutes.
+const IDLE_REFRESH_SECS: u64 = 29 * 60;
+const RECONNECT_BASE_SECS: u64 = 30;
+const RECONNECT_MAX_SECS: u64 = 15 * 60;
+
+type ImapSession = async_imap::Session<tokio_rustls::client::TlsStream<tokio::net::TcpStream>>;
+
+fn default_mailbox() -> String {
+  "INBOX".to_string()
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct ImapCursor {
+  pub uid_validity: u32,
+  pub last_uid: u32,
+}
+
+#[derive(serde::Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ImapArgs {
+  /// Communication account id; also keys the password in the secret store.
+  pub account_id: String,
+  #[serde(default)]
+  pub workspace_id: Option<String>,
+  pub host: String,
+  #[serde(default)]
+  pub port: Option<u16>,
+  /// "tls" (default) or "starttls".
+  #[serde(default)]
+  pub security: Option<String>,
+  pub user: String,
+  #[serde(default)]
+  pub pass: Option<String>,
+  #[serde(default = "default_mailbox")]
+  pub mailbox: String,
+  #[serde(default)]
+  pub cursor: Option<ImapCursor>,
+  #[serde(default)]
+  pub max_messages: Option<usize>,
+  /// Without a cursor, only mail from the last N days is fetched.
+  #[serde(default)]
+  pub initial_days: Option<i64>,
+}
+
+#[derive(serde::Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ImapMessage {
+  uid: u32,
+  internal_date: Option<i64>,
+  #[serde(flatten)]
+  email: super::email_ingest::ParsedEmail,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImapFetchResult {
+  cursor: ImapCursor,
+  /// The server reset UIDs; the old cursor was ignored.
+  uid_validity_changed: bool,
+  has_more: bool,
+  messages: Vec<ImapMessage>,
+  /// UIDs that were missing from FETCH (expunged in between) or could not be
+  /// parsed. Neither gets better on a retry, so the cursor moves past them
+  /// and they are only reported here.
+  skipped_uids: Vec<u32>,
+}
+
+#[derive(serde::Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ImapIdleEvent {
+  account_id: String,
+  mailbox: String,
+  error: Option<String>,
+  retry_in_secs: Option<u64>,
+}
+
+fn secret_account(account_id: &str) -> String {
+  format!("imap:{account_id}")
+}
+
+fn resolve_password(args: &ImapArgs) -> Result<String, String> {
+  if let Some(pass) = args.pass.clone().filter(|p| !p.is_empty()) {
+    return Ok(pass);
+  }
+  let workspace_id = args
+    .workspace_id
+    .as_deref()
+    .filter(|id| !id.trim().is_empty())
+    .ok_or_else(|| "IMAP password is not set".to_string())?;
+  super::secrets::get_secret(workspace_id, &secret_account(&args.account_id))?.ok_or_else(|| "IMAP password is not set".to_string())
+}
+
+fn tls_connector() -> Result<tokio_rustls::TlsConnector, String> {
+  use tokio_rustls::rustls;
+  let roots = rustls::RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() };
+  let config = rustls::ClientConfig::builder_with_provider(std::sync::Arc::new(rustls::crypto::ring::default_provider()))
+    .with_safe_default_protocol_versions()
+    .map_err(|e| format!("TLS setup failed: {e}"))?
+    .with_root_certificates(roots)
+    .with_no_client_auth();
+  Ok(tokio_rustls::TlsConnector::from(std::sync::Arc::new(config)))
+}
+
+async fn connect(args: &ImapArgs) -> Result<ImapSession, String> {
+  let host = args.host.trim();
+  if host.is_empty() {
+    return Err("IMAP host is required".to_string());
+  }
+  let security = args.security.as_deref().unwrap_or("tls").to_ascii_lowercase();
+  let default_port = match security.as_str() {
+    "tls" | "ssl" => 993,
+    "starttls" => 143,
+    other => return Err(format!("Unknown IMAP security mode: {other}")),
+  };
+  let port = args.port.unwrap_or(default_port);
+  let server_name = tokio_rustls::rustls::pki_types::ServerName::try_from(host.to_string()).map_err(|e| format!("Invalid IMAP host: {e}"))?;
+
+  let tcp = tokio::time::timeout(std::time::Duration::from_secs(20), tokio::net::TcpStream::connect((host, port)))
+    .await
+    .map_err(|_| format!("IMAP connect to {host}:{port} timed out"))?
+    .map_err(|e| format!("IMAP connect to {host}:{port} failed: {e}"))?;
+
+  let tcp = if security == "starttls" {
+    let mut plain = async_imap::Client::new(tcp);
+    plain.read_response().await.ok_or_else(|| "IMAP server closed the connection".to_string())?.map_err(|e| format!("IMAP greeting failed: {e}"))?;
+    plain
+      .run_command_and_check_ok("STARTTLS", None)
+      .await
+      .map_err(|e| format!("IMAP STARTTLS failed: {e}"))?;
+    plain.into_inner()
+  } else {
+    tcp
+  };
+
+  let tls = tls_connector()?
+    .connect(server_name, tcp)
+    .await
+    .map_err(|e| format!("IMAP TLS handshake failed: {e}"))?;
+  let mut client = async_imap::Client::new(tls);
+  if security != "starttls" {
+    client.read_response().await.ok_or_else(|| "IMAP server closed the connection".to_string())?.map_err(|e| format!("IMAP greeting failed: {e}"))?;
+  }
+
+  let pass = resolve_password(args)?;
+  client
+    .login(args.user.trim(), pass)
+    .await
+    .map_err(|(e, _)| format!("IMAP login failed: {e}"))
+}
+
+/// IMAP SEARCH date format, e.g. 01-Feb-2026.
+fn imap_date(ts: chrono::DateTime<chrono::Utc>) -> String {
+  ts.format("%d-%b-%Y").to_string()
+}
+
+/// Where the cursor moves after fetching `uids` (sorted), and which of them
+/// were skipped. A UID missing from FETCH or failing to parse would fail the
+/// same way on every call, so it is reported once and passed rather than
+/// holding the cursor (and every later message) back.
+fn advance_cursor(last_uid: u32, uids: &[u32], processed: &std::collections::HashSet<u32>) -> (u32, Vec<u32>) {
+  let skipped = uids.iter().filter(|u| !processed.contains(u)).copied().collect();
+  (uids.last().copied().unwrap_or(last_uid), skipped)
+}
+
+async fn fetch_new(args: &ImapArgs) -> Result<ImapFetchResult, String> {
+  let mut session = connect(args).await?;
+  let mailbox = session
+    .examine(&args.mailbox)
+    .await
+    .map_err(|e| format!("IMAP EXAMINE {} failed: {e}", args.mailbox))?;
+  let uid_validity = mailbox.uid_validity.unwrap_or(0);
+
+  let cursor = args.cursor.filter(|c| c.uid_validity == uid_validity);
+  let uid_validity_changed = args.cursor.is_some() && cursor.is_none();
+  let query = match cursor {
+    Some(c) => format!("UID {}:*", c.last_uid.saturating_add(1)),
+    None => {
+      let days = args.initial_days.unwrap_or(DEFAULT_INITIAL_DAYS).max(1);
+      format!("SINCE {}", imap_date(chrono::Utc::now() - chrono::Duration::days(days)))
+    }
+  };
+  let last_uid = cursor.map(|c| c.last_uid).unwrap_or(0);
+
+  let found = session.uid_search(&query).await.map_err(|e| format!("IMAP SEARCH failed: {e}"))?;
+  // "n:*" always matches the newest message, even when its UID is below n.
+  let mut uids: Vec<u32> = found.into_iter().filter(|u| *u > last_uid).collect();
+  uids.sort_unstable();
+  let max = args.max_messages.unwrap_or(DEFAULT_MAX_MESSAGES).max(1);
+  let has_more = uids.len() > max;
+  uids.truncate(max);
+
+  let mut messages = vec![];
+  if !uids.is_empty() {
+    let set = uids.iter().map(|u| u.to_string()).collect::<Vec<_>>().join(",");
+    let fetches: Vec<async_imap::types::Fetch> = session
+      .uid_fetch(&set, "(UID INTERNALDATE BODY.PEEK[])")
+      .await
+      .map_err(|e| format!("IMAP FETCH failed: {e}"))?
+      .try_collect()
+      .await
+      .map_err(|e| format!("IMAP FETCH failed: {e}"))?;
+    for fetch in fetches {
+      let uid = match fetch.uid {
+        Some(uid) => uid,
+        None => continue,
+      };
+      if let Some(Ok(email)) = fetch.body().map(super::email_ingest::parse_email) {
+        messages.push(ImapMessage { uid, internal_date: fetch.internal_date().map(|d| d.timestamp_millis()), email });
+      }
+    }
+    messages.sort_by_key(|m| m.uid);
+  }
+
+  let _ = session.logout().await;
+  let processed: std::collections::HashSet<u32> = messages.iter().map(|m| m.uid).collect();
+  let (next_uid, skipped_uids) = advance_cursor(last_uid, &uids, &processed);
+  Ok(ImapFetchResult {
+    cursor: ImapCursor { uid_validity, last_uid: next_uid },
+    uid_validity_changed,
+    has_more,
+    messages,
+    skipped_uids,
+  })
+}
+
+/// Fetches messages newer than `cursor` (or from the last `initialDays`).
+#[tauri::command]
+pub async fn imap_fetch_new(args: ImapArgs) -> Result<ImapFetchResult, String> {
+  fetch_new(&args).await
+}
+
+/// Stores the IMAP password of an account in the secret store.
+#[tauri::command]
+pub fn imap_set_password(workspace_id: String, account_id: String, pass: String) -> Result<(), String> {
+  super::secrets::set_secret(&workspace_id, &secret_account(&account_id), &pass)
+}
+
+struct IdleHandle {
+  stop: std::sync::Arc<tokio::sync::Notify>,
+  task: tauri::async_runtime::JoinHandle<()>,
+}
+
+#[derive(Default)]
+pub struct ImapIdleState {
+  handles: std::sync::Mutex<std::collections::HashMap<String, IdleHandle>>,
+}
+
+impl ImapIdleState {
+  fn stop(&self, account_id: &str) {
+    if let Some(h) = self.handles.lock().unwrap().remove(account_id) {
+      h.stop.notify_one();
+      h.task.abort();
+    }
+  }
+}
+
+/// One IDLE session until an error; returns Ok(()) only when stopped.
+async fn idle_session(app: &tauri::AppHandle, args: &ImapArgs, stop: &tokio::sync::Notify) -> Result<(), String> {
+  let mut session = connect(args).await?;
+  session
+    .select(&args.mailbox)
+    .await
+    .map_err(|e| format!("IMAP SELECT {} failed: {e}", args.mailbox))?;
+
+  loop {
+    let mut idle = session.idle();
+    idle.init().await.map_err(|e| format!("IMAP IDLE failed: {e}"))?;
+    let new_data = {
+      let (wait, _interrupt) = idle.wait_with_timeout(std::time::Duration::from_secs(IDLE_REFRESH_SECS));
+      tokio::select! {
+        _ = stop.notified() => return Ok(()),
+        res = wait => matches!(res.map_err(|e| format!("IMAP IDLE failed: {e}"))?, async_imap::extensions::idle::IdleResponse::NewData(_)),
+      }
+    };
+    session = idle.done().await.map_err(|e| format!("IMAP IDLE failed: {e}"))?;
+    if new_data {
+      let _ = app.emit(
+        NEW_MAIL_EVENT,
+        ImapIdleEvent { account_id: args.account_id.clone(), mailbox: args.mailbox.clone(), error: None, retry_in_secs: None },
+      );
+    }
+  }
+}
+
+async fn idle_loop(app: tauri::AppHandle, args: ImapArgs, stop: std::sync::Arc<tokio::sync::Notify>) {
+  let mut failures: u32 = 0;
+  loop {
+    let started = std::time::Instant::now();
+    let err = match idle_session(&app, &args, &stop).await {
+      Ok(()) => return,
+      Err(e) => e,
+    };
+    // A session that stayed up for a while was healthy; start backoff over.
+    if started.elapsed().as_secs() > RECONNECT_MAX_SECS {
+      failures = 0;
+    }
+    failures = failures.saturating_add(1);
+    let wait = RECONNECT_BASE_SECS.saturating_mul(1u64 << failures.saturating_sub(1).min(16)).min(RECONNECT_MAX_SECS);
+    let _ = app.emit(
+      IDLE_ERROR_EVENT,
+      ImapIdleEvent { account_id: args.account_id.clone(), mailbox: args.mailbox.clone(), error: Some(err), retry_in_secs: Some(wait) },
+    );
+    tokio::select! {
+      _ = stop.notified() => return,
+      _ = tokio::time::sleep(std::time::Duration::from_secs(wait)) => {}
+    }
+  }
+}
+
+/// Keeps an IDLE connection open and emits `imap:new-mail` when the mailbox
+/// changes; the frontend then calls `imap_fetch_new`.
+#[tauri::command]
+pub fn imap_idle_start(app: tauri::AppHandle, state: tauri::State<'_, ImapIdleState>, args: ImapArgs) -> Result<(), String> {
+  if args.account_id.trim().is_empty() {
+    return Err("Missing account id".to_string());
+  }
+  state.stop(&args.account_id);
+  let stop = std::sync::Arc::new(tokio::sync::Notify::new());
+  let account_id = args.account_id.clone();
+  let task = tauri::async_runtime::spawn(idle_loop(app, args, stop.clone()));
+  state.handles.lock().unwrap().insert(account_id, IdleHandle { stop, task });
+  Ok(())
+}
+
+#[tauri::command]
+pub fn imap_idle_stop(state: tauri::State<'_, ImapIdleState>, account_id: String) -> Result<(), String> {
+  state.stop(&account_id);
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn cursor_passes_unprocessed_uids_and_reports_them() {
+    let processed = [10, 11, 13].into_iter().collect();
+    assert_eq!(advance_cursor(9, &[10, 11, 12, 13], &processed), (13, vec![12]));
+  }
+
+  #[test]
+  fn gap_uid_that_never_succeeds_does_not_pin_the_cursor() {
+    // UID 12 is never returned by FETCH (expunged, or unparseable every time).
+    let mut last_uid = 9;
+    let mut reported = vec![];
+    for batch in [vec![10, 11, 12], vec![13, 14]] {
+      let uids: Vec<u32> = batch.into_iter().filter(|u| *u > last_uid).collect();
+      let processed = uids.iter().copied().filter(|u| *u != 12).collect();
+      let (next, skipped) = advance_cursor(last_uid, &uids, &processed);
+      last_uid = next;
+      reported.extend(skipped);
+    }
+    assert_eq!(last_uid, 14);
+    assert_eq!(reported, vec![12]);
+  }
+
+  #[test]
+  fn cursor_moves_to_last_when_all_processed() {
+    let processed = [10, 11].into_iter().collect();
+    assert_eq!(advance_cursor(9, &[10, 11], &processed), (11, vec![]));
+    assert_eq!(advance_cursor(9, &[], &processed), (9, vec![]));
+  }
+}
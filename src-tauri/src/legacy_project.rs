@@ -0,0 +1,179 @@
+// In-place conversion of legacy folder projects (`project.json` + `db.sqlite`)
+// into the workspace layout (`workspace.json` + `database.sqlite`).
+//
+// The sync code keeps translating between both layouts
+// (`detect_local_sync_context`, `adapt_remote_workspace_json_for_local`);
+// once every folder project has been converted that branch can go.
+
+const LEGACY_DIR: &str = "legacy-project";
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrateProjectResult {
+  workspace_json_path: String,
+  db_path: String,
+  /// Name of the ZIP in `backups/` holding the original files.
+  backup: String,
+  sync_state_carried: bool,
+  schema_version: u32,
+}
+
+fn backup_legacy_files(root: &std::path::Path, files: &[(&str, std::path::PathBuf)]) -> Result<String, String> {
+  let (_wjson, _db, backups, _media) = super::workspace_paths(root);
+  super::ensure_dir(&backups)?;
+  let filename = super::timestamp_backup_name("autobackup_before_workspace_conversion_", "zip");
+  let backup_path = backups.join(&filename);
+
+  let f = std::fs::File::create(&backup_path).map_err(|e| format!("Failed creating backup {}: {e}", backup_path.display()))?;
+  let mut zip = zip::ZipWriter::new(f);
+  let opts = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+  use std::io::Write;
+  for (name, path) in files {
+    if !path.exists() {
+      continue;
+    }
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed reading {}: {e}", path.display()))?;
+    zip.start_file(*name, opts).map_err(|e| format!("ZIP start {name} failed: {e}"))?;
+    zip.write_all(&bytes).map_err(|e| format!("ZIP write {name} failed: {e}"))?;
+  }
+  let metadata = serde_json::json!({
+    "app": "RentikPro",
+    "format": "rentikpro-folder-project-backup",
+    "createdAt": chrono::Utc::now().timestamp_millis(),
+    "dbFile": "db.sqlite",
+  });
+  let metadata_bytes = serde_json::to_vec_pretty(&metadata).map_err(|e| format!("Metadata encode failed: {e}"))?;
+  zip.start_file("metadata.json", opts).map_err(|e| format!("ZIP start metadata.json failed: {e}"))?;
+  zip.write_all(&metadata_bytes).map_err(|e| format!("ZIP write metadata.json failed: {e}"))?;
+  zip.finish().map_err(|e| format!("ZIP finalize failed: {e}"))?;
+  Ok(filename)
+}
+
+/// `project.json` with the fields `workspace.json` needs. The id is kept, since
+/// it is the WebDAV slug other devices already sync against.
+fn workspace_json_from_project(project: &serde_json::Value, root: &std::path::Path) -> serde_json::Value {
+  let mut meta = super::default_workspace_json();
+  let now = chrono::Utc::now().timestamp_millis();
+  let obj = meta.as_object_mut().unwrap();
+  if let Some(project) = project.as_object() {
+    for (k, v) in project {
+      obj.insert(k.clone(), v.clone());
+    }
+  }
+  obj.entry("name".to_string()).or_insert_with(|| {
+    serde_json::json!(root.file_name().and_then(|s| s.to_str()).unwrap_or("RentikPro Workspace"))
+  });
+  let keep_id = obj.get("id").and_then(|v| v.as_str()).map(|s| !s.trim().is_empty()).unwrap_or(false);
+  if !keep_id {
+    obj.insert("id".to_string(), serde_json::json!(format!("ws_{now}")));
+  }
+  obj.insert("kind".to_string(), serde_json::json!("workspace"));
+  obj.insert("dbFile".to_string(), serde_json::json!(super::WORKSPACE_DB_NAME));
+  obj.insert("updatedAt".to_string(), serde_json::json!(now));
+  obj.insert("convertedFrom".to_string(), serde_json::json!({ "kind": "folder-project", "at": now }));
+  meta
+}
+
+/// Points `sync/state.json` at the workspace layout. Schema migrations rewrite
+/// the database, so when the project was unchanged since its last sync the
+/// recorded sha256 moves to the migrated file; otherwise the next sync would
+/// see a local change that is only the migration. A project with unsynced
+/// edits keeps its old hash and still pushes them.
+fn carry_sync_state(root: &std::path::Path, converted_sha: &str, migrated_sha: &str) -> Result<bool, String> {
+  let path = root.join("sync").join("state.json");
+  let mut state: super::SyncStateV1 = match super::read_json_file(&path) {
+    Some(s) => s,
+    None => return Ok(false),
+  };
+  if state.sha256 == converted_sha {
+    state.sha256 = migrated_sha.to_string();
+  }
+  state.workspace_kind = "workspace".to_string();
+  state.db_file = super::WORKSPACE_DB_NAME.to_string();
+  state.metadata_file = super::WORKSPACE_JSON_NAME.to_string();
+  let txt = serde_json::to_vec_pretty(&state).map_err(|e| format!("Sync state encode failed: {e}"))?;
+  super::atomic_write(&path, &txt)?;
+  Ok(true)
+}
+
+fn rollback(root: &std::path::Path, state_before: Option<Vec<u8>>) {
+  let (wjson, db, _backups, _media) = super::workspace_paths(root);
+  let _ = std::fs::remove_file(wjson);
+  let _ = std::fs::remove_file(db);
+  if let Some(bytes) = state_before {
+    let _ = super::atomic_write(&root.join("sync").join("state.json"), &bytes);
+  }
+}
+
+/// Converts the folder project at `path` into a workspace in place. The
+/// original files are zipped into `backups/` first and then moved to
+/// `sync/legacy-project/` once the new layout opens.
+#[tauri::command]
+pub fn migrate_project_to_workspace(path: String) -> Result<MigrateProjectResult, String> {
+  let root = std::path::PathBuf::from(&path);
+  if !root.is_dir() {
+    return Err("Project folder does not exist".to_string());
+  }
+  let (pj, legacy_db) = super::project_paths(&root);
+  let (wjson, db, backups, media) = super::workspace_paths(&root);
+  if wjson.exists() || db.exists() {
+    return Err("Folder already contains a workspace".to_string());
+  }
+  if !pj.exists() || !legacy_db.exists() {
+    return Err("Not a folder project: missing project.json or db.sqlite".to_string());
+  }
+
+  let db_bytes = std::fs::read(&legacy_db).map_err(|e| format!("Failed reading db.sqlite: {e}"))?;
+  if !super::is_workspace_db_bytes(&db_bytes) {
+    return Err("db.sqlite is not a valid SQLite database".to_string());
+  }
+  let project: serde_json::Value = super::read_json_file(&pj).ok_or_else(|| "project.json is not valid JSON".to_string())?;
+
+  let state_path = root.join("sync").join("state.json");
+  let backup = backup_legacy_files(
+    &root,
+    &[("project.json", pj.clone()), ("db.sqlite", legacy_db.clone()), ("sync/state.json", state_path.clone())],
+  )?;
+  super::ensure_dir(&backups)?;
+  super::ensure_dir(&media)?;
+
+  let state_before = std::fs::read(&state_path).ok();
+  let meta = workspace_json_from_project(&project, &root);
+  let converted = (|| {
+    super::atomic_write(&db, &db_bytes)?;
+    let txt = serde_json::to_vec_pretty(&meta).map_err(|e| format!("JSON encode failed: {e}"))?;
+    super::atomic_write(&wjson, &txt)?;
+    // Same checks and schema migrations `open_workspace` runs, without
+    // registering the folder or watching it; the UI opens it afterwards.
+    let plain = super::plain_db_bytes(&root, db_bytes.clone())?;
+    let (_plain, migration) = super::workspace_migrations::migrate_workspace(&root, plain)?;
+    let on_disk = std::fs::read(&db).map_err(|e| format!("Failed reading {}: {e}", db.display()))?;
+    let carried = carry_sync_state(&root, &super::sha256_hex(&db_bytes), &super::sha256_hex(&on_disk))?;
+    Ok::<_, String>((carried, migration))
+  })();
+  let (sync_state_carried, migration) = match converted {
+    Ok(v) => v,
+    Err(e) => {
+      rollback(&root, state_before);
+      return Err(format!("Conversion failed, folder project left unchanged: {e}"));
+    }
+  };
+
+  // Out of the way, so nothing mistakes the folder for a project again.
+  let legacy_dir = root.join("sync").join(LEGACY_DIR);
+  super::ensure_dir(&legacy_dir)?;
+  for from in [&pj, &legacy_db] {
+    if let Some(name) = from.file_name() {
+      std::fs::rename(from, legacy_dir.join(name)).map_err(|e| format!("Failed moving {} aside: {e}", from.display()))?;
+    }
+  }
+
+  Ok(MigrateProjectResult {
+    workspace_json_path: wjson.to_string_lossy().to_string(),
+    db_path: db.to_string_lossy().to_string(),
+    backup,
+    sync_state_carried,
+    schema_version: migration.schema_version,
+  })
+}
@@ -10,6 +10,30 @@ use chrono::{Datelike, Timelike};
 
 use tauri::Manager;
 
+mod db_crypto;
+mod email_ingest;
+mod email_outbox;
+mod ical;
+mod ical_feeds;
+mod ical_server;
+mod imap;
+mod legacy_project;
+mod media_sync;
+mod secrets;
+mod smtp;
+mod sync_audit;
+mod sync_devices;
+mod sync_scheduler;
+mod webdav_auth;
+mod webdav_probe;
+mod workspace_db;
+mod workspace_doctor;
+mod workspace_migrations;
+mod workspace_move;
+mod workspace_recovery;
+mod workspace_registry;
+mod workspace_watcher;
+
 #[tauri::command]
 fn open_devtools(window: tauri::WebviewWindow) {
   #[cfg(debug_assertions)]
@@ -32,34 +56,16 @@ struct IcalFetchResult {
   etag: Option<String>,
   last_modified: Option<String>,
   content_type: Option<String>,
+  /// URL that finally answered, after redirects.
+  final_url: String,
+  redirects: Vec<ical_feeds::RedirectHop>,
+  attempts: u32,
+  charset: String,
 }
 
 #[tauri::command]
-async fn fetch_ical_url(url: String, etag: Option<String>, last_modified: Option<String>) -> Result<IcalFetchResult, String> {
-  let client = reqwest::Client::builder()
-    .timeout(std::time::Duration::from_secs(20))
-    .user_agent("RentikPro/2 (calendar-sync)")
-    .build()
-    .map_err(|e| e.to_string())?;
-
-  let mut req = client.get(&url)
-    .header("Accept", "text/calendar, text/plain, */*");
-
-  if let Some(e) = etag {
-    req = req.header("If-None-Match", e);
-  }
-  if let Some(lm) = last_modified {
-    req = req.header("If-Modified-Since", lm);
-  }
-
-  let resp = req.send().await.map_err(|e| format!("Fetch error: {e}"))?;
-  let status = resp.status().as_u16();
-  let etag_out = resp.headers().get("etag").and_then(|v| v.to_str().ok()).map(String::from);
-  let lm_out = resp.headers().get("last-modified").and_then(|v| v.to_str().ok()).map(String::from);
-  let ct_out = resp.headers().get("content-type").and_then(|v| v.to_str().ok()).map(String::from);
-  let body = resp.text().await.map_err(|e| format!("Read error: {e}"))?;
-
-  Ok(IcalFetchResult { status, body, etag: etag_out, last_modified: lm_out, content_type: ct_out })
+async fn fetch_ical_url(url: String, etag: Option<String>, last_modified: Option<String>, options: Option<ical_feeds::FetchOptions>) -> Result<IcalFetchResult, String> {
+  ical_feeds::fetch(&url, etag, last_modified, &options.unwrap_or_default()).await
 }
 
 fn main() {
@@ -72,6 +78,10 @@ fn main() {
     .plugin(tauri_plugin_dialog::init())
     .plugin(tauri_plugin_fs::init())
     .plugin(tauri_plugin_shell::init())
+    .manage(sync_scheduler::SyncSchedulerState::default())
+    .manage(email_outbox::OutboxState::default())
+    .manage(imap::ImapIdleState::default())
+    .manage(ical_server::IcalServerState::default())
     .setup(move |app| {
       #[cfg(all(debug_assertions, not(mobile)))]
       {
@@ -88,10 +98,22 @@ fn main() {
     .invoke_handler(tauri::generate_handler![
       open_devtools,
       fetch_ical_url,
+      ical::fetch_and_parse_ical,
+      ical::parse_ical_text,
+      ical_feeds::fetch_ical_batch,
+      ical_feeds::ical_cache_forget,
+      ical_feeds::ical_cache_ack,
+      ical_server::ical_server_start,
+      ical_server::ical_server_stop,
+      ical_server::ical_server_status,
+      ical_server::ical_server_feed_token,
+      ical_server::ical_server_feeds,
+      ical_server::ical_server_revoke_feed,
       pick_project_folder,
       validate_project_folder,
       open_project_folder,
       write_project_folder,
+      legacy_project::migrate_project_to_workspace,
       setup_workspace,
       open_workspace,
       save_workspace,
@@ -99,7 +121,55 @@ fn main() {
       list_backups,
       restore_backup,
       reset_workspace,
-      webdav_sync
+      workspace_doctor::workspace_doctor,
+      workspace_doctor::workspace_doctor_fix,
+      workspace_recovery::workspace_recovery_scan,
+      workspace_recovery::workspace_recovery_resolve,
+      workspace_move::move_workspace,
+      workspace_registry::recent_workspaces_list,
+      workspace_registry::recent_workspaces_add,
+      workspace_registry::recent_workspaces_remove,
+      workspace_registry::recent_workspaces_pin,
+      workspace_registry::recent_workspaces_relocate,
+      workspace_watcher::workspace_watch_stop,
+      webdav_sync,
+      sync_devices::webdav_list_devices,
+      sync_devices::webdav_rename_device,
+      sync_devices::webdav_revoke_device_lock,
+      sync_audit::webdav_break_lock,
+      webdav_probe::webdav_test_connection,
+      media_sync::webdav_sync_media,
+      webdav_auth::webdav_set_credentials,
+      webdav_auth::webdav_get_credentials_info,
+      webdav_auth::webdav_clear_credentials,
+      webdav_auth::webdav_login_flow_start,
+      webdav_auth::webdav_login_flow_poll,
+      secrets::secret_set,
+      secrets::secret_get,
+      secrets::secret_delete,
+      secrets::secret_store_backend,
+      db_crypto::workspace_enable_encryption,
+      db_crypto::workspace_disable_encryption,
+      db_crypto::workspace_unlock,
+      db_crypto::workspace_lock,
+      smtp::send_email,
+      smtp::smtp_set_password,
+      email_outbox::outbox_start,
+      email_outbox::outbox_stop,
+      email_outbox::outbox_enqueue,
+      email_outbox::outbox_list,
+      email_outbox::outbox_ack,
+      email_outbox::outbox_retry,
+      email_ingest::import_emails,
+      email_ingest::import_email_attachment,
+      imap::imap_fetch_new,
+      imap::imap_set_password,
+      imap::imap_idle_start,
+      imap::imap_idle_stop,
+      sync_scheduler::sync_scheduler_start,
+      sync_scheduler::sync_scheduler_stop,
+      sync_scheduler::sync_scheduler_status,
+      sync_scheduler::sync_scheduler_trigger
     ])
     .run(tauri::generate_context!())
     .expect("error while running tauri application");
@@ -110,6 +180,38 @@ const WORKSPACE_DB_NAME: &str = "database.sqlite";
 const WORKSPACE_BACKUPS_DIR: &str = "backups";
 const WORKSPACE_MEDIA_DIR: &str = "media";
 const REMOTE_WORKSPACE_FORMAT: &str = "rentikpro.remote-workspace.v1";
+/// `code` of the `save_workspace` error when `expected_sha256` no longer
+/// matches the file on disk.
+const SAVE_CONFLICT_ERROR: &str = "SAVE_CONFLICT";
+
+/// `save_workspace` error: a plain message like every other command, or an
+/// object the UI can act on without parsing text.
+#[derive(serde::Serialize, Debug)]
+#[serde(untagged)]
+enum SaveWorkspaceError {
+  Conflict(SaveConflict),
+  Message(String),
+}
+
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SaveConflict {
+  /// Always `SAVE_CONFLICT`.
+  code: &'static str,
+  expected: String,
+  /// `None` when the file is gone.
+  found: Option<String>,
+}
+
+impl From<String> for SaveWorkspaceError {
+  fn from(e: String) -> Self {
+    SaveWorkspaceError::Message(e)
+  }
+}
+
+// Held while `database.sqlite` is checked and replaced, so a save's hash
+// precondition cannot interleave with a sync writing the same file.
+static DB_WRITE_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
 
 fn is_sqlite_bytes(bytes: &[u8]) -> bool {
   // "SQLite format 3\0"
@@ -120,6 +222,11 @@ fn is_sqlite_bytes(bytes: &[u8]) -> bool {
   bytes[0..16] == MAGIC
 }
 
+/// Plain SQLite or a database encrypted by `db_crypto`.
+fn is_workspace_db_bytes(bytes: &[u8]) -> bool {
+  is_sqlite_bytes(bytes) || db_crypto::is_encrypted_db(bytes)
+}
+
 fn workspace_paths(root: &std::path::Path) -> (std::path::PathBuf, std::path::PathBuf, std::path::PathBuf, std::path::PathBuf) {
   (
     root.join(WORKSPACE_JSON_NAME),
@@ -190,18 +297,52 @@ fn atomic_write(path: &std::path::Path, bytes: &[u8]) -> Result<(), String> {
     .ok_or_else(|| "Invalid path filename".to_string())?;
   // Requirement: <name>.tmp (e.g. database.sqlite.tmp)
   let tmp = parent.join(format!("{filename}.tmp"));
-  std::fs::write(&tmp, bytes).map_err(|e| format!("Failed writing temp {}: {e}", tmp.display()))?;
+  write_synced(&tmp, bytes)?;
+
+  // std::fs::rename replaces the target on Windows too (MOVEFILE_REPLACE_EXISTING),
+  // so there is no window where neither file exists.
+  std::fs::rename(&tmp, path).map_err(|e| format!("Failed renaming temp into {}: {e}", path.display()))?;
+  sync_dir(parent);
+  Ok(())
+}
 
-  // On Windows, rename cannot overwrite an existing file.
-  #[cfg(target_os = "windows")]
+/// Writes a temp file and flushes it to disk, so the rename that follows can
+/// never expose a file whose contents are still in flight.
+fn write_synced(tmp: &std::path::Path, bytes: &[u8]) -> Result<(), String> {
+  use std::io::Write;
+  let mut f = std::fs::File::create(tmp).map_err(|e| format!("Failed creating temp {}: {e}", tmp.display()))?;
+  f.write_all(bytes).map_err(|e| format!("Failed writing temp {}: {e}", tmp.display()))?;
+  f.sync_all().map_err(|e| format!("Failed flushing temp {}: {e}", tmp.display()))
+}
+
+/// Makes a rename in `dir` durable. Best effort: directories cannot be
+/// opened for syncing on Windows, where NTFS journals the rename itself.
+fn sync_dir(dir: &std::path::Path) {
+  #[cfg(unix)]
   {
-    if path.exists() {
-      std::fs::remove_file(path).map_err(|e| format!("Failed removing existing {}: {e}", path.display()))?;
+    if let Ok(d) = std::fs::File::open(dir) {
+      let _ = d.sync_all();
     }
   }
+  #[cfg(not(unix))]
+  {
+    let _ = dir;
+  }
+}
 
-  std::fs::rename(&tmp, path).map_err(|e| format!("Failed renaming temp into {}: {e}", path.display()))?;
-  Ok(())
+fn workspace_id_of(root: &std::path::Path) -> Option<String> {
+  let meta = read_json_file::<serde_json::Value>(&root.join(WORKSPACE_JSON_NAME))?;
+  meta.get("id").and_then(|v| v.as_str()).map(String::from).filter(|s| !s.is_empty())
+}
+
+/// Plain SQLite bytes for the webview, decrypting with the session or keychain
+/// key when the file is encrypted.
+fn plain_db_bytes(root: &std::path::Path, bytes: Vec<u8>) -> Result<Vec<u8>, String> {
+  if !db_crypto::is_encrypted_db(&bytes) {
+    return Ok(bytes);
+  }
+  db_crypto::decrypt_with_available_key(root, workspace_id_of(root).as_deref(), &bytes)
+    .ok_or_else(|| db_crypto::LOCKED_ERROR.to_string())
 }
 
 fn default_workspace_json() -> serde_json::Value {
@@ -222,6 +363,12 @@ struct OpenWorkspaceResult {
   workspace_json_path: String,
   db_path: String,
   backups_dir: String,
+  encrypted: bool,
+  migration: workspace_migrations::MigrationReport,
+  /// Orphaned temp files the user should promote or discard.
+  recovery: Vec<workspace_recovery::OrphanTemp>,
+  /// Hash of `database.sqlite` as it is on disk now.
+  db_sha256: String,
 }
 
 #[tauri::command]
@@ -246,7 +393,7 @@ fn setup_workspace(path: String) -> Result<(), String> {
 }
 
 #[tauri::command]
-fn open_workspace(path: String) -> Result<OpenWorkspaceResult, String> {
+fn open_workspace(app: tauri::AppHandle, path: String, passphrase: Option<String>) -> Result<OpenWorkspaceResult, String> {
   let root = std::path::PathBuf::from(&path);
   if !root.exists() {
     return Err("Workspace folder does not exist".to_string());
@@ -259,6 +406,10 @@ fn open_workspace(path: String) -> Result<OpenWorkspaceResult, String> {
   ensure_dir(&backups)?;
   ensure_dir(&media)?;
 
+  // An interrupted write may have left the only good copy in a .tmp file.
+  if let Some(e) = workspace_recovery::missing_file_error(&root) {
+    return Err(e);
+  }
   if !wjson.exists() {
     write_json_file(&wjson, &default_workspace_json())?;
   }
@@ -267,12 +418,32 @@ fn open_workspace(path: String) -> Result<OpenWorkspaceResult, String> {
   }
 
   let db_bytes = std::fs::read(&db).map_err(|e| format!("Failed reading {}: {e}", db.display()))?;
-  if !is_sqlite_bytes(&db_bytes) {
+  if !is_workspace_db_bytes(&db_bytes) {
     return Err(format!("{} is not a valid SQLite database", WORKSPACE_DB_NAME));
   }
-  let db_base64 = base64::engine::general_purpose::STANDARD.encode(db_bytes);
+  let encrypted = db_crypto::is_encrypted_db(&db_bytes);
+  let plain = match passphrase.filter(|p| encrypted && !p.is_empty()) {
+    Some(p) => {
+      let key = db_crypto::derive_key(&p, Some(&db_bytes))?;
+      let plain = db_crypto::decrypt_db(&db_bytes, &key)?;
+      db_crypto::remember_key(&root, key);
+      plain
+    }
+    None => plain_db_bytes(&root, db_bytes)?,
+  };
+  let (plain, migration) = workspace_migrations::migrate_workspace(&root, plain)?;
+  let db_base64 = base64::engine::general_purpose::STANDARD.encode(plain);
+  // Migrations may have rewritten the file.
+  let on_disk = std::fs::read(&db).map_err(|e| format!("Failed reading {}: {e}", db.display()))?;
+  workspace_watcher::record(&db, &on_disk);
+  if let Err(e) = workspace_watcher::watch(&app, &root) {
+    eprintln!("[workspace] external changes will not be detected: {e}");
+  }
 
   let workspace_json = std::fs::read_to_string(&wjson).unwrap_or_else(|_| "{}".to_string());
+  if let Err(e) = workspace_registry::touch(&app, &path, None) {
+    eprintln!("[workspace] recent list not updated: {e}");
+  }
 
   Ok(OpenWorkspaceResult {
     workspace_json,
@@ -280,17 +451,31 @@ fn open_workspace(path: String) -> Result<OpenWorkspaceResult, String> {
     workspace_json_path: wjson.to_string_lossy().to_string(),
     db_path: db.to_string_lossy().to_string(),
     backups_dir: backups.to_string_lossy().to_string(),
+    encrypted,
+    migration,
+    recovery: workspace_recovery::scan(&root),
+    db_sha256: sha256_hex(&on_disk),
   })
 }
 
+/// Writes the webview's database to disk and returns the sha256 of what was
+/// written. With `expected_sha256` (the hash from `open_workspace` or the
+/// previous save) the write is refused with a `SAVE_CONFLICT` error carrying
+/// both hashes if the file has changed since, instead of overwriting someone
+/// else's update.
 #[tauri::command]
-fn save_workspace(path: String, db_b64: String) -> Result<(), String> {
+fn save_workspace(
+  scheduler: tauri::State<'_, sync_scheduler::SyncSchedulerState>,
+  path: String,
+  db_b64: String,
+  expected_sha256: Option<String>,
+) -> Result<String, SaveWorkspaceError> {
   let root = std::path::PathBuf::from(&path);
   if !root.exists() {
-    return Err("Workspace folder does not exist".to_string());
+    return Err("Workspace folder does not exist".to_string().into());
   }
   if !root.is_dir() {
-    return Err("Workspace path is not a folder".to_string());
+    return Err("Workspace path is not a folder".to_string().into());
   }
 
   let (_wjson, db, backups, media) = workspace_paths(&root);
@@ -302,10 +487,27 @@ fn save_workspace(path: String, db_b64: String) -> Result<(), String> {
     .map_err(|e| format!("Invalid db base64: {e}"))?;
 
   if !is_sqlite_bytes(&bytes) {
-    return Err(format!("Refusing to write: {} is not valid SQLite bytes", WORKSPACE_DB_NAME));
+    return Err(format!("Refusing to write: {} is not valid SQLite bytes", WORKSPACE_DB_NAME).into());
   }
 
-  atomic_write(&db, &bytes)
+  // Keep an encrypted workspace encrypted; never fall back to plain bytes.
+  let bytes = match db_crypto::session_key(&root) {
+    Some(key) => db_crypto::encrypt_db(&bytes, &key)?,
+    None if db_crypto::file_is_encrypted(&db) => return Err(db_crypto::LOCKED_ERROR.to_string().into()),
+    None => bytes,
+  };
+
+  let _guard = DB_WRITE_LOCK.lock().unwrap();
+  if let Some(expected) = expected_sha256.filter(|h| !h.trim().is_empty()) {
+    let current = std::fs::read(&db).ok().map(|b| sha256_hex(&b));
+    if current.as_deref() != Some(expected.trim()) {
+      return Err(SaveWorkspaceError::Conflict(SaveConflict { code: SAVE_CONFLICT_ERROR, expected: expected.trim().to_string(), found: current }));
+    }
+  }
+  atomic_write(&db, &bytes)?;
+  workspace_watcher::record(&db, &bytes);
+  scheduler.notify_saved(&root);
+  Ok(sha256_hex(&bytes))
 }
 
 fn timestamp_backup_name(prefix: &str, ext: &str) -> String {
@@ -329,16 +531,18 @@ fn create_backup_internal(root: &std::path::Path, prefix: &str) -> Result<String
     return Err(format!("Missing {} in workspace", WORKSPACE_DB_NAME));
   }
   let db_bytes = std::fs::read(&db).map_err(|e| format!("Failed reading {}: {e}", db.display()))?;
-  if !is_sqlite_bytes(&db_bytes) {
+  if !is_workspace_db_bytes(&db_bytes) {
     return Err(format!("{} is not a valid SQLite database", WORKSPACE_DB_NAME));
   }
 
+  // Encrypted databases are stored as-is, so backups stay encrypted too.
   let workspace_json = std::fs::read_to_string(&wjson).unwrap_or_else(|_| "{}".to_string());
   let metadata = serde_json::json!({
     "app": "RentikPro",
     "format": "rentikpro-workspace-backup",
     "createdAt": chrono::Utc::now().timestamp_millis(),
     "dbFile": WORKSPACE_DB_NAME,
+    "encrypted": db_crypto::is_encrypted_db(&db_bytes),
   });
   let metadata_bytes = serde_json::to_vec_pretty(&metadata).map_err(|e| format!("Metadata encode failed: {e}"))?;
 
@@ -437,7 +641,7 @@ fn extract_db_from_backup(backup_path: &std::path::Path) -> Result<Vec<u8>, Stri
   use std::io::Read;
   let mut bytes: Vec<u8> = vec![];
   file.read_to_end(&mut bytes).map_err(|e| format!("Failed reading database from ZIP: {e}"))?;
-  if !is_sqlite_bytes(&bytes) {
+  if !is_workspace_db_bytes(&bytes) {
     return Err("database.sqlite extracted from backup is not valid SQLite bytes".to_string());
   }
   Ok(bytes)
@@ -460,6 +664,9 @@ fn restore_backup(path: String, backup_name: String) -> Result<String, String> {
     return Err("Invalid backup name".to_string());
   }
 
+  // Held from the safety backup until the restored file is recorded, so a save
+  // or sync cannot land in between and be lost.
+  let _guard = DB_WRITE_LOCK.lock().unwrap();
   // Auto-backup current state before overwriting.
   create_backup_internal(&root, "autobackup_before_restore_").ok();
 
@@ -476,25 +683,21 @@ fn restore_backup(path: String, backup_name: String) -> Result<String, String> {
 
   // Extract ONLY database.sqlite (or legacy db.sqlite) from the ZIP
   let bytes = extract_db_from_backup(&backup_path)?;
+  // The webview needs plain SQLite; an encrypted backup must be unlockable
+  // before anything on disk is replaced.
+  let plain = plain_db_bytes(&root, bytes.clone())?;
 
   // Requirement: write EXACTLY to <workspace>/database.sqlite with atomic tmp+rename
   let final_db = root.join(WORKSPACE_DB_NAME);
-  let tmp_db = root.join(format!("{WORKSPACE_DB_NAME}.tmp"));
-
-  std::fs::write(&tmp_db, &bytes)
-    .map_err(|e| format!("Failed writing temp {}: {e}", tmp_db.display()))?;
-
-  // On Windows, rename cannot overwrite an existing file.
-  #[cfg(target_os = "windows")]
-  {
-    if final_db.exists() {
-      std::fs::remove_file(&final_db)
-        .map_err(|e| format!("Failed removing existing {}: {e}", final_db.display()))?;
-    }
-  }
-
-  std::fs::rename(&tmp_db, &final_db)
-    .map_err(|e| format!("Failed renaming temp into {}: {e}", final_db.display()))?;
+  // Same rule as `save_workspace`: a backup taken before encryption was
+  // enabled must not put plain bytes back on disk.
+  let bytes = match db_crypto::session_key(&root) {
+    Some(key) => db_crypto::encrypt_db(&plain, &key)?,
+    None if db_crypto::file_is_encrypted(&final_db) => return Err(db_crypto::LOCKED_ERROR.to_string()),
+    None => bytes,
+  };
+  atomic_write(&final_db, &bytes)?;
+  workspace_watcher::record(&final_db, &bytes);
 
   // Verification requirement
   let ok = final_db.exists()
@@ -507,7 +710,7 @@ fn restore_backup(path: String, backup_name: String) -> Result<String, String> {
 
   // Log requirement
   println!("Workspace database restored successfully");
-  Ok(base64::engine::general_purpose::STANDARD.encode(bytes))
+  Ok(base64::engine::general_purpose::STANDARD.encode(plain))
 }
 
 #[tauri::command]
@@ -531,21 +734,87 @@ fn reset_workspace(path: String) -> Result<(), String> {
   Ok(())
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 struct WebDavSyncArgs {
-  mode: String, // "up" | "down"
+  mode: String, // "up" | "down" | "auto"
   url: String,
+  // Legacy inline credentials; when empty the ones stored for the workspace are used.
+  #[serde(default)]
   user: String,
+  #[serde(default)]
   pass: String,
+  #[serde(default)]
+  workspace_id: Option<String>,
   slug: String,
   project_path: String,
   client_id: String,
   force: bool,
-  local_db_base64: String,
+  // When omitted the database is read from the local workspace file.
+  #[serde(default)]
+  local_db_base64: Option<String>,
+  #[serde(default)]
+  device_name: Option<String>,
 }
 
-#[derive(serde::Serialize)]
+/// Connection details for commands that talk to a workspace's remote folder
+/// without running a full sync.
+#[derive(serde::Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct WebDavRemoteArgs {
+  url: String,
+  #[serde(default)]
+  user: String,
+  #[serde(default)]
+  pass: String,
+  #[serde(default)]
+  workspace_id: Option<String>,
+  #[serde(default)]
+  slug: String,
+  #[serde(default)]
+  client_id: String,
+}
+
+struct WebDavRemote {
+  client: reqwest::Client,
+  auth: webdav_auth::DavAuth,
+  root: String,
+}
+
+impl WebDavRemote {
+  fn url(&self, name: &str) -> String {
+    join_base(&self.root, name)
+  }
+}
+
+impl WebDavRemoteArgs {
+  fn base_url(&self) -> Result<String, String> {
+    let base = self.url.trim().trim_end_matches('/').to_string();
+    if base.is_empty() {
+      return Err("Missing WebDAV url".to_string());
+    }
+    Ok(base)
+  }
+
+  fn auth(&self) -> Result<webdav_auth::DavAuth, String> {
+    webdav_auth::resolve_auth(&self.user, &self.pass, self.workspace_id.as_deref())
+  }
+
+  fn connect(&self) -> Result<WebDavRemote, String> {
+    let slug = self.slug.trim();
+    if slug.is_empty() {
+      return Err("Missing slug".to_string());
+    }
+    let base = self.base_url()?;
+    Ok(WebDavRemote {
+      client: reqwest::Client::new(),
+      auth: self.auth()?,
+      root: remote_workspace_root(&base, slug),
+    })
+  }
+}
+
+#[derive(serde::Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 struct WebDavSyncResponse {
   success: bool,
@@ -557,6 +826,13 @@ struct WebDavSyncResponse {
   db_base64: Option<String>,
   applied: Option<bool>,
   workspace_kind: Option<String>,
+  /// Direction actually used ("up" | "down"); `mode: "auto"` resolves to one.
+  mode: Option<String>,
+  /// Result of the media pass, run after a successful database sync.
+  media: Option<media_sync::MediaSyncSummary>,
+  /// Hash of the local database file when this run rewrote it, even if a
+  /// later step failed; the webview's `expected_sha256` for its next save.
+  db_sha256: Option<String>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Clone, Default)]
@@ -677,11 +953,21 @@ fn join_base(base: &str, suffix: &str) -> String {
   format!("{b}/{s}")
 }
 
-async fn dav_mkcol(client: &reqwest::Client, url: &str, auth: &str) -> Result<(), String> {
-  let res = client
-    .request(reqwest::Method::from_bytes(b"MKCOL").unwrap(), url)
-    .header("Authorization", auth)
-    .send()
+fn remote_workspace_root(base: &str, slug: &str) -> String {
+  join_base(base, &format!("RentikProSync/{slug}"))
+}
+
+fn basic_auth_header(user: &str, pass: &str) -> String {
+  format!(
+    "Basic {}",
+    base64::engine::general_purpose::STANDARD.encode(format!("{user}:{pass}"))
+  )
+}
+
+async fn dav_mkcol(client: &reqwest::Client, url: &str, auth: &webdav_auth::DavAuth) -> Result<(), String> {
+  let req = client.request(reqwest::Method::from_bytes(b"MKCOL").unwrap(), url);
+  let res = auth
+    .execute(client, req)
     .await
     .map_err(|e| format!("MKCOL failed: {e}"))?;
   let status = res.status();
@@ -692,11 +978,10 @@ async fn dav_mkcol(client: &reqwest::Client, url: &str, auth: &str) -> Result<()
   Err(format!("MKCOL {url} -> {status} {t}"))
 }
 
-async fn dav_get_bytes(client: &reqwest::Client, url: &str, auth: &str) -> Result<Vec<u8>, String> {
-  let res = client
-    .get(url)
-    .header("Authorization", auth)
-    .send()
+async fn dav_get_bytes(client: &reqwest::Client, url: &str, auth: &webdav_auth::DavAuth) -> Result<Vec<u8>, String> {
+  let req = client.get(url);
+  let res = auth
+    .execute(client, req)
     .await
     .map_err(|e| format!("GET failed: {e}"))?;
   let status = res.status();
@@ -711,13 +996,13 @@ async fn dav_get_bytes(client: &reqwest::Client, url: &str, auth: &str) -> Resul
   Ok(b.to_vec())
 }
 
-async fn dav_put_bytes(client: &reqwest::Client, url: &str, auth: &str, bytes: Vec<u8>, content_type: &str) -> Result<(), String> {
-  let res = client
+async fn dav_put_bytes(client: &reqwest::Client, url: &str, auth: &webdav_auth::DavAuth, bytes: Vec<u8>, content_type: &str) -> Result<(), String> {
+  let req = client
     .put(url)
-    .header("Authorization", auth)
     .header("Content-Type", content_type)
-    .body(bytes)
-    .send()
+    .body(bytes);
+  let res = auth
+    .execute(client, req)
     .await
     .map_err(|e| format!("PUT failed: {e}"))?;
   let status = res.status();
@@ -728,11 +1013,10 @@ async fn dav_put_bytes(client: &reqwest::Client, url: &str, auth: &str, bytes: V
   Ok(())
 }
 
-async fn dav_delete(client: &reqwest::Client, url: &str, auth: &str) -> Result<(), String> {
-  let res = client
-    .delete(url)
-    .header("Authorization", auth)
-    .send()
+async fn dav_delete(client: &reqwest::Client, url: &str, auth: &webdav_auth::DavAuth) -> Result<(), String> {
+  let req = client.delete(url);
+  let res = auth
+    .execute(client, req)
     .await
     .map_err(|e| format!("DELETE failed: {e}"))?;
   let status = res.status();
@@ -743,13 +1027,13 @@ async fn dav_delete(client: &reqwest::Client, url: &str, auth: &str) -> Result<(
   Err(format!("DELETE {url} -> {status} {t}"))
 }
 
-async fn dav_move(client: &reqwest::Client, from_url: &str, to_url: &str, auth: &str) -> Result<(), String> {
-  let res = client
+async fn dav_move(client: &reqwest::Client, from_url: &str, to_url: &str, auth: &webdav_auth::DavAuth) -> Result<(), String> {
+  let req = client
     .request(reqwest::Method::from_bytes(b"MOVE").unwrap(), from_url)
-    .header("Authorization", auth)
     .header("Destination", to_url)
-    .header("Overwrite", "T")
-    .send()
+    .header("Overwrite", "T");
+  let res = auth
+    .execute(client, req)
     .await
     .map_err(|e| format!("MOVE failed: {e}"))?;
   let status = res.status();
@@ -760,7 +1044,7 @@ async fn dav_move(client: &reqwest::Client, from_url: &str, to_url: &str, auth:
   Ok(())
 }
 
-async fn read_remote_lock(client: &reqwest::Client, lock_url: &str, auth: &str) -> Result<Option<LockFileV1>, String> {
+async fn read_remote_lock(client: &reqwest::Client, lock_url: &str, auth: &webdav_auth::DavAuth) -> Result<Option<LockFileV1>, String> {
   match dav_get_bytes(client, lock_url, auth).await {
     Ok(bytes) => Ok(serde_json::from_slice::<LockFileV1>(&bytes).ok()),
     Err(e) if e == "NOT_FOUND" => Ok(None),
@@ -768,12 +1052,12 @@ async fn read_remote_lock(client: &reqwest::Client, lock_url: &str, auth: &str)
   }
 }
 
-async fn write_remote_lock(client: &reqwest::Client, lock_url: &str, auth: &str, lock: &LockFileV1) -> Result<(), String> {
+async fn write_remote_lock(client: &reqwest::Client, lock_url: &str, auth: &webdav_auth::DavAuth, lock: &LockFileV1) -> Result<(), String> {
   let txt = serde_json::to_vec(lock).map_err(|e| format!("Lock encode failed: {e}"))?;
   dav_put_bytes(client, lock_url, auth, txt, "application/json").await
 }
 
-async fn acquire_lock(client: &reqwest::Client, lock_url: &str, auth: &str, client_id: &str, workspace_kind: &str, ttl_ms: i64) -> Result<Option<LockFileV1>, String> {
+async fn acquire_lock(client: &reqwest::Client, lock_url: &str, auth: &webdav_auth::DavAuth, client_id: &str, workspace_kind: &str, ttl_ms: i64) -> Result<Option<LockFileV1>, String> {
   let now = chrono::Utc::now().timestamp_millis();
   if let Some(lock) = read_remote_lock(client, lock_url, auth).await? {
     if lock.client_id != client_id && now < lock.expires_at {
@@ -794,7 +1078,7 @@ async fn acquire_lock(client: &reqwest::Client, lock_url: &str, auth: &str, clie
   Ok(Some(lock))
 }
 
-async fn renew_lock(client: &reqwest::Client, lock_url: &str, auth: &str, lock: &mut LockFileV1, ttl_ms: i64) -> Result<(), String> {
+async fn renew_lock(client: &reqwest::Client, lock_url: &str, auth: &webdav_auth::DavAuth, lock: &mut LockFileV1, ttl_ms: i64) -> Result<(), String> {
   let now = chrono::Utc::now().timestamp_millis();
   lock.heartbeat_at = now;
   lock.expires_at = now + ttl_ms;
@@ -835,15 +1119,79 @@ fn finish_sync_response(
     db_base64,
     applied,
     workspace_kind,
+    mode: None,
+    media: None,
+    db_sha256: None,
   }
 }
 
+/// Picks the direction for `mode: "auto"`: pull when there is no local
+/// database yet (a fresh device) or it is unchanged since the last recorded
+/// sync, otherwise push.
+fn auto_sync_mode(local_empty: bool, local_sha: &str, local_state: Option<&SyncStateV1>) -> &'static str {
+  if local_empty {
+    return "down";
+  }
+  match local_state {
+    Some(ls) if ls.sha256 == local_sha => "down",
+    _ => "up",
+  }
+}
+
+fn read_local_db_bytes(ctx: &LocalSyncContext, local_db_base64: Option<&str>) -> Result<Vec<u8>, String> {
+  // An encrypted workspace syncs its file as-is; the webview export is plain
+  // and must never reach the remote.
+  let on_disk_encrypted = db_crypto::file_is_encrypted(&ctx.db_path);
+  if let Some(b64) = local_db_base64.filter(|s| !s.is_empty() && !on_disk_encrypted) {
+    return base64::engine::general_purpose::STANDARD
+      .decode(b64.as_bytes())
+      .map_err(|e| format!("Invalid local DB base64: {e}"));
+  }
+  if !ctx.db_path.exists() {
+    return Ok(vec![]);
+  }
+  let bytes = std::fs::read(&ctx.db_path).map_err(|e| format!("Failed reading {}: {e}", ctx.db_path.display()))?;
+  if !is_workspace_db_bytes(&bytes) {
+    return Err(format!("{} is not a valid SQLite database", ctx.local_db_file));
+  }
+  Ok(bytes)
+}
+
+/// Replaces the local database during a sync, under the same lock as
+/// `save_workspace`, and returns the hash of what was written. `expected` is
+/// the hash the sync started from (`None`: there was no file); when the file
+/// changed since, a save landed mid-sync and the write is refused with
+/// `SAVE_CONFLICT` rather than overwriting it.
+fn write_local_db(path: &std::path::Path, bytes: &[u8], expected: Option<&str>) -> Result<String, String> {
+  let _guard = DB_WRITE_LOCK.lock().unwrap();
+  let current = std::fs::read(path).ok().map(|b| sha256_hex(&b));
+  if current.as_deref() != expected {
+    return Err(format!("{SAVE_CONFLICT_ERROR}: {} changed on disk during the sync", WORKSPACE_DB_NAME));
+  }
+  atomic_write(path, bytes)?;
+  workspace_watcher::record(path, bytes);
+  Ok(sha256_hex(bytes))
+}
+
 #[tauri::command]
-async fn webdav_sync(args: WebDavSyncArgs) -> Result<WebDavSyncResponse, String> {
-  let auth = format!(
-    "Basic {}",
-    base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", args.user, args.pass))
-  );
+async fn webdav_sync(app: tauri::AppHandle, args: WebDavSyncArgs) -> Result<WebDavSyncResponse, String> {
+  let _guard = sync_scheduler::LOCAL_SYNC_GUARD.lock().await;
+  let (project_path, slug) = (args.project_path.clone(), args.slug.clone());
+  let resp = webdav_sync_internal(args).await?;
+  if resp.success {
+    record_sync_slug(&app, &project_path, &slug);
+  }
+  Ok(resp)
+}
+
+/// The recent-workspaces list shows the slug a workspace actually syncs to.
+fn record_sync_slug(app: &tauri::AppHandle, project_path: &str, slug: &str) {
+  if let Err(e) = workspace_registry::record_sync_slug(app, project_path, slug) {
+    eprintln!("[sync] recent list not updated: {e}");
+  }
+}
+
+async fn webdav_sync_internal(args: WebDavSyncArgs) -> Result<WebDavSyncResponse, String> {
   let client = reqwest::Client::new();
 
   let slug = args.slug.trim().to_string();
@@ -862,22 +1210,35 @@ async fn webdav_sync(args: WebDavSyncArgs) -> Result<WebDavSyncResponse, String>
   ensure_dir(&local_ctx.backups_dir)?;
   ensure_dir(&local_ctx.conflicts_dir)?;
 
-  let remote_root = join_base(&base, &format!("RentikProSync/{slug}"));
+  let remote_root = remote_workspace_root(&base, &slug);
   let remote_state_url = join_base(&remote_root, "state.json");
   let remote_lock_url = join_base(&remote_root, "lock.json");
+  let remote_devices_url = join_base(&remote_root, sync_devices::DEVICES_FILE_NAME);
   let remote_db_url = join_base(&remote_root, WORKSPACE_DB_NAME);
   let remote_meta_url = join_base(&remote_root, WORKSPACE_JSON_NAME);
 
-  // ensure dirs
-  dav_mkcol(&client, &join_base(&base, "RentikProSync"), &auth).await.ok();
-  dav_mkcol(&client, &remote_root, &auth).await.ok();
-
   let local_state_path = local_ctx.sync_dir.join("state.json");
   let local_meta = read_local_metadata(&local_ctx, &slug);
   let remote_workspace_meta = build_remote_workspace_json(&local_meta, &local_ctx);
 
   let local_state: Option<SyncStateV1> = read_json_file(&local_state_path);
 
+  let workspace_id = args
+    .workspace_id
+    .clone()
+    .or_else(|| local_meta.get("id").and_then(|v| v.as_str()).map(String::from));
+  let auth = match webdav_auth::resolve_auth(&args.user, &args.pass, workspace_id.as_deref()) {
+    Ok(a) => a,
+    Err(e) => return Ok(finish_sync_response(false, Some(e), false, None, local_state.as_ref().and_then(|s| serde_json::to_value(s).ok()), None, None, None, Some(local_ctx.kind.clone()))),
+  };
+
+  // ensure dirs
+  for dir_url in [join_base(&base, "RentikProSync"), remote_root.clone()] {
+    if let Err(e) = dav_mkcol(&client, &dir_url, &auth).await {
+      return Ok(finish_sync_response(false, Some(e), false, None, local_state.as_ref().and_then(|s| serde_json::to_value(s).ok()), None, None, None, Some(local_ctx.kind.clone())));
+    }
+  }
+
   let remote_state: Option<SyncStateV1> = match dav_get_bytes(&client, &remote_state_url, &auth).await {
     Ok(b) => serde_json::from_slice(&b).ok(),
     Err(e) if e == "NOT_FOUND" => None,
@@ -898,21 +1259,43 @@ async fn webdav_sync(args: WebDavSyncArgs) -> Result<WebDavSyncResponse, String>
   let mut held_lock = lock.unwrap();
 
   let now = chrono::Utc::now().timestamp_millis();
-  let local_db_bytes = match base64::engine::general_purpose::STANDARD.decode(args.local_db_base64.as_bytes()) {
+  let local_db_bytes = match read_local_db_bytes(&local_ctx, args.local_db_base64.as_deref()) {
     Ok(b) => b,
     Err(e) => {
       let _ = dav_delete(&client, &remote_lock_url, &auth).await;
-      return Ok(finish_sync_response(false, Some(format!("Invalid local DB base64: {e}")), false, remote_state.as_ref().and_then(|s| serde_json::to_value(s).ok()), local_state.as_ref().and_then(|s| serde_json::to_value(s).ok()), None, None, None, Some(local_ctx.kind.clone())));
+      return Ok(finish_sync_response(false, Some(e), false, remote_state.as_ref().and_then(|s| serde_json::to_value(s).ok()), local_state.as_ref().and_then(|s| serde_json::to_value(s).ok()), None, None, None, Some(local_ctx.kind.clone())));
     }
   };
   let local_sha = sha256_hex(&local_db_bytes);
+  // What is on disk now; the local write at the end only goes ahead if it
+  // still is (see `write_local_db`).
+  let disk_sha = std::fs::read(&local_ctx.db_path).ok().map(|b| sha256_hex(&b));
+  let mode = if args.mode == "auto" {
+    auto_sync_mode(local_db_bytes.is_empty(), &local_sha, local_state.as_ref()).to_string()
+  } else {
+    args.mode.clone()
+  };
 
-  let finish = |resp: WebDavSyncResponse| async {
+  // Set once this run has rewritten the local database.
+  let written_sha: std::sync::Mutex<Option<String>> = std::sync::Mutex::new(None);
+  let finish = |mut resp: WebDavSyncResponse| async {
+    resp.mode = Some(mode.clone());
+    resp.db_sha256 = written_sha.lock().unwrap().clone();
+    if resp.success && local_ctx.kind == "workspace" {
+      resp.media = Some(match media_sync::sync_media(&client, &auth, &remote_root, &local_ctx.root, &args.client_id).await {
+        Ok(summary) => summary,
+        Err(e) => media_sync::MediaSyncSummary::failed(e),
+      });
+    }
+    let _ = sync_devices::record_device_sync(&client, &remote_devices_url, &auth, &args.client_id, args.device_name.as_deref(), Some(resp.success)).await;
     let _ = dav_delete(&client, &remote_lock_url, &auth).await;
     resp
   };
 
-  if args.mode == "up" {
+  if mode == "up" {
+    if local_db_bytes.is_empty() {
+      return Ok(finish(finish_sync_response(false, Some(format!("Missing local {}", local_ctx.local_db_file)), false, remote_state.as_ref().and_then(|s| serde_json::to_value(s).ok()), local_state.as_ref().and_then(|s| serde_json::to_value(s).ok()), None, None, None, Some(local_ctx.kind.clone()))).await);
+    }
     let mut no_op = false;
 
     if let Some(rs) = remote_state.clone() {
@@ -976,8 +1359,15 @@ async fn webdav_sync(args: WebDavSyncArgs) -> Result<WebDavSyncResponse, String>
       }
     }
 
-    if let Err(e) = atomic_write(&local_ctx.db_path, &local_db_bytes) {
-      return Ok(finish(finish_sync_response(false, Some(format!("Failed writing local {}: {e}", local_ctx.local_db_file)), false, remote_state.as_ref().and_then(|s| serde_json::to_value(s).ok()), local_state.as_ref().and_then(|s| serde_json::to_value(s).ok()), None, None, None, Some(local_ctx.kind.clone()))).await);
+    match write_local_db(&local_ctx.db_path, &local_db_bytes, disk_sha.as_deref()) {
+      Ok(sha) => *written_sha.lock().unwrap() = Some(sha),
+      Err(e) if e.starts_with(SAVE_CONFLICT_ERROR) => {
+        // Nothing was uploaded yet; the next run pushes the newer save.
+        return Ok(finish(finish_sync_response(false, Some("Conflict: local database changed during the sync; sync again".to_string()), true, remote_state.as_ref().and_then(|s| serde_json::to_value(s).ok()), local_state.as_ref().and_then(|s| serde_json::to_value(s).ok()), None, None, None, Some(local_ctx.kind.clone()))).await);
+      }
+      Err(e) => {
+        return Ok(finish(finish_sync_response(false, Some(format!("Failed writing local {}: {e}", local_ctx.local_db_file)), false, remote_state.as_ref().and_then(|s| serde_json::to_value(s).ok()), local_state.as_ref().and_then(|s| serde_json::to_value(s).ok()), None, None, None, Some(local_ctx.kind.clone()))).await);
+      }
     }
     let local_workspace_meta = adapt_remote_workspace_json_for_local(&remote_workspace_meta, &local_ctx);
     if let Err(e) = write_json_file(&local_ctx.meta_path, &local_workspace_meta) {
@@ -1088,12 +1478,26 @@ async fn webdav_sync(args: WebDavSyncArgs) -> Result<WebDavSyncResponse, String>
   }
 
   let backup_path = local_ctx.backups_dir.join(format!("local-{}-{}.sqlite", now, &local_sha[..8]));
-  if let Err(e) = std::fs::write(&backup_path, &local_db_bytes) {
-    return Ok(finish(finish_sync_response(false, Some(format!("Failed writing backup: {e}")), false, serde_json::to_value(rs).ok(), local_state.as_ref().and_then(|s| serde_json::to_value(s).ok()), None, None, None, Some(local_ctx.kind.clone()))).await);
+  // A fresh device has no local database to keep.
+  if !local_db_bytes.is_empty() {
+    if let Err(e) = std::fs::write(&backup_path, &local_db_bytes) {
+      return Ok(finish(finish_sync_response(false, Some(format!("Failed writing backup: {e}")), false, serde_json::to_value(rs).ok(), local_state.as_ref().and_then(|s| serde_json::to_value(s).ok()), None, None, None, Some(local_ctx.kind.clone()))).await);
+    }
   }
 
-  if let Err(e) = atomic_write(&local_ctx.db_path, &remote_db) {
-    return Ok(finish(finish_sync_response(false, Some(format!("Failed writing local {}: {e}", local_ctx.local_db_file)), false, serde_json::to_value(rs).ok(), local_state.as_ref().and_then(|s| serde_json::to_value(s).ok()), None, None, None, Some(local_ctx.kind.clone()))).await);
+  match write_local_db(&local_ctx.db_path, &remote_db, disk_sha.as_deref()) {
+    Ok(sha) => *written_sha.lock().unwrap() = Some(sha),
+    Err(e) if e.starts_with(SAVE_CONFLICT_ERROR) => {
+      // The save that landed stays on disk; keep the download next to it.
+      let remote_copy_path = local_ctx.conflicts_dir.join(format!("remote-{}-{}.sqlite", now, &rs.sha256[..8]));
+      let _ = std::fs::write(&remote_copy_path, &remote_db);
+      return Ok(finish(finish_sync_response(false, Some("Conflict: local database changed during the download".to_string()), true, serde_json::to_value(rs).ok(), local_state.as_ref().and_then(|s| serde_json::to_value(s).ok()), serde_json::to_value(serde_json::json!({
+        "remoteCopy": remote_copy_path.to_string_lossy().to_string()
+      })).ok(), None, None, Some(local_ctx.kind.clone()))).await);
+    }
+    Err(e) => {
+      return Ok(finish(finish_sync_response(false, Some(format!("Failed writing local {}: {e}", local_ctx.local_db_file)), false, serde_json::to_value(rs).ok(), local_state.as_ref().and_then(|s| serde_json::to_value(s).ok()), None, None, None, Some(local_ctx.kind.clone()))).await);
+    }
   }
   if let Some(remote_meta) = remote_meta_json.as_ref() {
     let local_meta_json = adapt_remote_workspace_json_for_local(remote_meta, &local_ctx);
@@ -1105,7 +1509,12 @@ async fn webdav_sync(args: WebDavSyncArgs) -> Result<WebDavSyncResponse, String>
     return Ok(finish(finish_sync_response(false, Some(e), false, serde_json::to_value(rs).ok(), local_state.as_ref().and_then(|s| serde_json::to_value(s).ok()), None, None, None, Some(local_ctx.kind.clone()))).await);
   }
 
-  return Ok(finish(finish_sync_response(true, None, false, serde_json::to_value(rs.clone()).ok(), serde_json::to_value(rs.clone()).ok(), None, Some(base64::engine::general_purpose::STANDARD.encode(remote_db)), Some(true), Some(local_ctx.kind.clone()))).await);
+  // Hand the webview plain SQLite; an encrypted download we cannot unlock yet
+  // is left on disk for the next `open_workspace`.
+  let downloaded_b64 = plain_db_bytes(&local_ctx.root, remote_db)
+    .ok()
+    .map(|b| base64::engine::general_purpose::STANDARD.encode(b));
+  return Ok(finish(finish_sync_response(true, None, false, serde_json::to_value(rs.clone()).ok(), serde_json::to_value(rs.clone()).ok(), None, downloaded_b64, Some(true), Some(local_ctx.kind.clone()))).await);
 }
 
 #[derive(serde::Serialize)]
@@ -1114,6 +1523,8 @@ struct ValidateProjectResult {
   error: Option<String>,
   project_json_path: String,
   db_path: String,
+  /// `.prev` / `.tmp` files left by an interrupted `write_project_folder`.
+  leftover_files: Vec<String>,
 }
 
 #[derive(serde::Serialize)]
@@ -1139,6 +1550,7 @@ fn validate_project_folder(path: String) -> Result<ValidateProjectResult, String
       error: Some("Folder does not exist".to_string()),
       project_json_path: root.join("project.json").to_string_lossy().to_string(),
       db_path: root.join("db.sqlite").to_string_lossy().to_string(),
+      leftover_files: vec![],
     });
   }
   if !root.is_dir() {
@@ -1147,6 +1559,7 @@ fn validate_project_folder(path: String) -> Result<ValidateProjectResult, String
       error: Some("Path is not a folder".to_string()),
       project_json_path: root.join("project.json").to_string_lossy().to_string(),
       db_path: root.join("db.sqlite").to_string_lossy().to_string(),
+      leftover_files: vec![],
     });
   }
 
@@ -1154,8 +1567,29 @@ fn validate_project_folder(path: String) -> Result<ValidateProjectResult, String
   let pj_ok = pj.exists();
   let db_ok = db.exists();
 
-  let ok = pj_ok && db_ok;
-  let error = if ok {
+  let mut leftovers = vec![];
+  for path in [&db, &pj] {
+    for suffix in ["prev", "tmp"] {
+      let p = staged_path(path, suffix)?;
+      if p.exists() {
+        leftovers.push(p);
+      }
+    }
+  }
+  let names: Vec<String> = leftovers.iter().filter_map(|p| p.file_name()).map(|n| n.to_string_lossy().to_string()).collect();
+  // A `.prev` next to a missing file or an unconsumed `.tmp` means the swap
+  // stopped halfway and the pair may mix versions. A lone `.prev` is only a
+  // cleanup that did not finish.
+  let has_prev = names.iter().any(|n| n.ends_with(".prev"));
+  let interrupted = has_prev && (!pj_ok || !db_ok || names.iter().any(|n| n.ends_with(".tmp")));
+
+  let ok = pj_ok && db_ok && !interrupted;
+  let error = if interrupted {
+    Some(format!(
+      "An interrupted save left {}; the .prev files hold the previous version. Restore them or remove them before opening",
+      names.join(", ")
+    ))
+  } else if ok {
     None
   } else {
     Some(format!(
@@ -1170,6 +1604,7 @@ fn validate_project_folder(path: String) -> Result<ValidateProjectResult, String
     error,
     project_json_path: pj.to_string_lossy().to_string(),
     db_path: db.to_string_lossy().to_string(),
+    leftover_files: leftovers.iter().map(|p| p.to_string_lossy().to_string()).collect(),
   })
 }
 
@@ -1197,6 +1632,78 @@ fn open_project_folder(path: String) -> Result<OpenProjectResult, String> {
   })
 }
 
+fn staged_path(path: &std::path::Path, suffix: &str) -> Result<std::path::PathBuf, String> {
+  let filename = path
+    .file_name()
+    .and_then(|s| s.to_str())
+    .ok_or_else(|| "Invalid path filename".to_string())?;
+  Ok(path.with_file_name(format!("{filename}.{suffix}")))
+}
+
+/// Replaces `files` together: every new version is staged as `<name>.tmp`,
+/// the current ones are set aside as `<name>.prev`, and if any rename fails
+/// the ones already swapped in are undone so the pair never mixes versions.
+fn commit_files_atomically(files: &[(&std::path::Path, &[u8])]) -> Result<(), String> {
+  let mut staged = vec![];
+  for (path, bytes) in files {
+    let tmp = staged_path(path, "tmp")?;
+    if let Err(e) = write_synced(&tmp, bytes) {
+      let _ = std::fs::remove_file(&tmp);
+      for t in &staged {
+        let _ = std::fs::remove_file(t);
+      }
+      return Err(e);
+    }
+    staged.push(tmp);
+  }
+
+  let mut set_aside: Vec<(std::path::PathBuf, std::path::PathBuf)> = vec![];
+  let mut committed: Vec<&std::path::Path> = vec![];
+  let mut failure = None;
+  for ((path, _), tmp) in files.iter().zip(&staged) {
+    if path.exists() {
+      let prev = staged_path(path, "prev")?;
+      if let Err(e) = std::fs::rename(path, &prev) {
+        failure = Some(format!("Failed setting aside {}: {e}", path.display()));
+        break;
+      }
+      set_aside.push((prev, path.to_path_buf()));
+    }
+    if let Err(e) = std::fs::rename(tmp, path) {
+      failure = Some(format!("Failed renaming temp into {}: {e}", path.display()));
+      break;
+    }
+    committed.push(path);
+  }
+
+  let mut parents: Vec<&std::path::Path> = files.iter().filter_map(|(p, _)| p.parent()).collect();
+  parents.dedup();
+  let result = match failure {
+    None => {
+      // The renames must be on disk before the previous versions go.
+      parents.iter().for_each(|d| sync_dir(d));
+      for (prev, _) in set_aside {
+        let _ = std::fs::remove_file(prev);
+      }
+      Ok(())
+    }
+    Some(err) => {
+      for path in committed {
+        let _ = std::fs::remove_file(path);
+      }
+      for (prev, path) in set_aside {
+        let _ = std::fs::rename(prev, path);
+      }
+      for tmp in staged {
+        let _ = std::fs::remove_file(tmp);
+      }
+      Err(err)
+    }
+  };
+  parents.iter().for_each(|d| sync_dir(d));
+  result
+}
+
 #[tauri::command]
 fn write_project_folder(path: String, project_json: String, db_base64: String, overwrite: bool) -> Result<ValidateProjectResult, String> {
   let root = std::path::PathBuf::from(&path);
@@ -1218,14 +1725,124 @@ fn write_project_folder(path: String, project_json: String, db_base64: String, o
     }
   }
 
-  std::fs::write(&pj, project_json.as_bytes())
-    .map_err(|e| format!("Failed writing project.json: {e}"))?;
-
+  // Validate everything before touching the folder.
+  match serde_json::from_str::<serde_json::Value>(&project_json) {
+    Ok(v) if v.is_object() => {}
+    Ok(_) => return Err("Refusing to write: project.json must be a JSON object".to_string()),
+    Err(e) => return Err(format!("Refusing to write: project.json is not valid JSON: {e}")),
+  }
   let db_bytes = base64::engine::general_purpose::STANDARD
     .decode(db_base64.as_bytes())
     .map_err(|e| format!("Invalid db base64: {e}"))?;
-  std::fs::write(&db, db_bytes)
-    .map_err(|e| format!("Failed writing db.sqlite: {e}"))?;
+  if !is_sqlite_bytes(&db_bytes) {
+    return Err("Refusing to write: db.sqlite is not valid SQLite bytes".to_string());
+  }
+
+  // The database goes first: a project.json without its db.sqlite is the
+  // state validate_project_folder already reports as broken.
+  commit_files_atomically(&[(&db, &db_bytes), (&pj, project_json.as_bytes())])?;
 
   validate_project_folder(path)
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn state_with_sha(sha: &str) -> SyncStateV1 {
+    SyncStateV1 { version: 1, sha256: sha.to_string(), ..Default::default() }
+  }
+
+  #[test]
+  fn auto_sync_mode_pulls_on_fresh_device() {
+    let empty_sha = sha256_hex(&[]);
+    assert_eq!(auto_sync_mode(true, &empty_sha, None), "down");
+    assert_eq!(auto_sync_mode(true, &empty_sha, Some(&state_with_sha("abc"))), "down");
+  }
+
+  #[test]
+  fn auto_sync_mode_pushes_local_changes() {
+    assert_eq!(auto_sync_mode(false, "new", Some(&state_with_sha("old"))), "up");
+    assert_eq!(auto_sync_mode(false, "new", None), "up");
+  }
+
+  #[test]
+  fn auto_sync_mode_pulls_when_local_unchanged() {
+    assert_eq!(auto_sync_mode(false, "same", Some(&state_with_sha("same"))), "down");
+  }
+
+  #[test]
+  fn commit_files_atomically_replaces_both_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let (pj, db) = project_paths(dir.path());
+    std::fs::write(&db, b"old-db").unwrap();
+    std::fs::write(&pj, b"old-pj").unwrap();
+    commit_files_atomically(&[(&db, b"new-db"), (&pj, b"new-pj")]).unwrap();
+    assert_eq!(std::fs::read(&db).unwrap(), b"new-db");
+    assert_eq!(std::fs::read(&pj).unwrap(), b"new-pj");
+    let v = validate_project_folder(dir.path().to_string_lossy().to_string()).unwrap();
+    assert!(v.ok && v.leftover_files.is_empty());
+  }
+
+  #[test]
+  fn commit_files_atomically_rolls_back_on_failure() {
+    let dir = tempfile::tempdir().unwrap();
+    let (pj, db) = project_paths(dir.path());
+    std::fs::write(&db, b"old-db").unwrap();
+    std::fs::write(&pj, b"old-pj").unwrap();
+    // A directory where project.json would be set aside makes the second
+    // swap fail after db.sqlite was already replaced.
+    let blocker = dir.path().join("project.json.prev");
+    std::fs::create_dir(&blocker).unwrap();
+    std::fs::write(blocker.join("keep"), b"x").unwrap();
+
+    assert!(commit_files_atomically(&[(&db, b"new-db"), (&pj, b"new-pj")]).is_err());
+    assert_eq!(std::fs::read(&db).unwrap(), b"old-db");
+    assert_eq!(std::fs::read(&pj).unwrap(), b"old-pj");
+    assert!(!dir.path().join("db.sqlite.prev").exists());
+    assert!(!dir.path().join("db.sqlite.tmp").exists() && !dir.path().join("project.json.tmp").exists());
+  }
+
+  #[test]
+  fn validate_project_folder_reports_interrupted_save() {
+    let dir = tempfile::tempdir().unwrap();
+    let (pj, db) = project_paths(dir.path());
+    std::fs::write(&db, b"new-db").unwrap();
+    std::fs::write(dir.path().join("db.sqlite.prev"), b"old-db").unwrap();
+    std::fs::write(&pj, b"old-pj").unwrap();
+    std::fs::write(dir.path().join("project.json.tmp"), b"new-pj").unwrap();
+    let v = validate_project_folder(dir.path().to_string_lossy().to_string()).unwrap();
+    assert!(!v.ok);
+    assert_eq!(v.leftover_files.len(), 2);
+    assert!(v.error.unwrap().contains("db.sqlite.prev"));
+  }
+
+  #[test]
+  fn sync_write_refuses_a_file_changed_mid_sync() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = dir.path().join(WORKSPACE_DB_NAME);
+    std::fs::write(&db, b"read at start").unwrap();
+    let start = sha256_hex(b"read at start");
+
+    std::fs::write(&db, b"saved meanwhile").unwrap();
+    let err = write_local_db(&db, b"downloaded", Some(&start)).unwrap_err();
+    assert!(err.starts_with(SAVE_CONFLICT_ERROR), "{err}");
+    assert_eq!(std::fs::read(&db).unwrap(), b"saved meanwhile");
+
+    let current = sha256_hex(b"saved meanwhile");
+    assert_eq!(write_local_db(&db, b"downloaded", Some(&current)).unwrap(), sha256_hex(b"downloaded"));
+    assert_eq!(std::fs::read(&db).unwrap(), b"downloaded");
+
+    // A fresh device: no file at start, and none may appear meanwhile.
+    let fresh = dir.path().join("fresh.sqlite");
+    assert!(write_local_db(&fresh, b"downloaded", None).is_ok());
+  }
+
+  #[test]
+  fn save_conflict_serializes_as_object() {
+    let conflict = SaveWorkspaceError::Conflict(SaveConflict { code: SAVE_CONFLICT_ERROR, expected: "aa".to_string(), found: None });
+    assert_eq!(serde_json::to_value(&conflict).unwrap(), serde_json::json!({ "code": "SAVE_CONFLICT", "expected": "aa", "found": null }));
+    let other = SaveWorkspaceError::from("Workspace folder does not exist".to_string());
+    assert_eq!(serde_json::to_value(&other).unwrap(), serde_json::json!("Workspace folder does not exist"));
+  }
+}
@@ -0,0 +1,543 @@
+// Content-addressed sync of the workspace `media/` folder over WebDAV.
+//
+// Remote layout under the workspace root:
+//   media/<sha256>           one blob per distinct file content
+//   media/manifest.json      path -> sha256, with tombstones for deletions
+//
+// Each run is a three-way diff between the local folder, the remote manifest
+// and the manifest as of our last sync (`sync/media-state.json`), so we can
+// tell "deleted here" from "added there". Only missing blobs move. Must run
+// while holding the remote lock, like every other read-modify-write of the
+// remote folder.
+
+const REMOTE_MEDIA_DIR: &str = "media";
+const MANIFEST_FILE_NAME: &str = "manifest.json";
+const LOCAL_STATE_FILE_NAME: &str = "media-state.json";
+/// Renew the remote lock at least this often during long transfers.
+const LOCK_RENEW_INTERVAL_MS: i64 = 30_000;
+const LOCK_TTL_MS: i64 = 120_000;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct MediaEntryV1 {
+  pub sha256: String,
+  pub size: u64,
+  pub modified_at: i64,
+  pub client_id: String,
+  /// Set when the file was deleted; the entry is kept so other devices
+  /// delete their copy instead of uploading it again.
+  pub deleted_at: Option<i64>,
+}
+
+impl MediaEntryV1 {
+  fn live_sha(&self) -> Option<&str> {
+    match self.deleted_at {
+      None => Some(self.sha256.as_str()),
+      Some(_) => None,
+    }
+  }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct MediaManifestV1 {
+  pub version: u32,
+  pub format: String,
+  pub updated_at: i64,
+  /// Keyed by path relative to `media/`, always with `/` separators.
+  pub files: std::collections::BTreeMap<String, MediaEntryV1>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+struct HashCacheEntry {
+  size: u64,
+  modified_ms: i64,
+  sha256: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+struct MediaSyncStateV1 {
+  version: u32,
+  /// Per path, the remote entry as of the last sync that applied it. Paths
+  /// that failed keep their older entry, so the next sync decides again.
+  base: MediaManifestV1,
+  /// Avoids rehashing files whose size and mtime did not change.
+  hashes: std::collections::BTreeMap<String, HashCacheEntry>,
+}
+
+#[derive(serde::Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaSyncSummary {
+  uploaded: usize,
+  downloaded: usize,
+  deleted_local: usize,
+  tombstoned: usize,
+  conflicts: usize,
+  bytes_uploaded: u64,
+  bytes_downloaded: u64,
+  /// Per-file failures. Their base entry is left as it was, so the same
+  /// transfer or deletion is attempted again on the next sync; paths the
+  /// manifest marks unsafe are skipped every time.
+  errors: Vec<String>,
+}
+
+impl MediaSyncSummary {
+  pub fn failed(error: String) -> Self {
+    MediaSyncSummary { errors: vec![error], ..Default::default() }
+  }
+}
+
+struct LocalFile {
+  path: std::path::PathBuf,
+  sha256: String,
+  size: u64,
+  modified_ms: i64,
+}
+
+fn modified_ms(meta: &std::fs::Metadata) -> i64 {
+  meta
+    .modified()
+    .ok()
+    .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+    .map(|d| d.as_millis() as i64)
+    .unwrap_or(0)
+}
+
+/// Accepts only plain relative paths, so a hostile manifest cannot write
+/// outside `media/`.
+fn safe_rel_path(rel: &str) -> Option<std::path::PathBuf> {
+  if rel.is_empty() || rel.starts_with('/') || rel.contains('\\') {
+    return None;
+  }
+  let mut out = std::path::PathBuf::new();
+  for part in rel.split('/') {
+    if part.is_empty() || part == "." || part == ".." || part.contains(':') {
+      return None;
+    }
+    out.push(part);
+  }
+  Some(out)
+}
+
+fn is_valid_sha(sha: &str) -> bool {
+  sha.len() == 64 && sha.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn scan_dir(
+  dir: &std::path::Path,
+  prefix: &str,
+  cache: &std::collections::BTreeMap<String, HashCacheEntry>,
+  out: &mut std::collections::BTreeMap<String, LocalFile>,
+) -> Result<(), String> {
+  let entries = match std::fs::read_dir(dir) {
+    Ok(e) => e,
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+    Err(e) => return Err(format!("Failed reading {}: {e}", dir.display())),
+  };
+  for entry in entries.flatten() {
+    let name = entry.file_name().to_string_lossy().to_string();
+    // Hidden files and leftovers from atomic_write are not media.
+    if name.starts_with('.') || name.ends_with(".tmp") {
+      continue;
+    }
+    let rel = if prefix.is_empty() { name.clone() } else { format!("{prefix}/{name}") };
+    let path = entry.path();
+    let meta = match entry.metadata() {
+      Ok(m) => m,
+      Err(_) => continue,
+    };
+    if meta.is_dir() {
+      scan_dir(&path, &rel, cache, out)?;
+      continue;
+    }
+    if !meta.is_file() {
+      continue;
+    }
+    let size = meta.len();
+    let modified = modified_ms(&meta);
+    let sha256 = match cache.get(&rel) {
+      Some(c) if c.size == size && c.modified_ms == modified && is_valid_sha(&c.sha256) => c.sha256.clone(),
+      _ => {
+        let bytes = std::fs::read(&path).map_err(|e| format!("Failed reading {}: {e}", path.display()))?;
+        super::sha256_hex(&bytes)
+      }
+    };
+    out.insert(rel, LocalFile { path, sha256, size, modified_ms: modified });
+  }
+  Ok(())
+}
+
+struct MediaRemote<'a> {
+  client: &'a reqwest::Client,
+  auth: &'a super::webdav_auth::DavAuth,
+  dir_url: String,
+  lock_url: String,
+  client_id: String,
+  last_renew_at: i64,
+}
+
+impl MediaRemote<'_> {
+  fn blob_url(&self, sha: &str) -> String {
+    super::join_base(&self.dir_url, sha)
+  }
+
+  /// Keeps our lock alive through long transfers; a no-op when someone else
+  /// holds it (then the caller was not supposed to be here anyway).
+  async fn keep_lock(&mut self) {
+    let now = chrono::Utc::now().timestamp_millis();
+    if now - self.last_renew_at < LOCK_RENEW_INTERVAL_MS {
+      return;
+    }
+    self.last_renew_at = now;
+    if let Ok(Some(mut lock)) = super::read_remote_lock(self.client, &self.lock_url, self.auth).await {
+      if lock.client_id == self.client_id {
+        let _ = super::renew_lock(self.client, &self.lock_url, self.auth, &mut lock, LOCK_TTL_MS).await;
+      }
+    }
+  }
+}
+
+fn conflict_copy_path(conflicts_dir: &std::path::Path, rel: &std::path::Path) -> std::path::PathBuf {
+  let ts = chrono::Utc::now().format("%Y%m%d-%H%M%S");
+  conflicts_dir.join("media").join(ts.to_string()).join(rel)
+}
+
+/// Moves a local file aside instead of deleting it outright.
+fn move_aside(from: &std::path::Path, to: &std::path::Path) -> Result<(), String> {
+  if let Some(parent) = to.parent() {
+    super::ensure_dir(parent)?;
+  }
+  std::fs::rename(from, to)
+    .or_else(|_| std::fs::copy(from, to).and_then(|_| std::fs::remove_file(from)))
+    .map_err(|e| format!("Failed moving {} aside: {e}", from.display()))
+}
+
+enum Action {
+  Upload,
+  Download,
+  DeleteLocal,
+  Tombstone,
+}
+
+/// Decides what to do with one path. `local`, `remote` and `base` are the
+/// live sha256 on each side (None when missing or tombstoned).
+fn plan(local: Option<&LocalFile>, remote: Option<&MediaEntryV1>, base: Option<&str>) -> Option<(Action, bool)> {
+  let remote_sha = remote.and_then(|r| r.live_sha());
+  match (local, remote_sha) {
+    (Some(l), Some(r)) if l.sha256 == r => None,
+    (Some(l), Some(r)) => {
+      if base == Some(r) {
+        Some((Action::Upload, false))
+      } else if base == Some(l.sha256.as_str()) {
+        Some((Action::Download, false))
+      } else {
+        // Both changed: the newer edit wins, the other copy is kept.
+        let remote_newer = remote.map(|e| e.modified_at > l.modified_ms).unwrap_or(false);
+        Some((if remote_newer { Action::Download } else { Action::Upload }, true))
+      }
+    }
+    (Some(l), None) => {
+      let deleted_remotely = remote.map(|r| r.deleted_at.is_some()).unwrap_or(false);
+      if deleted_remotely && base == Some(l.sha256.as_str()) {
+        Some((Action::DeleteLocal, false))
+      } else {
+        Some((Action::Upload, false))
+      }
+    }
+    (None, Some(r)) => {
+      if base == Some(r) {
+        Some((Action::Tombstone, false))
+      } else {
+        Some((Action::Download, false))
+      }
+    }
+    (None, None) => None,
+  }
+}
+
+/// Syncs `<root>/media` with the remote workspace folder `remote_root`.
+/// The caller must hold the remote lock.
+pub async fn sync_media(
+  client: &reqwest::Client,
+  auth: &super::webdav_auth::DavAuth,
+  remote_root: &str,
+  root: &std::path::Path,
+  client_id: &str,
+) -> Result<MediaSyncSummary, String> {
+  let (_wjson, _db, _backups, media_dir) = super::workspace_paths(root);
+  let sync_dir = root.join("sync");
+  let state_path = sync_dir.join(LOCAL_STATE_FILE_NAME);
+  let state: MediaSyncStateV1 = super::read_json_file(&state_path).unwrap_or_default();
+
+  let mut local = std::collections::BTreeMap::new();
+  scan_dir(&media_dir, "", &state.hashes, &mut local)?;
+
+  let dir_url = super::join_base(remote_root, REMOTE_MEDIA_DIR);
+  super::dav_mkcol(client, &dir_url, auth).await?;
+  let manifest_url = super::join_base(&dir_url, MANIFEST_FILE_NAME);
+  let remote_manifest: MediaManifestV1 = match super::dav_get_bytes(client, &manifest_url, auth).await {
+    Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| format!("Remote media manifest is invalid: {e}"))?,
+    Err(e) if e == "NOT_FOUND" => MediaManifestV1::default(),
+    Err(e) => return Err(e),
+  };
+
+  let mut remote = MediaRemote {
+    client,
+    auth,
+    dir_url,
+    lock_url: super::join_base(remote_root, "lock.json"),
+    client_id: client_id.to_string(),
+    last_renew_at: chrono::Utc::now().timestamp_millis(),
+  };
+  let mut manifest = remote_manifest.clone();
+  let mut live_blobs: std::collections::HashSet<String> =
+    remote_manifest.files.values().filter_map(|e| e.live_sha().map(String::from)).collect();
+  let mut summary = MediaSyncSummary::default();
+  let now = chrono::Utc::now().timestamp_millis();
+  let mut base_files = state.base.files.clone();
+
+  let paths: std::collections::BTreeSet<String> = local.keys().chain(remote_manifest.files.keys()).cloned().collect();
+  for rel in paths {
+    let rel_path = match safe_rel_path(&rel) {
+      Some(p) => p,
+      None => {
+        summary.errors.push(format!("{rel}: unsafe path in manifest, skipped"));
+        continue;
+      }
+    };
+    let l = local.get(&rel);
+    let r = remote_manifest.files.get(&rel);
+    let base = state.base.files.get(&rel).and_then(|e| e.live_sha());
+    let (action, conflict) = match plan(l, r, base) {
+      Some(p) => p,
+      None => {
+        // Already in agreement, including paths deleted on both sides.
+        match r {
+          Some(r) => base_files.insert(rel, r.clone()),
+          None => base_files.remove(&rel),
+        };
+        continue;
+      }
+    };
+    if conflict {
+      summary.conflicts += 1;
+    }
+    remote.keep_lock().await;
+
+    match action {
+      Action::Upload => {
+        let l = l.unwrap();
+        // Local wins a conflict: keep the remote version next to the other
+        // conflict copies before the manifest stops pointing at it.
+        if conflict {
+          if let Some(r) = r.filter(|r| is_valid_sha(&r.sha256)) {
+            let kept = match super::dav_get_bytes(client, &remote.blob_url(&r.sha256), auth).await {
+              Ok(bytes) => super::atomic_write(&conflict_copy_path(&sync_dir.join("conflicts"), &rel_path), &bytes),
+              Err(e) => Err(e),
+            };
+            if let Err(e) = kept {
+              summary.errors.push(format!("{rel}: {e}"));
+              continue;
+            }
+          }
+        }
+        if !live_blobs.contains(&l.sha256) {
+          let bytes = match std::fs::read(&l.path) {
+            Ok(b) => b,
+            Err(e) => {
+              summary.errors.push(format!("{rel}: {e}"));
+              continue;
+            }
+          };
+          // The file changed since it was hashed; pick it up next time.
+          if super::sha256_hex(&bytes) != l.sha256 {
+            summary.errors.push(format!("{rel}: changed during sync"));
+            continue;
+          }
+          let size = bytes.len() as u64;
+          if let Err(e) = super::dav_put_bytes(client, &remote.blob_url(&l.sha256), auth, bytes, "application/octet-stream").await {
+            summary.errors.push(format!("{rel}: {e}"));
+            continue;
+          }
+          live_blobs.insert(l.sha256.clone());
+          summary.bytes_uploaded += size;
+        }
+        let entry = MediaEntryV1 { sha256: l.sha256.clone(), size: l.size, modified_at: l.modified_ms, client_id: client_id.to_string(), deleted_at: None };
+        manifest.files.insert(rel.clone(), entry.clone());
+        base_files.insert(rel, entry);
+        summary.uploaded += 1;
+      }
+      Action::Download => {
+        let r = r.unwrap();
+        if !is_valid_sha(&r.sha256) {
+          summary.errors.push(format!("{rel}: invalid sha256 in manifest"));
+          continue;
+        }
+        let bytes = match super::dav_get_bytes(client, &remote.blob_url(&r.sha256), auth).await {
+          Ok(b) => b,
+          Err(e) => {
+            summary.errors.push(format!("{rel}: {e}"));
+            continue;
+          }
+        };
+        if super::sha256_hex(&bytes) != r.sha256 {
+          summary.errors.push(format!("{rel}: downloaded sha256 mismatch"));
+          continue;
+        }
+        let target = media_dir.join(&rel_path);
+        if conflict {
+          if let Some(l) = l {
+            if let Err(e) = move_aside(&l.path, &conflict_copy_path(&sync_dir.join("conflicts"), &rel_path)) {
+              summary.errors.push(format!("{rel}: {e}"));
+              continue;
+            }
+          }
+        }
+        summary.bytes_downloaded += bytes.len() as u64;
+        if let Err(e) = super::atomic_write(&target, &bytes) {
+          summary.errors.push(format!("{rel}: {e}"));
+          continue;
+        }
+        base_files.insert(rel, r.clone());
+        summary.downloaded += 1;
+      }
+      Action::DeleteLocal => {
+        let l = l.unwrap();
+        // On failure the base keeps the live entry: the next sync sees the
+        // tombstone again and retries the deletion instead of uploading.
+        if let Err(e) = move_aside(&l.path, &sync_dir.join("media-trash").join(&rel_path)) {
+          summary.errors.push(format!("{rel}: {e}"));
+          continue;
+        }
+        if let Some(r) = r {
+          base_files.insert(rel, r.clone());
+        }
+        summary.deleted_local += 1;
+      }
+      Action::Tombstone => {
+        if let Some(entry) = manifest.files.get_mut(&rel) {
+          entry.deleted_at = Some(now);
+          entry.client_id = client_id.to_string();
+          base_files.insert(rel, entry.clone());
+        }
+        summary.tombstoned += 1;
+      }
+    }
+  }
+
+  if manifest != remote_manifest {
+    manifest.version = 1;
+    manifest.format = super::REMOTE_WORKSPACE_FORMAT.to_string();
+    manifest.updated_at = now;
+    let bytes = serde_json::to_vec_pretty(&manifest).map_err(|e| format!("Media manifest encode failed: {e}"))?;
+    super::dav_put_bytes(client, &manifest_url, auth, bytes, "application/json").await?;
+
+    // Blobs no live entry points to any more. Best effort: a leftover blob
+    // only costs space.
+    let still_live: std::collections::HashSet<&str> = manifest.files.values().filter_map(|e| e.live_sha()).collect();
+    for sha in remote_manifest.files.values().filter_map(|e| e.live_sha()) {
+      if !still_live.contains(sha) && is_valid_sha(sha) {
+        let _ = super::dav_delete(client, &remote.blob_url(sha), auth).await;
+      }
+    }
+  }
+
+  // Rescan so the hash cache matches what is on disk now.
+  let mut after = std::collections::BTreeMap::new();
+  scan_dir(&media_dir, "", &state.hashes, &mut after)?;
+  let new_state = MediaSyncStateV1 {
+    version: 1,
+    base: MediaManifestV1 { files: base_files, ..manifest },
+    hashes: after
+      .into_iter()
+      .map(|(rel, f)| (rel, HashCacheEntry { size: f.size, modified_ms: f.modified_ms, sha256: f.sha256 }))
+      .collect(),
+  };
+  super::ensure_dir(&sync_dir)?;
+  let txt = serde_json::to_vec_pretty(&new_state).map_err(|e| format!("Media state encode failed: {e}"))?;
+  super::atomic_write(&state_path, &txt)?;
+  Ok(summary)
+}
+
+/// Syncs only the media folder, taking the remote lock for the duration.
+#[tauri::command]
+pub async fn webdav_sync_media(args: super::WebDavRemoteArgs, workspace_path: String) -> Result<MediaSyncSummary, String> {
+  let remote = args.connect()?;
+  if args.client_id.trim().is_empty() {
+    return Err("Missing client id".to_string());
+  }
+  let root = std::path::PathBuf::from(&workspace_path);
+  let _guard = super::sync_scheduler::LOCAL_SYNC_GUARD.lock().await;
+
+  let lock_url = remote.url("lock.json");
+  if super::acquire_lock(&remote.client, &lock_url, &remote.auth, &args.client_id, "workspace", LOCK_TTL_MS).await?.is_none() {
+    return Err("Remote locked by another client".to_string());
+  }
+  let result = sync_media(&remote.client, &remote.auth, &remote.root, &root, &args.client_id).await;
+  let _ = super::dav_delete(&remote.client, &lock_url, &remote.auth).await;
+  result
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const A: &str = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+  const B: &str = "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+  const C: &str = "cccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccc";
+
+  fn local(sha: &str, modified_ms: i64) -> LocalFile {
+    LocalFile { path: std::path::PathBuf::from("x"), sha256: sha.to_string(), size: 1, modified_ms }
+  }
+
+  fn remote(sha: &str, modified_at: i64, deleted: bool) -> MediaEntryV1 {
+    MediaEntryV1 { sha256: sha.to_string(), size: 1, modified_at, client_id: "other".to_string(), deleted_at: if deleted { Some(modified_at) } else { None } }
+  }
+
+  fn action(p: Option<(Action, bool)>) -> Option<(&'static str, bool)> {
+    p.map(|(a, c)| {
+      let name = match a {
+        Action::Upload => "upload",
+        Action::Download => "download",
+        Action::DeleteLocal => "delete_local",
+        Action::Tombstone => "tombstone",
+      };
+      (name, c)
+    })
+  }
+
+  #[test]
+  fn plan_leaves_identical_files_alone() {
+    assert_eq!(action(plan(Some(&local(A, 1)), Some(&remote(A, 1, false)), None)), None);
+    assert_eq!(action(plan(None, Some(&remote(A, 1, true)), Some(A))), None);
+    assert_eq!(action(plan(None, None, Some(A))), None);
+  }
+
+  #[test]
+  fn plan_moves_the_side_that_changed() {
+    assert_eq!(action(plan(Some(&local(B, 1)), Some(&remote(A, 1, false)), Some(A))), Some(("upload", false)));
+    assert_eq!(action(plan(Some(&local(A, 1)), Some(&remote(B, 1, false)), Some(A))), Some(("download", false)));
+    assert_eq!(action(plan(Some(&local(A, 1)), None, None)), Some(("upload", false)));
+    assert_eq!(action(plan(None, Some(&remote(A, 1, false)), None)), Some(("download", false)));
+  }
+
+  #[test]
+  fn plan_propagates_deletions() {
+    assert_eq!(action(plan(None, Some(&remote(A, 1, false)), Some(A))), Some(("tombstone", false)));
+    assert_eq!(action(plan(Some(&local(A, 1)), Some(&remote(A, 2, true)), Some(A))), Some(("delete_local", false)));
+    // Edited here after the other device deleted it: the edit is kept.
+    assert_eq!(action(plan(Some(&local(B, 3)), Some(&remote(A, 2, true)), Some(A))), Some(("upload", false)));
+  }
+
+  #[test]
+  fn plan_resolves_conflicts_by_modification_time() {
+    assert_eq!(action(plan(Some(&local(B, 10)), Some(&remote(C, 20, false)), Some(A))), Some(("download", true)));
+    assert_eq!(action(plan(Some(&local(B, 30)), Some(&remote(C, 20, false)), Some(A))), Some(("upload", true)));
+  }
+}
@@ -0,0 +1,322 @@
+// Credentials kept on the Rust side, out of database.sqlite and its backups.
+//
+// Entries are keyed by workspace id and account name (e.g. "webdav", "smtp"),
+// so two workspaces on the same machine never share a password. The OS store
+// is used when available (Keychain, Credential Manager, Secret Service); on
+// headless Linux without a Secret Service we fall back to an AES-GCM encrypted
+// file in the app data dir. Its key is bound to this machine (`machine-id`) and
+// a random per-install salt, so a copied file is useless elsewhere; it does not
+// protect against someone who can already read the user's home directory.
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use base64::Engine;
+use sha2::Digest;
+
+const KEYRING_SERVICE: &str = "com.rentikpro.app";
+const FALLBACK_FILE_NAME: &str = "secrets.enc.json";
+const FALLBACK_SALT_NAME: &str = "secrets.salt";
+/// The `secret_*` commands only reach accounts under this prefix, so the
+/// webview can never read the database key or the sync/mail passwords.
+const UI_ACCOUNT_PREFIX: &str = "ui:";
+
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+#[serde(default)]
+struct FallbackFileV1 {
+  version: u32,
+  entries: std::collections::BTreeMap<String, FallbackEntry>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+#[serde(default)]
+struct FallbackEntry {
+  nonce: String,
+  ciphertext: String,
+}
+
+// Serialises read-modify-write of the fallback file.
+static FALLBACK_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+#[derive(Clone, Copy, PartialEq)]
+enum Backend {
+  Keychain,
+  File,
+}
+
+fn secret_key(workspace_id: &str, account: &str) -> Result<String, String> {
+  let workspace_id = workspace_id.trim();
+  let account = account.trim();
+  if workspace_id.is_empty() || account.is_empty() {
+    return Err("Secret key requires a workspace id and an account".to_string());
+  }
+  Ok(format!("{workspace_id}:{account}"))
+}
+
+fn entry(key: &str) -> Result<keyring::Entry, String> {
+  keyring::Entry::new(KEYRING_SERVICE, key).map_err(|e| format!("Keychain unavailable: {e}"))
+}
+
+/// Probed once per process. `RENTIKPRO_SECRET_STORE=file` forces the fallback.
+fn backend() -> Backend {
+  static BACKEND: std::sync::OnceLock<Backend> = std::sync::OnceLock::new();
+  *BACKEND.get_or_init(|| {
+    if std::env::var("RENTIKPRO_SECRET_STORE").map(|v| v == "file").unwrap_or(false) {
+      return Backend::File;
+    }
+    if !cfg!(target_os = "linux") {
+      return Backend::Keychain;
+    }
+    match entry("__probe__").map(|e| e.get_password()) {
+      Ok(Ok(_)) | Ok(Err(keyring::Error::NoEntry)) => Backend::Keychain,
+      _ => Backend::File,
+    }
+  })
+}
+
+fn fallback_dir() -> Result<std::path::PathBuf, String> {
+  let data_home = std::env::var_os("XDG_DATA_HOME")
+    .map(std::path::PathBuf::from)
+    .filter(|p| p.is_absolute())
+    .or_else(|| std::env::var_os("HOME").map(|h| std::path::PathBuf::from(h).join(".local").join("share")))
+    .ok_or_else(|| "Cannot locate a data directory for the secret store".to_string())?;
+  Ok(data_home.join(KEYRING_SERVICE))
+}
+
+#[cfg(unix)]
+fn restrict_permissions(path: &std::path::Path) {
+  use std::os::unix::fs::PermissionsExt;
+  let _ = std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600));
+}
+
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &std::path::Path) {}
+
+/// Writes a fresh salt only if none exists yet. Losing the existing salt
+/// would make every stored secret undecryptable, so it is never replaced.
+fn create_salt(salt_path: &std::path::Path) -> Result<Vec<u8>, String> {
+  use std::io::Write;
+  let fresh = aes_gcm::Aes256Gcm::generate_key(OsRng).to_vec();
+  let mut f = match std::fs::OpenOptions::new().write(true).create_new(true).open(salt_path) {
+    Ok(f) => f,
+    // Another process got there first; use its salt.
+    Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => return read_salt(salt_path),
+    Err(e) => return Err(format!("Failed creating secret store salt: {e}")),
+  };
+  restrict_permissions(salt_path);
+  let written = f.write_all(&fresh).and_then(|_| f.sync_all());
+  if let Err(e) = written {
+    let _ = std::fs::remove_file(salt_path);
+    return Err(format!("Failed writing secret store salt: {e}"));
+  }
+  if let Some(dir) = salt_path.parent() {
+    super::sync_dir(dir);
+  }
+  Ok(fresh)
+}
+
+fn read_salt(salt_path: &std::path::Path) -> Result<Vec<u8>, String> {
+  match std::fs::read(salt_path) {
+    Ok(b) if b.len() == 32 => Ok(b),
+    Ok(_) => Err(format!("Secret store salt {} is corrupt; stored secrets cannot be decrypted", salt_path.display())),
+    Err(e) => Err(format!("Failed reading secret store salt {}: {e}", salt_path.display())),
+  }
+}
+
+fn machine_id() -> String {
+  std::fs::read_to_string("/etc/machine-id")
+    .or_else(|_| std::fs::read_to_string("/var/lib/dbus/machine-id"))
+    .unwrap_or_default()
+}
+
+/// Callers hold `FALLBACK_LOCK`. A salt is only created when `create` is set,
+/// none exists yet and no stored secret could depend on a lost one.
+fn fallback_cipher(dir: &std::path::Path, machine_id: &str, create: bool) -> Result<aes_gcm::Aes256Gcm, String> {
+  let salt_path = dir.join(FALLBACK_SALT_NAME);
+  let salt = if create && !salt_path.exists() && read_fallback(dir).entries.is_empty() {
+    create_salt(&salt_path)?
+  } else {
+    read_salt(&salt_path)?
+  };
+  let mut hasher = sha2::Sha256::new();
+  hasher.update(b"rentikpro-secrets-v1");
+  hasher.update(machine_id.trim().as_bytes());
+  hasher.update(&salt);
+  let key = hasher.finalize();
+  aes_gcm::Aes256Gcm::new_from_slice(&key).map_err(|e| format!("Secret store key invalid: {e}"))
+}
+
+fn read_fallback(dir: &std::path::Path) -> FallbackFileV1 {
+  super::read_json_file(&dir.join(FALLBACK_FILE_NAME)).unwrap_or_default()
+}
+
+fn write_fallback(dir: &std::path::Path, file: &mut FallbackFileV1) -> Result<(), String> {
+  file.version = 1;
+  let path = dir.join(FALLBACK_FILE_NAME);
+  let txt = serde_json::to_vec_pretty(file).map_err(|e| format!("Secret store encode failed: {e}"))?;
+  super::atomic_write(&path, &txt)?;
+  restrict_permissions(&path);
+  Ok(())
+}
+
+fn file_get(dir: &std::path::Path, machine_id: &str, key: &str) -> Result<Option<String>, String> {
+  let _guard = FALLBACK_LOCK.lock().unwrap();
+  let file = read_fallback(dir);
+  let e = match file.entries.get(key) {
+    Some(e) => e,
+    None => return Ok(None),
+  };
+  let nonce = base64::engine::general_purpose::STANDARD
+    .decode(&e.nonce)
+    .map_err(|err| format!("Secret store entry corrupt: {err}"))?;
+  let ct = base64::engine::general_purpose::STANDARD
+    .decode(&e.ciphertext)
+    .map_err(|err| format!("Secret store entry corrupt: {err}"))?;
+  if nonce.len() != 12 {
+    return Err("Secret store entry corrupt: bad nonce".to_string());
+  }
+  let plain = fallback_cipher(dir, machine_id, false)?
+    .decrypt(aes_gcm::Nonce::from_slice(&nonce), Payload { msg: &ct, aad: key.as_bytes() })
+    .map_err(|_| "Secret store entry cannot be decrypted on this machine".to_string())?;
+  String::from_utf8(plain).map(Some).map_err(|e| format!("Secret store entry corrupt: {e}"))
+}
+
+fn file_set(dir: &std::path::Path, machine_id: &str, key: &str, value: &str) -> Result<(), String> {
+  let _guard = FALLBACK_LOCK.lock().unwrap();
+  super::ensure_dir(dir)?;
+  let cipher = fallback_cipher(dir, machine_id, true)?;
+  let nonce = aes_gcm::Aes256Gcm::generate_nonce(&mut OsRng);
+  let ct = cipher
+    .encrypt(&nonce, Payload { msg: value.as_bytes(), aad: key.as_bytes() })
+    .map_err(|_| "Secret encryption failed".to_string())?;
+  let mut file = read_fallback(dir);
+  file.entries.insert(
+    key.to_string(),
+    FallbackEntry {
+      nonce: base64::engine::general_purpose::STANDARD.encode(nonce),
+      ciphertext: base64::engine::general_purpose::STANDARD.encode(ct),
+    },
+  );
+  write_fallback(dir, &mut file)
+}
+
+fn file_delete(dir: &std::path::Path, key: &str) -> Result<(), String> {
+  let _guard = FALLBACK_LOCK.lock().unwrap();
+  let mut file = read_fallback(dir);
+  if file.entries.remove(key).is_some() {
+    write_fallback(dir, &mut file)?;
+  }
+  Ok(())
+}
+
+pub fn get_secret(workspace_id: &str, account: &str) -> Result<Option<String>, String> {
+  let key = secret_key(workspace_id, account)?;
+  if backend() == Backend::File {
+    return file_get(&fallback_dir()?, &machine_id(), &key);
+  }
+  match entry(&key)?.get_password() {
+    Ok(v) => Ok(Some(v)),
+    Err(keyring::Error::NoEntry) => Ok(None),
+    Err(e) => Err(format!("Keychain read failed: {e}")),
+  }
+}
+
+pub fn set_secret(workspace_id: &str, account: &str, value: &str) -> Result<(), String> {
+  let key = secret_key(workspace_id, account)?;
+  if backend() == Backend::File {
+    return file_set(&fallback_dir()?, &machine_id(), &key, value);
+  }
+  entry(&key)?
+    .set_password(value)
+    .map_err(|e| format!("Keychain write failed: {e}"))
+}
+
+pub fn delete_secret(workspace_id: &str, account: &str) -> Result<(), String> {
+  let key = secret_key(workspace_id, account)?;
+  if backend() == Backend::File {
+    return file_delete(&fallback_dir()?, &key);
+  }
+  match entry(&key)?.delete_credential() {
+    Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+    Err(e) => Err(format!("Keychain delete failed: {e}")),
+  }
+}
+
+/// `account` as seen by the webview, inside `UI_ACCOUNT_PREFIX`.
+fn ui_account(account: &str) -> Result<String, String> {
+  let account = account.trim();
+  if account.is_empty() {
+    return Err("Secret key requires a workspace id and an account".to_string());
+  }
+  Ok(format!("{UI_ACCOUNT_PREFIX}{account}"))
+}
+
+#[tauri::command]
+pub fn secret_set(workspace_id: String, account: String, value: String) -> Result<(), String> {
+  set_secret(&workspace_id, &ui_account(&account)?, &value)
+}
+
+#[tauri::command]
+pub fn secret_get(workspace_id: String, account: String) -> Result<Option<String>, String> {
+  get_secret(&workspace_id, &ui_account(&account)?)
+}
+
+#[tauri::command]
+pub fn secret_delete(workspace_id: String, account: String) -> Result<(), String> {
+  delete_secret(&workspace_id, &ui_account(&account)?)
+}
+
+/// "keychain" or "file", so settings can tell the user where secrets live.
+#[tauri::command]
+pub fn secret_store_backend() -> String {
+  match backend() {
+    Backend::Keychain => "keychain".to_string(),
+    Backend::File => "file".to_string(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn ui_accounts_cannot_reach_internal_ones() {
+    for internal in [super::super::db_crypto::KEY_SECRET_ACCOUNT, super::super::webdav_auth::SECRET_ACCOUNT, super::super::smtp::SECRET_ACCOUNT, "imap:a1"] {
+      assert_ne!(ui_account(internal).unwrap(), internal);
+    }
+    assert!(ui_account("  ").is_err());
+  }
+
+  #[test]
+  fn file_store_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    file_set(dir.path(), "machine-a", "ws1:webdav", "hunter2").unwrap();
+    assert_eq!(file_get(dir.path(), "machine-a", "ws1:webdav").unwrap().as_deref(), Some("hunter2"));
+    assert_eq!(file_get(dir.path(), "machine-a", "ws2:webdav").unwrap(), None);
+    let on_disk = std::fs::read_to_string(dir.path().join(FALLBACK_FILE_NAME)).unwrap();
+    assert!(!on_disk.contains("hunter2"));
+
+    file_delete(dir.path(), "ws1:webdav").unwrap();
+    assert_eq!(file_get(dir.path(), "machine-a", "ws1:webdav").unwrap(), None);
+  }
+
+  #[test]
+  fn file_store_is_bound_to_the_machine() {
+    let dir = tempfile::tempdir().unwrap();
+    file_set(dir.path(), "machine-a", "ws1:smtp", "secret").unwrap();
+    let err = file_get(dir.path(), "machine-b", "ws1:smtp").unwrap_err();
+    assert!(err.contains("cannot be decrypted on this machine"), "{err}");
+  }
+
+  #[test]
+  fn file_store_never_regenerates_the_salt() {
+    let dir = tempfile::tempdir().unwrap();
+    file_set(dir.path(), "m", "ws1:a", "one").unwrap();
+    let salt = std::fs::read(dir.path().join(FALLBACK_SALT_NAME)).unwrap();
+    file_set(dir.path(), "m", "ws1:b", "two").unwrap();
+    assert_eq!(std::fs::read(dir.path().join(FALLBACK_SALT_NAME)).unwrap(), salt);
+
+    // With secrets stored, a lost salt is an error, not a fresh start.
+    std::fs::remove_file(dir.path().join(FALLBACK_SALT_NAME)).unwrap();
+    assert!(file_set(dir.path(), "m", "ws1:c", "three").is_err());
+    assert!(!dir.path().join(FALLBACK_SALT_NAME).exists());
+  }
+}
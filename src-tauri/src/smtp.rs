@@ -0,0 +1,259 @@
+// Outgoing mail over SMTP (`send_email`).
+//
+// The password comes from the secret store ("smtp" account of the workspace)
+// unless the caller passes one explicitly, e.g. to test settings before saving.
+// Attachments are referenced by path relative to the workspace `media/` folder
+// and never leave it.
+
+use lettre::AsyncTransport;
+
+pub const SECRET_ACCOUNT: &str = "smtp";
+
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SmtpSettings {
+  pub host: String,
+  #[serde(default)]
+  pub port: Option<u16>,
+  /// "starttls" (default), "tls" (implicit TLS, usually 465) or "none".
+  #[serde(default)]
+  pub security: Option<String>,
+  #[serde(default)]
+  pub user: String,
+  #[serde(default)]
+  pub pass: Option<String>,
+  /// "plain" or "login"; both are offered to the server when unset.
+  #[serde(default)]
+  pub auth_mechanism: Option<String>,
+  #[serde(default)]
+  pub timeout_secs: Option<u64>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SendEmailArgs {
+  pub smtp: SmtpSettings,
+  #[serde(default)]
+  pub workspace_id: Option<String>,
+  /// Workspace folder; required when `attachments` is not empty.
+  #[serde(default)]
+  pub workspace_path: Option<String>,
+  pub from: String,
+  pub to: Vec<String>,
+  #[serde(default)]
+  pub cc: Vec<String>,
+  #[serde(default)]
+  pub bcc: Vec<String>,
+  #[serde(default)]
+  pub reply_to: Option<String>,
+  pub subject: String,
+  #[serde(default)]
+  pub text_body: Option<String>,
+  #[serde(default)]
+  pub html_body: Option<String>,
+  /// Paths relative to `media/`.
+  #[serde(default)]
+  pub attachments: Vec<String>,
+}
+
+#[derive(serde::Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SendEmailResult {
+  pub success: bool,
+  /// SMTP reply code of the final answer, e.g. 250 or 550.
+  pub code: Option<u16>,
+  pub message: Option<String>,
+  pub error: Option<String>,
+  /// Transient failures (4xx, network) are worth retrying; 5xx are not.
+  pub retryable: bool,
+  pub message_id: Option<String>,
+}
+
+fn parse_mailbox(field: &str, value: &str) -> Result<lettre::message::Mailbox, String> {
+  value
+    .trim()
+    .parse::<lettre::message::Mailbox>()
+    .map_err(|e| format!("Invalid {field} address '{value}': {e}"))
+}
+
+fn content_type_for(path: &std::path::Path) -> &'static str {
+  let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+  match ext.as_str() {
+    "pdf" => "application/pdf",
+    "png" => "image/png",
+    "jpg" | "jpeg" => "image/jpeg",
+    "gif" => "image/gif",
+    "webp" => "image/webp",
+    "svg" => "image/svg+xml",
+    "txt" => "text/plain",
+    "csv" => "text/csv",
+    "html" | "htm" => "text/html",
+    "ics" => "text/calendar",
+    "json" => "application/json",
+    "zip" => "application/zip",
+    "doc" => "application/msword",
+    "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    "xls" => "application/vnd.ms-excel",
+    "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    _ => "application/octet-stream",
+  }
+}
+
+/// Resolves `rel` inside `media/`, rejecting anything that escapes it.
+fn media_file(workspace_path: &str, rel: &str) -> Result<std::path::PathBuf, String> {
+  let (_wjson, _db, _backups, media) = super::workspace_paths(std::path::Path::new(workspace_path));
+  let media = media
+    .canonicalize()
+    .map_err(|e| format!("Media folder not available: {e}"))?;
+  let file = media
+    .join(rel.trim_start_matches(['/', '\\']))
+    .canonicalize()
+    .map_err(|e| format!("Attachment {rel} not found: {e}"))?;
+  if !file.starts_with(&media) || !file.is_file() {
+    return Err(format!("Attachment {rel} is not a file inside media/"));
+  }
+  Ok(file)
+}
+
+fn build_message(args: &SendEmailArgs) -> Result<lettre::Message, String> {
+  if args.to.is_empty() {
+    return Err("At least one recipient is required".to_string());
+  }
+  let mut builder = lettre::Message::builder()
+    .from(parse_mailbox("from", &args.from)?)
+    .subject(args.subject.clone());
+  for to in &args.to {
+    builder = builder.to(parse_mailbox("to", to)?);
+  }
+  for cc in &args.cc {
+    builder = builder.cc(parse_mailbox("cc", cc)?);
+  }
+  for bcc in &args.bcc {
+    builder = builder.bcc(parse_mailbox("bcc", bcc)?);
+  }
+  if let Some(reply_to) = args.reply_to.as_deref().filter(|s| !s.trim().is_empty()) {
+    builder = builder.reply_to(parse_mailbox("reply-to", reply_to)?);
+  }
+
+  use lettre::message::{Attachment, MultiPart, SinglePart};
+  let text = args.text_body.clone().filter(|s| !s.is_empty());
+  let html = args.html_body.clone().filter(|s| !s.is_empty());
+  let body = match (text, html) {
+    (Some(t), Some(h)) => MultiPart::alternative_plain_html(t, h),
+    (None, Some(h)) => MultiPart::mixed().singlepart(SinglePart::html(h)),
+    (Some(t), None) => MultiPart::mixed().singlepart(SinglePart::plain(t)),
+    (None, None) => MultiPart::mixed().singlepart(SinglePart::plain(String::new())),
+  };
+
+  if args.attachments.is_empty() {
+    return builder.multipart(body).map_err(|e| format!("Failed building message: {e}"));
+  }
+  let workspace_path = args
+    .workspace_path
+    .as_deref()
+    .ok_or_else(|| "Attachments require a workspace path".to_string())?;
+  let mut mixed = MultiPart::mixed().multipart(body);
+  for rel in &args.attachments {
+    let path = media_file(workspace_path, rel)?;
+    let bytes = std::fs::read(&path).map_err(|e| format!("Failed reading attachment {rel}: {e}"))?;
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| rel.clone());
+    let content_type = lettre::message::header::ContentType::parse(content_type_for(&path))
+      .map_err(|e| format!("Invalid content type for {rel}: {e}"))?;
+    mixed = mixed.singlepart(Attachment::new(name).body(bytes, content_type));
+  }
+  builder.multipart(mixed).map_err(|e| format!("Failed building message: {e}"))
+}
+
+fn resolve_password(settings: &SmtpSettings, workspace_id: Option<&str>) -> Result<Option<String>, String> {
+  if let Some(pass) = settings.pass.clone().filter(|p| !p.is_empty()) {
+    return Ok(Some(pass));
+  }
+  match workspace_id.filter(|id| !id.trim().is_empty()) {
+    Some(id) => super::secrets::get_secret(id, SECRET_ACCOUNT),
+    None => Ok(None),
+  }
+}
+
+pub fn build_transport(settings: &SmtpSettings, workspace_id: Option<&str>) -> Result<lettre::AsyncSmtpTransport<lettre::Tokio1Executor>, String> {
+  use lettre::transport::smtp::authentication::{Credentials, Mechanism};
+  use lettre::transport::smtp::client::{Tls, TlsParameters};
+
+  let host = settings.host.trim();
+  if host.is_empty() {
+    return Err("SMTP host is required".to_string());
+  }
+  let security = settings.security.as_deref().unwrap_or("starttls").to_ascii_lowercase();
+  let tls_params = || TlsParameters::new(host.to_string()).map_err(|e| format!("TLS setup failed: {e}"));
+  let (tls, default_port) = match security.as_str() {
+    "tls" | "ssl" => (Tls::Wrapper(tls_params()?), 465),
+    "starttls" => (Tls::Required(tls_params()?), 587),
+    "none" => (Tls::None, 25),
+    other => return Err(format!("Unknown SMTP security mode: {other}")),
+  };
+
+  let mut builder = lettre::AsyncSmtpTransport::<lettre::Tokio1Executor>::builder_dangerous(host)
+    .port(settings.port.unwrap_or(default_port))
+    .tls(tls)
+    .timeout(Some(std::time::Duration::from_secs(settings.timeout_secs.unwrap_or(30))));
+
+  if !settings.user.trim().is_empty() {
+    let pass = resolve_password(settings, workspace_id)?.ok_or_else(|| "SMTP password is not set".to_string())?;
+    builder = builder.credentials(Credentials::new(settings.user.trim().to_string(), pass));
+    let mechanisms = match settings.auth_mechanism.as_deref().map(|m| m.to_ascii_lowercase()) {
+      Some(m) if m == "plain" => vec![Mechanism::Plain],
+      Some(m) if m == "login" => vec![Mechanism::Login],
+      Some(m) => return Err(format!("Unsupported SMTP auth mechanism: {m}")),
+      None => vec![Mechanism::Plain, Mechanism::Login],
+    };
+    builder = builder.authentication(mechanisms);
+  }
+  Ok(builder.build())
+}
+
+fn failure(error: String, code: Option<u16>, retryable: bool) -> SendEmailResult {
+  SendEmailResult { success: false, code, message: None, error: Some(error), retryable, message_id: None }
+}
+
+pub async fn send(args: &SendEmailArgs) -> SendEmailResult {
+  let message = match build_message(args) {
+    Ok(m) => m,
+    Err(e) => return failure(e, None, false),
+  };
+  let message_id = message
+    .headers()
+    .get_raw("Message-ID")
+    .map(|v| v.to_string());
+  let transport = match build_transport(&args.smtp, args.workspace_id.as_deref()) {
+    Ok(t) => t,
+    Err(e) => return failure(e, None, false),
+  };
+
+  match transport.send(message).await {
+    Ok(resp) => SendEmailResult {
+      success: true,
+      code: Some(u16::from(resp.code())),
+      message: Some(resp.message().collect::<Vec<_>>().join("\n")),
+      error: None,
+      retryable: false,
+      message_id,
+    },
+    Err(e) => {
+      let code = e.status().map(u16::from);
+      // Permanent 5xx rejections will fail again; timeouts, TLS or network
+      // trouble and 4xx replies may not.
+      let retryable = !e.is_permanent();
+      failure(format!("SMTP send failed: {e}"), code, retryable)
+    }
+  }
+}
+
+/// Stores the SMTP password of a workspace in the secret store.
+#[tauri::command]
+pub fn smtp_set_password(workspace_id: String, pass: String) -> Result<(), String> {
+  super::secrets::set_secret(&workspace_id, SECRET_ACCOUNT, &pass)
+}
+
+#[tauri::command]
+pub async fn send_email(args: SendEmailArgs) -> Result<SendEmailResult, String> {
+  Ok(send(&args).await)
+}
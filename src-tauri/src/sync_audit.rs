@@ -0,0 +1,108 @@
+// Remote `audit.log` and manual lock overrides.
+//
+// The log is newline-delimited JSON next to `state.json`. WebDAV has no append,
+// so entries are added with GET + PUT; good enough for the rare admin actions
+// recorded here.
+
+pub const AUDIT_LOG_NAME: &str = "audit.log";
+
+#[derive(serde::Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct AuditActor {
+  client_id: String,
+  device_name: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AuditEntry {
+  at: i64,
+  action: String,
+  by: AuditActor,
+  lock_holder: Option<AuditActor>,
+  lock: Option<super::LockFileV1>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BreakLockResult {
+  lock: Option<super::LockFileV1>,
+  holder_name: Option<String>,
+  expired: bool,
+  removed: bool,
+}
+
+async fn append_audit_entry(remote: &super::WebDavRemote, entry: &AuditEntry) -> Result<(), String> {
+  let url = remote.url(AUDIT_LOG_NAME);
+  let mut bytes = match super::dav_get_bytes(&remote.client, &url, &remote.auth).await {
+    Ok(b) => b,
+    Err(e) if e == "NOT_FOUND" => vec![],
+    Err(e) => return Err(e),
+  };
+  if !bytes.is_empty() && !bytes.ends_with(b"\n") {
+    bytes.push(b'\n');
+  }
+  let line = serde_json::to_vec(entry).map_err(|e| format!("Audit encode failed: {e}"))?;
+  bytes.extend_from_slice(&line);
+  bytes.push(b'\n');
+  super::dav_put_bytes(&remote.client, &url, &remote.auth, bytes, "application/x-ndjson").await
+}
+
+async fn device_name_of(remote: &super::WebDavRemote, client_id: &str) -> Option<String> {
+  let devices = super::sync_devices::read_devices(&remote.client, &remote.url(super::sync_devices::DEVICES_FILE_NAME), &remote.auth)
+    .await
+    .ok()?;
+  devices.devices.get(client_id).map(|d| d.name.clone()).filter(|n| !n.is_empty())
+}
+
+/// Records who removes whose lock in `audit.log`, then deletes `lock.json`.
+/// The entry goes first so a failed append leaves the lock in place rather
+/// than reporting an error for a removal that already happened unrecorded.
+pub async fn force_remove_lock(remote: &super::WebDavRemote, lock: super::LockFileV1, by_client_id: &str, action: &str) -> Result<(), String> {
+  let entry = AuditEntry {
+    at: chrono::Utc::now().timestamp_millis(),
+    action: action.to_string(),
+    by: AuditActor {
+      client_id: by_client_id.to_string(),
+      device_name: device_name_of(remote, by_client_id).await.or_else(|| Some(super::sync_devices::local_device_name())),
+    },
+    lock_holder: Some(AuditActor {
+      client_id: lock.client_id.clone(),
+      device_name: device_name_of(remote, &lock.client_id).await,
+    }),
+    lock: Some(lock),
+  };
+  append_audit_entry(remote, &entry).await?;
+  super::dav_delete(&remote.client, &remote.url("lock.json"), &remote.auth).await
+}
+
+/// Shows the current lock holder and, with `confirm`, removes the lock.
+///
+/// With `confirm`, `expected_client_id` must echo the holder shown to the
+/// user, so a lock that changed hands in the meantime is not broken by
+/// accident.
+#[tauri::command]
+pub async fn webdav_break_lock(args: super::WebDavRemoteArgs, confirm: bool, expected_client_id: Option<String>) -> Result<BreakLockResult, String> {
+  let remote = args.connect()?;
+  let lock = match super::read_remote_lock(&remote.client, &remote.url("lock.json"), &remote.auth).await? {
+    Some(lock) => lock,
+    None => return Ok(BreakLockResult { lock: None, holder_name: None, expired: false, removed: false }),
+  };
+
+  let holder_name = device_name_of(&remote, &lock.client_id).await;
+  let expired = chrono::Utc::now().timestamp_millis() >= lock.expires_at;
+  if !confirm {
+    return Ok(BreakLockResult { lock: Some(lock), holder_name, expired, removed: false });
+  }
+
+  match expected_client_id.as_deref() {
+    None => return Err("Missing expected lock holder; show the lock before breaking it".to_string()),
+    Some(expected) if expected != lock.client_id => {
+      return Err("Lock holder changed since it was shown; review it again before breaking".to_string());
+    }
+    Some(_) => {}
+  }
+
+  force_remove_lock(&remote, lock.clone(), &args.client_id, "break_lock").await?;
+  Ok(BreakLockResult { lock: Some(lock), holder_name, expired, removed: true })
+}
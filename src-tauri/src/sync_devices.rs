@@ -0,0 +1,172 @@
+// Registry of the devices that sync a workspace (`devices.json` on the remote).
+//
+// `SyncStateV1` and `LockFileV1` only carry an opaque `client_id`; this file
+// maps it to something a person recognises ("Recepción PC", "MacBook de Ana").
+
+pub const DEVICES_FILE_NAME: &str = "devices.json";
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct DeviceEntryV1 {
+  pub client_id: String,
+  pub name: String,
+  pub os: String,
+  pub app_version: String,
+  pub first_seen_at: i64,
+  pub last_seen_at: i64,
+  pub last_sync_at: Option<i64>,
+  pub last_sync_ok: Option<bool>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct DevicesFileV1 {
+  pub version: u32,
+  pub format: String,
+  pub devices: std::collections::BTreeMap<String, DeviceEntryV1>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceInfo {
+  #[serde(flatten)]
+  entry: DeviceEntryV1,
+  is_current: bool,
+  holds_lock: bool,
+  lock_expires_at: Option<i64>,
+}
+
+pub fn local_device_name() -> String {
+  gethostname::gethostname()
+    .into_string()
+    .ok()
+    .map(|s| s.trim().to_string())
+    .filter(|s| !s.is_empty())
+    .unwrap_or_else(|| "Unknown device".to_string())
+}
+
+fn local_os() -> String {
+  format!("{} {}", std::env::consts::OS, std::env::consts::ARCH)
+}
+
+pub async fn read_devices(client: &reqwest::Client, devices_url: &str, auth: &super::webdav_auth::DavAuth) -> Result<DevicesFileV1, String> {
+  match super::dav_get_bytes(client, devices_url, auth).await {
+    Ok(bytes) => Ok(serde_json::from_slice::<DevicesFileV1>(&bytes).unwrap_or_default()),
+    Err(e) if e == "NOT_FOUND" => Ok(DevicesFileV1::default()),
+    Err(e) => Err(e),
+  }
+}
+
+async fn write_devices(client: &reqwest::Client, devices_url: &str, auth: &super::webdav_auth::DavAuth, file: &mut DevicesFileV1) -> Result<(), String> {
+  file.version = 1;
+  file.format = super::REMOTE_WORKSPACE_FORMAT.to_string();
+  let bytes = serde_json::to_vec_pretty(file).map_err(|e| format!("Devices encode failed: {e}"))?;
+  super::dav_put_bytes(client, devices_url, auth, bytes, "application/json").await
+}
+
+/// Records that `client_id` just talked to the remote. Must be called while
+/// holding the remote lock, since `devices.json` is read-modify-write.
+pub async fn record_device_sync(
+  client: &reqwest::Client,
+  devices_url: &str,
+  auth: &super::webdav_auth::DavAuth,
+  client_id: &str,
+  device_name: Option<&str>,
+  sync_ok: Option<bool>,
+) -> Result<(), String> {
+  let mut file = read_devices(client, devices_url, auth).await?;
+  let now = chrono::Utc::now().timestamp_millis();
+  let entry = file.devices.entry(client_id.to_string()).or_insert_with(|| DeviceEntryV1 {
+    client_id: client_id.to_string(),
+    first_seen_at: now,
+    ..Default::default()
+  });
+  // A name set through `webdav_rename_device` wins over the local default.
+  if entry.name.is_empty() {
+    entry.name = device_name
+      .map(|s| s.trim().to_string())
+      .filter(|s| !s.is_empty())
+      .unwrap_or_else(local_device_name);
+  }
+  entry.os = local_os();
+  entry.app_version = env!("CARGO_PKG_VERSION").to_string();
+  entry.last_seen_at = now;
+  if let Some(ok) = sync_ok {
+    entry.last_sync_at = Some(now);
+    entry.last_sync_ok = Some(ok);
+  }
+  write_devices(client, devices_url, auth, &mut file).await
+}
+
+#[tauri::command]
+pub async fn webdav_list_devices(args: super::WebDavRemoteArgs) -> Result<Vec<DeviceInfo>, String> {
+  let remote = args.connect()?;
+  let file = read_devices(&remote.client, &remote.url(DEVICES_FILE_NAME), &remote.auth).await?;
+  let lock = super::read_remote_lock(&remote.client, &remote.url("lock.json"), &remote.auth).await?;
+  let now = chrono::Utc::now().timestamp_millis();
+  let active_lock = lock.filter(|l| now < l.expires_at);
+
+  let mut out: Vec<DeviceInfo> = file
+    .devices
+    .into_values()
+    .map(|entry| {
+      let holds_lock = active_lock.as_ref().map(|l| l.client_id == entry.client_id).unwrap_or(false);
+      DeviceInfo {
+        is_current: entry.client_id == args.client_id,
+        holds_lock,
+        lock_expires_at: if holds_lock { active_lock.as_ref().map(|l| l.expires_at) } else { None },
+        entry,
+      }
+    })
+    .collect();
+  out.sort_by_key(|d| std::cmp::Reverse(d.entry.last_seen_at));
+  Ok(out)
+}
+
+#[tauri::command]
+pub async fn webdav_rename_device(args: super::WebDavRemoteArgs, target_client_id: String, name: String) -> Result<(), String> {
+  let name = name.trim().to_string();
+  if name.is_empty() {
+    return Err("Device name cannot be empty".to_string());
+  }
+  let remote = args.connect()?;
+  let lock_url = remote.url("lock.json");
+  let devices_url = remote.url(DEVICES_FILE_NAME);
+
+  // Our own sync holds the same client_id lock; don't release it under it.
+  let _guard = super::sync_scheduler::LOCAL_SYNC_GUARD.lock().await;
+  let lock = super::acquire_lock(&remote.client, &lock_url, &remote.auth, &args.client_id, "registry", 30_000).await?;
+  if lock.is_none() {
+    return Err("Remote locked by another client".to_string());
+  }
+
+  let result = async {
+    let mut file = read_devices(&remote.client, &devices_url, &remote.auth).await?;
+    let entry = file
+      .devices
+      .get_mut(&target_client_id)
+      .ok_or_else(|| "Unknown device".to_string())?;
+    entry.name = name;
+    write_devices(&remote.client, &devices_url, &remote.auth, &mut file).await
+  }
+  .await;
+
+  let _ = super::dav_delete(&remote.client, &lock_url, &remote.auth).await;
+  result
+}
+
+/// Removes `lock.json` when it belongs to `target_client_id`, e.g. a laptop
+/// that crashed mid-sync. Returns whether a lock was removed.
+#[tauri::command]
+pub async fn webdav_revoke_device_lock(args: super::WebDavRemoteArgs, target_client_id: String) -> Result<bool, String> {
+  let remote = args.connect()?;
+  match super::read_remote_lock(&remote.client, &remote.url("lock.json"), &remote.auth).await? {
+    Some(lock) if lock.client_id == target_client_id => {
+      super::sync_audit::force_remove_lock(&remote, lock, &args.client_id, "revoke_device_lock").await?;
+      Ok(true)
+    }
+    _ => Ok(false),
+  }
+}
@@ -0,0 +1,424 @@
+// Background WebDAV sync driven from Rust.
+//
+// The scheduler runs on the Tauri async runtime, so it keeps going while the
+// window is minimised or the webview is throttled. It reads the database
+// straight from the local workspace (see `LocalSyncContext.db_path`) instead of
+// relying on the frontend to hand over a base64 export.
+
+use tauri::Emitter;
+
+const DEFAULT_INTERVAL_SECS: u64 = 300;
+const DEFAULT_DEBOUNCE_SECS: u64 = 5;
+const BACKOFF_BASE_SECS: u64 = 15;
+const BACKOFF_MAX_SECS: u64 = 30 * 60;
+
+pub const PROGRESS_EVENT: &str = "webdav-sync:progress";
+pub const CONFLICT_EVENT: &str = "webdav-sync:conflict";
+
+/// Serialises sync runs inside this process, whether they come from the
+/// scheduler or from an explicit `webdav_sync` call. The remote lock cannot do
+/// this for us because both callers share the same `client_id`.
+pub static LOCAL_SYNC_GUARD: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+fn default_interval_secs() -> u64 {
+  DEFAULT_INTERVAL_SECS
+}
+
+fn default_debounce_secs() -> u64 {
+  DEFAULT_DEBOUNCE_SECS
+}
+
+fn default_true() -> bool {
+  true
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncSchedulerConfig {
+  pub url: String,
+  #[serde(default)]
+  pub user: String,
+  #[serde(default)]
+  pub pass: String,
+  #[serde(default)]
+  pub workspace_id: Option<String>,
+  pub slug: String,
+  pub project_path: String,
+  pub client_id: String,
+  #[serde(default = "default_interval_secs")]
+  pub interval_secs: u64,
+  #[serde(default = "default_debounce_secs")]
+  pub debounce_secs: u64,
+  #[serde(default = "default_true")]
+  pub sync_on_start: bool,
+  #[serde(default)]
+  pub device_name: Option<String>,
+}
+
+#[derive(serde::Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncSchedulerStatus {
+  running: bool,
+  project_path: Option<String>,
+  slug: Option<String>,
+  last_run_at: Option<i64>,
+  last_success_at: Option<i64>,
+  last_error: Option<String>,
+  consecutive_failures: u32,
+  next_run_at: Option<i64>,
+  /// The last run ended in a conflict; runs are skipped until the local
+  /// database or the remote state changes (e.g. the user resolved it).
+  conflict: bool,
+}
+
+#[derive(serde::Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct SyncProgressEvent {
+  phase: String, // "started" | "finished" | "failed"
+  reason: String, // "startup" | "save" | "interval" | "manual"
+  /// "auto" until the run has picked a direction, then "up" | "down".
+  mode: String,
+  slug: String,
+  project_path: String,
+  /// The local database file was rewritten by this run.
+  applied: bool,
+  /// Hash of the local database file when the run rewrote it, also when a
+  /// later step failed; the webview's copy is stale whenever this differs
+  /// from what it last saved.
+  db_sha256: Option<String>,
+  /// The pulled database as plain SQLite, when a download replaced the file.
+  db_base64: Option<String>,
+  error: Option<String>,
+  retry_in_secs: Option<u64>,
+}
+
+struct SchedulerHandle {
+  config: SyncSchedulerConfig,
+  signals: std::sync::Arc<SchedulerSignals>,
+  task: tauri::async_runtime::JoinHandle<()>,
+}
+
+#[derive(Default)]
+struct SchedulerSignals {
+  saved: tokio::sync::Notify,
+  trigger: tokio::sync::Notify,
+  stop: tokio::sync::Notify,
+  status: std::sync::Mutex<SyncSchedulerStatus>,
+}
+
+#[derive(Default)]
+pub struct SyncSchedulerState {
+  handle: std::sync::Mutex<Option<SchedulerHandle>>,
+}
+
+impl SyncSchedulerState {
+  /// Called after the workspace database was written locally; schedules a
+  /// debounced push when the scheduler is watching that workspace.
+  pub fn notify_saved(&self, root: &std::path::Path) {
+    let guard = self.handle.lock().unwrap();
+    if let Some(h) = guard.as_ref() {
+      if super::workspace_registry::same_path(&h.config.project_path, &root.to_string_lossy()) {
+        h.signals.saved.notify_one();
+      }
+    }
+  }
+
+  /// Asks the loop to stop and waits for it, so a run in progress finishes
+  /// (and releases the remote lock) instead of being cut off halfway.
+  async fn stop_current(&self) {
+    let handle = self.handle.lock().unwrap().take();
+    if let Some(h) = handle {
+      h.signals.stop.notify_one();
+      let _ = h.task.await;
+    }
+  }
+
+  /// Stops the scheduler when it syncs the folder at `root` and hands back
+  /// its config, so the caller can restart it once the folder has moved.
+  pub async fn take_for(&self, root: &std::path::Path) -> Option<SyncSchedulerConfig> {
+    let config = match self.handle.lock().unwrap().as_ref() {
+      Some(h) if super::workspace_registry::same_path(&h.config.project_path, &root.to_string_lossy()) => h.config.clone(),
+      _ => return None,
+    };
+    self.stop_current().await;
+    Some(config)
+  }
+
+  pub async fn start(&self, app: tauri::AppHandle, config: SyncSchedulerConfig) -> SyncSchedulerStatus {
+    self.stop_current().await;
+
+    let signals = std::sync::Arc::new(SchedulerSignals::default());
+    {
+      let mut status = signals.status.lock().unwrap();
+      status.running = true;
+      status.project_path = Some(config.project_path.clone());
+      status.slug = Some(config.slug.clone());
+    }
+    let task = tauri::async_runtime::spawn(scheduler_loop(app, config.clone(), signals.clone()));
+    let status = signals.status.lock().unwrap().clone();
+    *self.handle.lock().unwrap() = Some(SchedulerHandle { config, signals, task });
+    status
+  }
+}
+
+fn now_ms() -> i64 {
+  chrono::Utc::now().timestamp_millis()
+}
+
+/// Transport failures, 5xx answers and a busy remote lock are worth retrying
+/// with backoff; anything else (bad credentials, conflicts) is not.
+fn is_transient_error(err: &str) -> bool {
+  const TRANSPORT_PREFIXES: [&str; 6] = [
+    "MKCOL failed:",
+    "GET failed:",
+    "GET bytes failed:",
+    "PUT failed:",
+    "DELETE failed:",
+    "MOVE failed:",
+  ];
+  TRANSPORT_PREFIXES.iter().any(|p| err.starts_with(p))
+    || err.contains(" -> 5")
+    || err == "Remote locked by another client"
+}
+
+fn backoff_secs(consecutive_failures: u32) -> u64 {
+  let exp = consecutive_failures.saturating_sub(1).min(16);
+  BACKOFF_BASE_SECS.saturating_mul(1u64 << exp).min(BACKOFF_MAX_SECS)
+}
+
+fn sync_args(config: &SyncSchedulerConfig) -> super::WebDavSyncArgs {
+  super::WebDavSyncArgs {
+    mode: "auto".to_string(),
+    url: config.url.clone(),
+    user: config.user.clone(),
+    pass: config.pass.clone(),
+    workspace_id: config.workspace_id.clone(),
+    slug: config.slug.clone(),
+    project_path: config.project_path.clone(),
+    client_id: config.client_id.clone(),
+    force: false,
+    local_db_base64: None,
+    device_name: config.device_name.clone(),
+  }
+}
+
+enum CycleOutcome {
+  Done,
+  Backoff(u64),
+  /// Hashes of the local database file and of the remote state when the
+  /// conflict was reported.
+  Conflict { local_sha: Option<String>, remote_sha: Option<String> },
+}
+
+fn local_db_sha(config: &SyncSchedulerConfig) -> Option<String> {
+  let ctx = super::detect_local_sync_context(std::path::Path::new(&config.project_path)).ok()?;
+  std::fs::read(&ctx.db_path).ok().map(|b| super::sha256_hex(&b))
+}
+
+/// Hash in the remote `state.json`; `None` when it could not be read.
+async fn remote_state_sha(config: &SyncSchedulerConfig) -> Option<Option<String>> {
+  let auth = super::webdav_auth::resolve_auth(&config.user, &config.pass, config.workspace_id.as_deref()).ok()?;
+  let base = config.url.trim().trim_end_matches('/');
+  let url = super::join_base(&super::remote_workspace_root(base, config.slug.trim()), "state.json");
+  match super::dav_get_bytes(&reqwest::Client::new(), &url, &auth).await {
+    Ok(b) => Some(serde_json::from_slice::<super::SyncStateV1>(&b).ok().map(|s| s.sha256)),
+    Err(e) if e == "NOT_FOUND" => Some(None),
+    Err(_) => None,
+  }
+}
+
+/// A paused conflict stays paused while both sides are what they were; an
+/// unreadable remote counts as unchanged so it cannot pile up more copies.
+fn conflict_unchanged(paused: (&Option<String>, &Option<String>), local: &Option<String>, remote: Option<Option<String>>) -> bool {
+  paused.0 == local && remote.is_none_or(|r| &r == paused.1)
+}
+
+/// Runs one sync cycle and reports whether to back off or pause.
+async fn run_cycle(app: &tauri::AppHandle, config: &SyncSchedulerConfig, signals: &SchedulerSignals, reason: &str) -> CycleOutcome {
+  let base_event = SyncProgressEvent {
+    phase: "started".to_string(),
+    reason: reason.to_string(),
+    mode: "auto".to_string(),
+    slug: config.slug.clone(),
+    project_path: config.project_path.clone(),
+    applied: false,
+    db_sha256: None,
+    db_base64: None,
+    error: None,
+    retry_in_secs: None,
+  };
+  let _ = app.emit(PROGRESS_EVENT, base_event.clone());
+  signals.status.lock().unwrap().last_run_at = Some(now_ms());
+
+  let result = {
+    let _guard = LOCAL_SYNC_GUARD.lock().await;
+    super::webdav_sync_internal(sync_args(config)).await
+  };
+
+  let mode = match &result {
+    Ok(resp) => resp.mode.clone().unwrap_or_else(|| base_event.mode.clone()),
+    Err(_) => base_event.mode.clone(),
+  };
+  let (error, applied) = match &result {
+    Ok(resp) if resp.success => {
+      super::record_sync_slug(app, &config.project_path, &config.slug);
+      (None, resp.applied.unwrap_or(false))
+    }
+    Ok(resp) => {
+      if resp.conflict {
+        let _ = app.emit(CONFLICT_EVENT, resp.clone());
+      }
+      (Some(resp.error.clone().unwrap_or_else(|| "Sync failed".to_string())), false)
+    }
+    Err(e) => (Some(e.clone()), false),
+  };
+  let conflict = matches!(&result, Ok(resp) if resp.conflict);
+  let db_sha256 = result.as_ref().ok().and_then(|resp| resp.db_sha256.clone());
+  let remote_sha = result
+    .as_ref()
+    .ok()
+    .and_then(|resp| resp.remote_state.as_ref())
+    .and_then(|s| s.get("sha256"))
+    .and_then(|v| v.as_str())
+    .map(String::from);
+  let local_sha = if conflict { local_db_sha(config) } else { None };
+
+  let mut status = signals.status.lock().unwrap();
+  status.conflict = conflict;
+  match error {
+    None => {
+      status.last_success_at = Some(now_ms());
+      status.last_error = None;
+      status.consecutive_failures = 0;
+      let db_base64 = match result {
+        Ok(resp) if applied => resp.db_base64,
+        _ => None,
+      };
+      let _ = app.emit(PROGRESS_EVENT, SyncProgressEvent { phase: "finished".to_string(), mode, applied, db_sha256, db_base64, ..base_event });
+      CycleOutcome::Done
+    }
+    Some(err) => {
+      status.last_error = Some(err.clone());
+      let retry = if !conflict && is_transient_error(&err) {
+        status.consecutive_failures = status.consecutive_failures.saturating_add(1);
+        Some(backoff_secs(status.consecutive_failures))
+      } else {
+        status.consecutive_failures = 0;
+        None
+      };
+      let _ = app.emit(PROGRESS_EVENT, SyncProgressEvent { phase: "failed".to_string(), mode, db_sha256, error: Some(err), retry_in_secs: retry, ..base_event });
+      match retry {
+        _ if conflict => CycleOutcome::Conflict { local_sha, remote_sha },
+        Some(secs) => CycleOutcome::Backoff(secs),
+        None => CycleOutcome::Done,
+      }
+    }
+  }
+}
+
+async fn scheduler_loop(app: tauri::AppHandle, config: SyncSchedulerConfig, signals: std::sync::Arc<SchedulerSignals>) {
+  let interval = std::time::Duration::from_secs(config.interval_secs.max(30));
+  let debounce = std::time::Duration::from_secs(config.debounce_secs);
+
+  let mut outcome = CycleOutcome::Done;
+  if config.sync_on_start {
+    outcome = run_cycle(&app, &config, &signals, "startup").await;
+  }
+
+  loop {
+    let backoff = match outcome {
+      CycleOutcome::Backoff(secs) => Some(secs),
+      _ => None,
+    };
+    let wait = backoff.map(std::time::Duration::from_secs).unwrap_or(interval);
+    signals.status.lock().unwrap().next_run_at = Some(now_ms() + wait.as_millis() as i64);
+    let retry_at = backoff.map(|_| tokio::time::Instant::now() + wait);
+
+    let reason = tokio::select! {
+      _ = signals.stop.notified() => return,
+      _ = tokio::time::sleep(wait) => "interval",
+      _ = signals.trigger.notified() => "manual",
+      _ = signals.saved.notified() => {
+        // Debounce bursts of saves into a single push.
+        loop {
+          tokio::select! {
+            _ = signals.stop.notified() => return,
+            _ = signals.saved.notified() => continue,
+            _ = tokio::time::sleep(debounce) => break,
+          }
+        }
+        // While backing off, a save must not bypass the retry delay.
+        if let Some(at) = retry_at {
+          tokio::select! {
+            _ = signals.stop.notified() => return,
+            _ = tokio::time::sleep_until(at) => {}
+          }
+        }
+        "save"
+      }
+    };
+
+    // Every run against an unresolved conflict would only add more copies.
+    if let CycleOutcome::Conflict { local_sha, remote_sha } = &outcome {
+      if conflict_unchanged((local_sha, remote_sha), &local_db_sha(&config), remote_state_sha(&config).await) {
+        continue;
+      }
+    }
+    outcome = run_cycle(&app, &config, &signals, reason).await;
+  }
+}
+
+#[tauri::command]
+pub async fn sync_scheduler_start(app: tauri::AppHandle, state: tauri::State<'_, SyncSchedulerState>, config: SyncSchedulerConfig) -> Result<SyncSchedulerStatus, String> {
+  if config.slug.trim().is_empty() {
+    return Err("Missing slug".to_string());
+  }
+  if config.url.trim().is_empty() {
+    return Err("Missing WebDAV url".to_string());
+  }
+  let root = std::path::PathBuf::from(&config.project_path);
+  super::detect_local_sync_context(&root)?;
+  Ok(state.start(app, config).await)
+}
+
+#[tauri::command]
+pub async fn sync_scheduler_stop(state: tauri::State<'_, SyncSchedulerState>) -> Result<(), String> {
+  state.stop_current().await;
+  Ok(())
+}
+
+#[tauri::command]
+pub fn sync_scheduler_status(state: tauri::State<'_, SyncSchedulerState>) -> Result<SyncSchedulerStatus, String> {
+  let guard = state.handle.lock().unwrap();
+  Ok(match guard.as_ref() {
+    Some(h) => h.signals.status.lock().unwrap().clone(),
+    None => SyncSchedulerStatus::default(),
+  })
+}
+
+#[tauri::command]
+pub fn sync_scheduler_trigger(state: tauri::State<'_, SyncSchedulerState>) -> Result<(), String> {
+  let guard = state.handle.lock().unwrap();
+  match guard.as_ref() {
+    Some(h) => {
+      h.signals.trigger.notify_one();
+      Ok(())
+    }
+    None => Err("Sync scheduler is not running".to_string()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn conflict_stays_paused_until_a_side_changes() {
+    let (local, remote) = (Some("aaa".to_string()), Some("bbb".to_string()));
+    assert!(conflict_unchanged((&local, &remote), &local, Some(remote.clone())));
+    assert!(conflict_unchanged((&local, &remote), &local, None));
+    assert!(!conflict_unchanged((&local, &remote), &Some("ccc".to_string()), Some(remote.clone())));
+    assert!(!conflict_unchanged((&local, &remote), &local, Some(Some("ddd".to_string()))));
+    assert!(!conflict_unchanged((&local, &remote), &local, Some(None)));
+  }
+}
@@ -0,0 +1,426 @@
+// WebDAV authentication: Basic, Bearer, Digest and Nextcloud app passwords.
+//
+// Credentials can still be passed per call (legacy `user`/`pass`), but the
+// preferred path is to store them once with `webdav_set_credentials` and let
+// every command look them up by workspace id.
+
+use sha2::Digest as _;
+
+pub const SECRET_ACCOUNT: &str = "webdav";
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum WebDavCredentials {
+  #[serde(rename_all = "camelCase")]
+  Basic { user: String, pass: String },
+  #[serde(rename_all = "camelCase")]
+  Bearer { token: String },
+  #[serde(rename_all = "camelCase")]
+  Digest { user: String, pass: String },
+  /// Result of the Nextcloud/ownCloud login flow v2; sent as Basic.
+  #[serde(rename_all = "camelCase")]
+  NextcloudAppPassword { server: String, login_name: String, app_password: String },
+}
+
+/// What the UI may see about stored credentials: never the secret itself.
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebDavCredentialsInfo {
+  kind: String,
+  user: Option<String>,
+  server: Option<String>,
+}
+
+impl WebDavCredentials {
+  fn info(&self) -> WebDavCredentialsInfo {
+    match self {
+      WebDavCredentials::Basic { user, .. } => WebDavCredentialsInfo { kind: "basic".to_string(), user: Some(user.clone()), server: None },
+      WebDavCredentials::Bearer { .. } => WebDavCredentialsInfo { kind: "bearer".to_string(), user: None, server: None },
+      WebDavCredentials::Digest { user, .. } => WebDavCredentialsInfo { kind: "digest".to_string(), user: Some(user.clone()), server: None },
+      WebDavCredentials::NextcloudAppPassword { server, login_name, .. } => WebDavCredentialsInfo {
+        kind: "nextcloudAppPassword".to_string(),
+        user: Some(login_name.clone()),
+        server: Some(server.clone()),
+      },
+    }
+  }
+}
+
+pub fn load_credentials(workspace_id: &str) -> Result<Option<WebDavCredentials>, String> {
+  match super::secrets::get_secret(workspace_id, SECRET_ACCOUNT)? {
+    Some(txt) => serde_json::from_str(&txt).map(Some).map_err(|e| format!("Stored WebDAV credentials are unreadable: {e}")),
+    None => Ok(None),
+  }
+}
+
+fn store_credentials(workspace_id: &str, creds: &WebDavCredentials) -> Result<(), String> {
+  let txt = serde_json::to_string(creds).map_err(|e| format!("Credentials encode failed: {e}"))?;
+  super::secrets::set_secret(workspace_id, SECRET_ACCOUNT, &txt)
+}
+
+/// Explicit `user`/`pass` win (older frontends still send them); otherwise the
+/// credentials stored for `workspace_id` are used.
+pub fn resolve_auth(user: &str, pass: &str, workspace_id: Option<&str>) -> Result<DavAuth, String> {
+  if !user.is_empty() || !pass.is_empty() {
+    return Ok(DavAuth::new(WebDavCredentials::Basic { user: user.to_string(), pass: pass.to_string() }));
+  }
+  let workspace_id = workspace_id.map(str::trim).filter(|s| !s.is_empty()).ok_or_else(|| "No WebDAV credentials: pass user/pass or a workspace id".to_string())?;
+  match load_credentials(workspace_id)? {
+    Some(creds) => Ok(DavAuth::new(creds)),
+    None => Err("No WebDAV credentials stored for this workspace".to_string()),
+  }
+}
+
+struct DigestChallenge {
+  realm: String,
+  nonce: String,
+  opaque: Option<String>,
+  qop: Option<String>,
+  algorithm: String,
+  nc: u32,
+  /// The server rejected an earlier nonce as expired, not the credentials.
+  stale: bool,
+}
+
+// Digest challenges by server origin and user, shared by every `DavAuth`, so
+// requests after the first one are authenticated up front instead of each
+// being sent once unauthenticated (a whole database PUT included) to fetch a
+// fresh 401.
+static DIGEST_CHALLENGES: std::sync::Mutex<Option<std::collections::HashMap<String, DigestChallenge>>> = std::sync::Mutex::new(None);
+
+fn challenge_key(url: &reqwest::Url, user: &str) -> String {
+  format!("{}|{user}", url.origin().ascii_serialization())
+}
+
+fn parse_auth_params(s: &str) -> std::collections::HashMap<String, String> {
+  let mut out = std::collections::HashMap::new();
+  let mut rest = s.trim();
+  while !rest.is_empty() {
+    let eq = match rest.find('=') {
+      Some(i) => i,
+      None => break,
+    };
+    let key = rest[..eq].trim().trim_start_matches(',').trim().to_ascii_lowercase();
+    rest = rest[eq + 1..].trim_start();
+    let value;
+    if let Some(stripped) = rest.strip_prefix('"') {
+      // quoted-string: a backslash escapes the next character.
+      let mut text = String::new();
+      let mut end = stripped.len();
+      let mut chars = stripped.char_indices();
+      while let Some((i, c)) = chars.next() {
+        match c {
+          '\\' => text.extend(chars.next().map(|(_, c)| c)),
+          '"' => {
+            end = i;
+            break;
+          }
+          c => text.push(c),
+        }
+      }
+      value = text;
+      rest = stripped.get(end + 1..).unwrap_or("");
+    } else {
+      let end = rest.find(',').unwrap_or(rest.len());
+      value = rest[..end].trim().to_string();
+      rest = &rest[end..];
+    }
+    rest = rest.trim_start().trim_start_matches(',').trim_start();
+    out.insert(key, value);
+  }
+  out
+}
+
+fn parse_digest_challenge(resp: &reqwest::Response) -> Option<DigestChallenge> {
+  resp.headers().get_all("www-authenticate").iter().find_map(|v| parse_digest_header(v.to_str().ok()?))
+}
+
+fn parse_digest_header(v: &str) -> Option<DigestChallenge> {
+  let params = v.strip_prefix("Digest ").or_else(|| v.strip_prefix("digest "))?;
+  let p = parse_auth_params(params);
+  let qop = p.get("qop").and_then(|q| q.split(',').map(str::trim).find(|q| *q == "auth").map(String::from));
+  Some(DigestChallenge {
+    realm: p.get("realm").cloned().unwrap_or_default(),
+    nonce: p.get("nonce").cloned()?,
+    opaque: p.get("opaque").cloned(),
+    qop,
+    algorithm: p.get("algorithm").cloned().unwrap_or_else(|| "MD5".to_string()),
+    nc: 0,
+    stale: p.get("stale").is_some_and(|s| s.eq_ignore_ascii_case("true")),
+  })
+}
+
+/// An auth-param value as a quoted-string (RFC 7230 section 3.2.6).
+fn quoted(value: &str) -> String {
+  format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The `Authorization` value answering `ch` for request number `ch.nc`.
+fn digest_authorization(ch: &DigestChallenge, user: &str, pass: &str, method: &str, uri: &str, cnonce: &str) -> String {
+  let nc = format!("{:08x}", ch.nc);
+  let mut ha1 = digest_hash(&ch.algorithm, &format!("{user}:{}:{pass}", ch.realm));
+  if ch.algorithm.to_ascii_lowercase().ends_with("-sess") {
+    ha1 = digest_hash(&ch.algorithm, &format!("{ha1}:{}:{cnonce}", ch.nonce));
+  }
+  let ha2 = digest_hash(&ch.algorithm, &format!("{method}:{uri}"));
+  let response = match &ch.qop {
+    Some(qop) => digest_hash(&ch.algorithm, &format!("{ha1}:{}:{nc}:{cnonce}:{qop}:{ha2}", ch.nonce)),
+    None => digest_hash(&ch.algorithm, &format!("{ha1}:{}:{ha2}", ch.nonce)),
+  };
+  let mut h = format!(
+    "Digest username={}, realm={}, nonce={}, uri={}, algorithm={}, response=\"{response}\"",
+    quoted(user),
+    quoted(&ch.realm),
+    quoted(&ch.nonce),
+    quoted(uri),
+    ch.algorithm
+  );
+  if let Some(qop) = &ch.qop {
+    h.push_str(&format!(", qop={qop}, nc={nc}, cnonce={}", quoted(cnonce)));
+  }
+  if let Some(opaque) = &ch.opaque {
+    h.push_str(&format!(", opaque={}", quoted(opaque)));
+  }
+  h
+}
+
+fn digest_hash(algorithm: &str, data: &str) -> String {
+  if algorithm.to_ascii_uppercase().starts_with("SHA-256") {
+    hex::encode(sha2::Sha256::digest(data.as_bytes()))
+  } else {
+    hex::encode(md5::Md5::digest(data.as_bytes()))
+  }
+}
+
+fn make_cnonce(nc: u32) -> String {
+  let seed = format!("{}:{nc}:{}", chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default(), std::process::id());
+  super::sha256_hex(seed.as_bytes())[..16].to_string()
+}
+
+pub struct DavAuth {
+  credentials: WebDavCredentials,
+}
+
+impl DavAuth {
+  pub fn new(credentials: WebDavCredentials) -> Self {
+    DavAuth { credentials }
+  }
+
+  fn header(&self, method: &reqwest::Method, url: &reqwest::Url) -> Option<String> {
+    match &self.credentials {
+      WebDavCredentials::Basic { user, pass } => Some(super::basic_auth_header(user, pass)),
+      WebDavCredentials::NextcloudAppPassword { login_name, app_password, .. } => Some(super::basic_auth_header(login_name, app_password)),
+      WebDavCredentials::Bearer { token } => Some(format!("Bearer {token}")),
+      WebDavCredentials::Digest { user, pass } => {
+        let mut guard = DIGEST_CHALLENGES.lock().unwrap();
+        let ch = guard.as_mut()?.get_mut(&challenge_key(url, user))?;
+        ch.nc += 1;
+        let uri = match url.query() {
+          Some(q) => format!("{}?{q}", url.path()),
+          None => url.path().to_string(),
+        };
+        let cnonce = make_cnonce(ch.nc);
+        Some(digest_authorization(ch, user, pass, method.as_str(), &uri, &cnonce))
+      }
+    }
+  }
+
+  /// Stores a Digest challenge from a 401 answer. Returns true when the
+  /// request is worth retrying with it: on first contact (`had_auth` false),
+  /// or when the server only rejected a stale nonce. Any other 401 after an
+  /// authenticated attempt means wrong credentials and is not retried.
+  fn accept_challenge(&self, resp: &reqwest::Response, url: &reqwest::Url, had_auth: bool) -> bool {
+    let user = match &self.credentials {
+      WebDavCredentials::Digest { user, .. } => user,
+      _ => return false,
+    };
+    let mut guard = DIGEST_CHALLENGES.lock().unwrap();
+    let cache = guard.get_or_insert_with(Default::default);
+    let key = challenge_key(url, user);
+    match parse_digest_challenge(resp) {
+      Some(ch) => {
+        let retry = !had_auth || ch.stale;
+        cache.insert(key, ch);
+        retry
+      }
+      None => {
+        cache.remove(&key);
+        false
+      }
+    }
+  }
+
+  /// Sends `req` with the right `Authorization` header. Digest reuses the
+  /// cached challenge for the server and answers a new one only on first
+  /// contact or when the nonce went stale.
+  pub async fn execute(&self, client: &reqwest::Client, req: reqwest::RequestBuilder) -> reqwest::Result<reqwest::Response> {
+    let mut request = req.build()?;
+    let url = request.url().clone();
+    let retry = request.try_clone();
+    self.apply(&mut request);
+    let had_auth = request.headers().contains_key(reqwest::header::AUTHORIZATION);
+    let resp = client.execute(request).await?;
+    if resp.status().as_u16() == 401 {
+      if let Some(mut again) = retry {
+        if self.accept_challenge(&resp, &url, had_auth) {
+          self.apply(&mut again);
+          return client.execute(again).await;
+        }
+      }
+    }
+    Ok(resp)
+  }
+
+  fn apply(&self, request: &mut reqwest::Request) {
+    if let Some(h) = self.header(request.method(), request.url()) {
+      if let Ok(v) = reqwest::header::HeaderValue::from_str(&h) {
+        request.headers_mut().insert(reqwest::header::AUTHORIZATION, v);
+      }
+    }
+  }
+}
+
+#[tauri::command]
+pub fn webdav_set_credentials(workspace_id: String, credentials: WebDavCredentials) -> Result<(), String> {
+  store_credentials(&workspace_id, &credentials)
+}
+
+#[tauri::command]
+pub fn webdav_get_credentials_info(workspace_id: String) -> Result<Option<WebDavCredentialsInfo>, String> {
+  Ok(load_credentials(&workspace_id)?.map(|c| c.info()))
+}
+
+#[tauri::command]
+pub fn webdav_clear_credentials(workspace_id: String) -> Result<(), String> {
+  super::secrets::delete_secret(&workspace_id, SECRET_ACCOUNT)
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginFlowStart {
+  login_url: String,
+  poll_token: String,
+  poll_endpoint: String,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginFlowPoll {
+  done: bool,
+  server: Option<String>,
+  login_name: Option<String>,
+  webdav_url: Option<String>,
+}
+
+/// Starts Nextcloud login flow v2. The UI opens `loginUrl` in the browser and
+/// then calls `webdav_login_flow_poll` until it reports `done`.
+#[tauri::command]
+pub async fn webdav_login_flow_start(server: String) -> Result<LoginFlowStart, String> {
+  let server = server.trim().trim_end_matches('/').to_string();
+  if server.is_empty() {
+    return Err("Missing server url".to_string());
+  }
+  let resp = reqwest::Client::new()
+    .post(super::join_base(&server, "index.php/login/v2"))
+    .header("User-Agent", "RentikPro")
+    .send()
+    .await
+    .map_err(|e| format!("Login flow failed: {e}"))?;
+  if !resp.status().is_success() {
+    return Err(format!("Login flow -> {}", resp.status()));
+  }
+  let v: serde_json::Value = resp.json().await.map_err(|e| format!("Login flow response invalid: {e}"))?;
+  let get = |ptr: &str| v.pointer(ptr).and_then(|x| x.as_str()).map(String::from);
+  Ok(LoginFlowStart {
+    login_url: get("/login").ok_or_else(|| "Login flow response missing login url".to_string())?,
+    poll_token: get("/poll/token").ok_or_else(|| "Login flow response missing poll token".to_string())?,
+    poll_endpoint: get("/poll/endpoint").ok_or_else(|| "Login flow response missing poll endpoint".to_string())?,
+  })
+}
+
+/// Polls the login flow once. On success the app password is stored for
+/// `workspace_id` and only non-secret details are returned.
+#[tauri::command]
+pub async fn webdav_login_flow_poll(workspace_id: String, poll_token: String, poll_endpoint: String) -> Result<LoginFlowPoll, String> {
+  let resp = reqwest::Client::new()
+    .post(&poll_endpoint)
+    .form(&[("token", poll_token.as_str())])
+    .send()
+    .await
+    .map_err(|e| format!("Login flow poll failed: {e}"))?;
+  if resp.status().as_u16() == 404 {
+    return Ok(LoginFlowPoll { done: false, server: None, login_name: None, webdav_url: None });
+  }
+  if !resp.status().is_success() {
+    return Err(format!("Login flow poll -> {}", resp.status()));
+  }
+  let v: serde_json::Value = resp.json().await.map_err(|e| format!("Login flow poll response invalid: {e}"))?;
+  let get = |k: &str| v.get(k).and_then(|x| x.as_str()).map(String::from);
+  let server = get("server").ok_or_else(|| "Login flow poll missing server".to_string())?;
+  let login_name = get("loginName").ok_or_else(|| "Login flow poll missing loginName".to_string())?;
+  let app_password = get("appPassword").ok_or_else(|| "Login flow poll missing appPassword".to_string())?;
+
+  store_credentials(&workspace_id, &WebDavCredentials::NextcloudAppPassword {
+    server: server.clone(),
+    login_name: login_name.clone(),
+    app_password,
+  })?;
+
+  let webdav_url = super::join_base(&server, &format!("remote.php/dav/files/{login_name}"));
+  Ok(LoginFlowPoll { done: true, server: Some(server), login_name: Some(login_name), webdav_url: Some(webdav_url) })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // RFC 7616 section 3.9.1.
+  const RFC_CHALLENGE: &str = "Digest realm=\"http-auth@example.org\", qop=\"auth, auth-int\", algorithm=ALGO, \
+    nonce=\"7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v\", opaque=\"FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS\"";
+  const RFC_CNONCE: &str = "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ";
+
+  fn rfc_response(algorithm: &str) -> String {
+    let mut ch = parse_digest_header(&RFC_CHALLENGE.replace("ALGO", algorithm)).unwrap();
+    ch.nc = 1;
+    let h = digest_authorization(&ch, "Mufasa", "Circle of Life", "GET", "/dir/index.html", RFC_CNONCE);
+    parse_auth_params(h.strip_prefix("Digest ").unwrap()).remove("response").unwrap()
+  }
+
+  #[test]
+  fn parses_a_digest_challenge() {
+    let ch = parse_digest_header(&RFC_CHALLENGE.replace("ALGO", "SHA-256")).unwrap();
+    assert_eq!(ch.realm, "http-auth@example.org");
+    assert_eq!(ch.nonce, "7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v");
+    assert_eq!(ch.opaque.as_deref(), Some("FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS"));
+    assert_eq!(ch.qop.as_deref(), Some("auth"));
+    assert_eq!(ch.algorithm, "SHA-256");
+    assert!(!ch.stale);
+
+    let ch = parse_digest_header("Digest realm=\"a \\\"b\\\" c\", nonce=abc, stale=TRUE").unwrap();
+    assert_eq!(ch.realm, "a \"b\" c");
+    assert_eq!(ch.nonce, "abc");
+    assert_eq!(ch.algorithm, "MD5");
+    assert!(ch.qop.is_none());
+    assert!(ch.stale);
+
+    assert!(parse_digest_header("Basic realm=\"x\"").is_none());
+    assert!(parse_digest_header("Digest realm=\"x\"").is_none());
+  }
+
+  #[test]
+  fn digest_response_matches_rfc_7616() {
+    assert_eq!(rfc_response("MD5"), "8ca523f5e9506fed4657c9700eebdbec");
+    assert_eq!(rfc_response("SHA-256"), "753927fa0e85d155564e2e272a28d1802ca10daf4496794697cf8db5856cb6c1");
+  }
+
+  #[test]
+  fn quoted_values_are_escaped() {
+    let ch = parse_digest_header("Digest realm=\"r\\\\x\", nonce=\"n\"").unwrap();
+    let h = digest_authorization(&ch, "ann \"o\" \\ b", "pw", "GET", "/", "c");
+    assert!(h.starts_with("Digest username=\"ann \\\"o\\\" \\\\ b\", realm=\"r\\\\x\""), "{h}");
+    let p = parse_auth_params(h.strip_prefix("Digest ").unwrap());
+    assert_eq!(p["username"], "ann \"o\" \\ b");
+    assert_eq!(p["realm"], "r\\x");
+    assert_eq!(p["uri"], "/");
+  }
+}
@@ -0,0 +1,251 @@
+// `webdav_test_connection`: a guided check of a WebDAV server before the first
+// sync, so a typo in the URL or a wrong password shows up as "authentication
+// failed" instead of "MKCOL ... -> 401" halfway through `webdav_sync`.
+
+#[derive(serde::Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProbeStep {
+  name: String,
+  ok: bool,
+  status: Option<u16>,
+  message: Option<String>,
+  elapsed_ms: u64,
+}
+
+#[derive(serde::Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionReport {
+  ok: bool,
+  reachable: bool,
+  auth_ok: bool,
+  auth_error: Option<String>,
+  dav_class: Option<String>,
+  allowed_methods: Vec<String>,
+  can_write: bool,
+  supports_move: bool,
+  server: Option<String>,
+  quota_available_bytes: Option<i64>,
+  quota_used_bytes: Option<i64>,
+  clock_skew_ms: Option<i64>,
+  steps: Vec<ProbeStep>,
+}
+
+const PROPFIND_QUOTA_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:">
+  <d:prop>
+    <d:resourcetype/>
+    <d:quota-available-bytes/>
+    <d:quota-used-bytes/>
+  </d:prop>
+</d:propfind>"#;
+
+/// Pulls the text of the first `<…name>` element out of a multistatus body.
+/// Servers disagree on namespace prefixes, so only the local name is matched.
+fn xml_prop_text(body: &str, name: &str) -> Option<String> {
+  let mut from = 0;
+  while let Some(rel) = body[from..].find(name) {
+    let idx = from + rel;
+    from = idx + name.len();
+    let rest = &body[from..];
+    if !rest.starts_with(|c: char| c == '>' || c == '/' || c.is_whitespace()) {
+      continue;
+    }
+    let prefix = match body[..idx].rfind('<') {
+      Some(lt) => &body[lt + 1..idx],
+      None => continue,
+    };
+    let is_open_tag = prefix.is_empty()
+      || (prefix.ends_with(':') && prefix[..prefix.len() - 1].chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-'));
+    if !is_open_tag {
+      continue;
+    }
+    let gt = rest.find('>')?;
+    if rest[..gt].ends_with('/') {
+      return None;
+    }
+    let content = &rest[gt + 1..];
+    let end = content.find('<').unwrap_or(content.len());
+    return Some(content[..end].trim().to_string());
+  }
+  None
+}
+
+fn header_str(resp: &reqwest::Response, name: &str) -> Option<String> {
+  resp.headers().get(name).and_then(|v| v.to_str().ok()).map(String::from)
+}
+
+/// Difference between the server `Date` header and the local clock, measured
+/// against the midpoint of the request.
+fn clock_skew_ms(resp: &reqwest::Response, sent_at: i64, received_at: i64) -> Option<i64> {
+  let date = header_str(resp, "date")?;
+  let server = chrono::DateTime::parse_from_rfc2822(&date).ok()?.timestamp_millis();
+  Some(server - (sent_at + received_at) / 2)
+}
+
+struct Prober {
+  client: reqwest::Client,
+  auth: super::webdav_auth::DavAuth,
+  steps: Vec<ProbeStep>,
+}
+
+impl Prober {
+  async fn send(&mut self, name: &str, req: reqwest::RequestBuilder, ok_statuses: &[u16]) -> Option<reqwest::Response> {
+    let started = std::time::Instant::now();
+    let res = self.auth.execute(&self.client, req).await;
+    let elapsed_ms = started.elapsed().as_millis() as u64;
+    match res {
+      Ok(resp) => {
+        let status = resp.status().as_u16();
+        let ok = ok_statuses.contains(&status) || (ok_statuses.is_empty() && resp.status().is_success());
+        self.steps.push(ProbeStep {
+          name: name.to_string(),
+          ok,
+          status: Some(status),
+          message: if ok { None } else { resp.status().canonical_reason().map(String::from) },
+          elapsed_ms,
+        });
+        Some(resp)
+      }
+      Err(e) => {
+        self.steps.push(ProbeStep { name: name.to_string(), ok: false, status: None, message: Some(e.to_string()), elapsed_ms });
+        None
+      }
+    }
+  }
+
+  fn last_ok(&self) -> bool {
+    self.steps.last().map(|s| s.ok).unwrap_or(false)
+  }
+
+  fn method(name: &[u8]) -> reqwest::Method {
+    reqwest::Method::from_bytes(name).unwrap()
+  }
+}
+
+#[tauri::command]
+pub async fn webdav_test_connection(args: super::WebDavRemoteArgs) -> Result<ConnectionReport, String> {
+  let base = args.base_url()?;
+  let mut report = ConnectionReport::default();
+  let mut p = Prober {
+    client: reqwest::Client::builder()
+      .timeout(std::time::Duration::from_secs(20))
+      .build()
+      .map_err(|e| e.to_string())?,
+    auth: args.auth()?,
+    steps: vec![],
+  };
+
+  // 1. OPTIONS: reachability, DAV compliance class, allowed methods.
+  let sent_at = chrono::Utc::now().timestamp_millis();
+  if let Some(resp) = p.send("options", p.client.request(reqwest::Method::OPTIONS, &base), &[]).await {
+    report.reachable = true;
+    report.clock_skew_ms = clock_skew_ms(&resp, sent_at, chrono::Utc::now().timestamp_millis());
+    report.server = header_str(&resp, "server");
+    report.dav_class = header_str(&resp, "dav");
+    report.allowed_methods = header_str(&resp, "allow")
+      .map(|a| a.split(',').map(|m| m.trim().to_uppercase()).filter(|m| !m.is_empty()).collect())
+      .unwrap_or_default();
+  }
+  if !report.reachable {
+    report.steps = p.steps;
+    return Ok(report);
+  }
+
+  // 2. PROPFIND on the base URL: credentials and quota.
+  let propfind = p
+    .client
+    .request(Prober::method(b"PROPFIND"), &base)
+    .header("Depth", "0")
+    .header("Content-Type", "application/xml; charset=utf-8")
+    .body(PROPFIND_QUOTA_BODY);
+  if let Some(resp) = p.send("propfind", propfind, &[207]).await {
+    match resp.status().as_u16() {
+      207 => {
+        report.auth_ok = true;
+        let body = resp.text().await.unwrap_or_default();
+        report.quota_available_bytes = xml_prop_text(&body, "quota-available-bytes").and_then(|v| v.parse().ok());
+        report.quota_used_bytes = xml_prop_text(&body, "quota-used-bytes").and_then(|v| v.parse().ok());
+      }
+      401 => report.auth_error = Some("Authentication failed: check user and password".to_string()),
+      403 => report.auth_error = Some("Access denied for this user".to_string()),
+      404 => report.auth_error = Some("URL not found: check the WebDAV path".to_string()),
+      405 => report.auth_error = Some("Server does not accept PROPFIND here: this may not be a WebDAV URL".to_string()),
+      s => report.auth_error = Some(format!("Unexpected PROPFIND status {s}")),
+    }
+  }
+  if !report.auth_ok {
+    report.steps = p.steps;
+    return Ok(report);
+  }
+
+  // 3. Write cycle inside a scratch collection under RentikProSync/.
+  let sync_root = super::join_base(&base, "RentikProSync");
+  let scratch = super::join_base(&sync_root, &format!(".probe-{}", chrono::Utc::now().timestamp_millis()));
+  let file_a = super::join_base(&scratch, "probe.txt");
+  let file_b = super::join_base(&scratch, "probe-moved.txt");
+  let payload = format!("rentikpro probe {}", chrono::Utc::now().to_rfc3339());
+
+  p.send("mkcol_root", p.client.request(Prober::method(b"MKCOL"), &sync_root), &[201, 405]).await;
+  if p.last_ok() {
+    p.send("mkcol_scratch", p.client.request(Prober::method(b"MKCOL"), &scratch), &[201]).await;
+  }
+  if p.last_ok() {
+    p.send("put", p.client.put(&file_a).header("Content-Type", "text/plain").body(payload.clone()), &[]).await;
+    report.can_write = p.last_ok();
+  }
+  if report.can_write {
+    let mv = p
+      .client
+      .request(Prober::method(b"MOVE"), &file_a)
+      .header("Destination", &file_b)
+      .header("Overwrite", "T");
+    p.send("move", mv, &[]).await;
+    if p.last_ok() {
+      if let Some(resp) = p.send("get_moved", p.client.get(&file_b), &[]).await {
+        let body = resp.text().await.unwrap_or_default();
+        report.supports_move = body == payload;
+        if !report.supports_move {
+          if let Some(step) = p.steps.last_mut() {
+            step.ok = false;
+            step.message = Some("Moved file content does not match what was written".to_string());
+          }
+        }
+      }
+    }
+  }
+  // Always try to clean up, even after a partial failure.
+  p.send("delete_scratch", p.client.delete(&scratch), &[200, 204, 404]).await;
+
+  report.ok = report.auth_ok && report.can_write && report.supports_move;
+  report.steps = p.steps;
+  Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn prop_text_ignores_the_namespace_prefix() {
+    let body = r#"<?xml version="1.0"?><multistatus xmlns="DAV:" xmlns:lp1="DAV:" xmlns:ns0="DAV:"><response><propstat><prop>
+      <lp1:getetag>"abc-123"</lp1:getetag><ns0:quota-available-bytes> 1024 </ns0:quota-available-bytes>
+      <getlastmodified xmlns="DAV:">Wed, 21 Oct 2015 07:28:00 GMT</getlastmodified></prop></propstat></response></multistatus>"#;
+    assert_eq!(xml_prop_text(body, "getetag").as_deref(), Some("\"abc-123\""));
+    assert_eq!(xml_prop_text(body, "quota-available-bytes").as_deref(), Some("1024"));
+    assert_eq!(xml_prop_text(body, "getlastmodified").as_deref(), Some("Wed, 21 Oct 2015 07:28:00 GMT"));
+    assert_eq!(xml_prop_text("<D:getetag>x</D:getetag>", "getetag").as_deref(), Some("x"));
+  }
+
+  #[test]
+  fn prop_text_of_self_closing_or_missing_elements_is_none() {
+    assert_eq!(xml_prop_text("<d:prop><d:getetag/></d:prop>", "getetag"), None);
+    assert_eq!(xml_prop_text("<x:prop><x:quota-used-bytes /></x:prop>", "quota-used-bytes"), None);
+    assert_eq!(xml_prop_text("<d:prop><d:getcontentlength>5</d:getcontentlength></d:prop>", "getetag"), None);
+  }
+
+  #[test]
+  fn prop_text_needs_the_whole_local_name() {
+    let body = "<d:getetagx>no</d:getetagx><d:xgetetag>no</d:xgetetag><d:getetag>yes</d:getetag>";
+    assert_eq!(xml_prop_text(body, "getetag").as_deref(), Some("yes"));
+  }
+}
@@ -0,0 +1,80 @@
+// Access to `database.sqlite` from Rust.
+//
+// The webview owns the live database (sql.js) and replaces the file on every
+// save, so Rust never keeps it open: it reads the bytes, decrypts them when
+// the workspace is encrypted, and queries an in-memory copy. Background
+// workers that must record something themselves go through `modify`.
+
+/// Plain SQLite bytes of the workspace database.
+pub fn load_bytes(root: &std::path::Path) -> Result<Vec<u8>, String> {
+  let (_wjson, db, _backups, _media) = super::workspace_paths(root);
+  let bytes = std::fs::read(&db).map_err(|e| format!("Failed reading {}: {e}", db.display()))?;
+  if !super::is_workspace_db_bytes(&bytes) {
+    return Err(format!("{} is not a valid SQLite database", super::WORKSPACE_DB_NAME));
+  }
+  super::plain_db_bytes(root, bytes)
+}
+
+/// Opens a private in-memory copy of `bytes`.
+pub fn open_in_memory(bytes: &[u8], read_only: bool) -> Result<rusqlite::Connection, String> {
+  if !super::is_sqlite_bytes(bytes) {
+    return Err("Not a SQLite database".to_string());
+  }
+  let mut conn = rusqlite::Connection::open_in_memory().map_err(|e| format!("SQLite open failed: {e}"))?;
+  // sqlite3_deserialize takes ownership of a buffer from sqlite3_malloc, so
+  // copy into one rather than handing it Rust-allocated memory.
+  let data = unsafe {
+    let ptr = rusqlite::ffi::sqlite3_malloc64(bytes.len() as u64) as *mut u8;
+    let ptr = std::ptr::NonNull::new(ptr).ok_or_else(|| "SQLite out of memory".to_string())?;
+    std::ptr::copy_nonoverlapping(bytes.as_ptr(), ptr.as_ptr(), bytes.len());
+    rusqlite::serialize::OwnedData::from_raw_nonnull(ptr, bytes.len())
+  };
+  conn
+    .deserialize(rusqlite::DatabaseName::Main, data, read_only)
+    .map_err(|e| format!("SQLite load failed: {e}"))?;
+  Ok(conn)
+}
+
+pub fn open_snapshot(root: &std::path::Path) -> Result<rusqlite::Connection, String> {
+  open_in_memory(&load_bytes(root)?, true)
+}
+
+pub fn has_column(conn: &rusqlite::Connection, table: &str, column: &str) -> bool {
+  let mut stmt = match conn.prepare(&format!("PRAGMA table_info(\"{}\")", table.replace('"', "\"\""))) {
+    Ok(s) => s,
+    Err(_) => return false,
+  };
+  let names = stmt.query_map([], |row| row.get::<_, String>(1));
+  match names {
+    Ok(rows) => rows.filter_map(|r| r.ok()).any(|n| n.eq_ignore_ascii_case(column)),
+    Err(_) => false,
+  }
+}
+
+/// Serializes a connection opened with `open_in_memory` back to file bytes.
+pub fn to_bytes(conn: &rusqlite::Connection) -> Result<Vec<u8>, String> {
+  let data = conn
+    .serialize(rusqlite::DatabaseName::Main)
+    .map_err(|e| format!("SQLite serialize failed: {e}"))?;
+  Ok(data.to_vec())
+}
+
+/// Runs `f` on a writable copy of the workspace database and writes it back
+/// (re-encrypted when needed) under the same lock as `save_workspace`.
+/// Returns `f`'s result and the sha256 of the new file. The webview's copy is
+/// stale afterwards, so callers must tell it what changed and the new hash.
+pub fn modify<T>(root: &std::path::Path, f: impl FnOnce(&rusqlite::Connection) -> Result<T, String>) -> Result<(T, String), String> {
+  let _guard = super::DB_WRITE_LOCK.lock().unwrap();
+  let (_wjson, db, _backups, _media) = super::workspace_paths(root);
+  let conn = open_in_memory(&load_bytes(root)?, false)?;
+  let out = f(&conn)?;
+  let plain = to_bytes(&conn)?;
+  let on_disk = match super::db_crypto::session_key(root) {
+    Some(key) => super::db_crypto::encrypt_db(&plain, &key)?,
+    None if super::db_crypto::file_is_encrypted(&db) => return Err(super::db_crypto::LOCKED_ERROR.to_string()),
+    None => plain,
+  };
+  super::atomic_write(&db, &on_disk)?;
+  super::workspace_watcher::record(&db, &on_disk);
+  Ok((out, super::sha256_hex(&on_disk)))
+}
@@ -0,0 +1,506 @@
+// `workspace_doctor`: deep checks of a workspace folder beyond the "files
+// exist and start with the SQLite magic" test done when opening it.
+//
+// Every problem becomes a finding; the ones with a safe, mechanical remedy
+// carry a `fix` the UI can hand back to `workspace_doctor_fix`.
+
+/// Above this many backups the doctor offers to prune the oldest.
+const MAX_BACKUPS: usize = 30;
+/// Foreign key violations listed individually before summarising.
+const MAX_FK_FINDINGS: usize = 20;
+/// Under `sync/`; "clear_conflicts" moves conflict copies here instead of
+/// deleting them.
+const TRASH_DIR: &str = "trash";
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DoctorFix {
+  /// "delete_file" | "repair_workspace_json" | "reset_sync_state" | "prune_backups" | "clear_conflicts"
+  /// ("clear_conflicts" moves `sync/conflicts` to `sync/trash/conflicts-<time>`).
+  action: String,
+  /// Path relative to the workspace root, for "delete_file".
+  #[serde(default)]
+  target: Option<String>,
+}
+
+#[derive(serde::Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DoctorFinding {
+  code: String,
+  /// "error" | "warning" | "info"
+  severity: String,
+  message: String,
+  path: Option<String>,
+  fix: Option<DoctorFix>,
+}
+
+#[derive(serde::Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DoctorReport {
+  checked_at: i64,
+  ok: bool,
+  findings: Vec<DoctorFinding>,
+  db_bytes: u64,
+  backups_count: usize,
+  backups_bytes: u64,
+  conflicts_bytes: u64,
+}
+
+impl DoctorReport {
+  fn add(&mut self, code: &str, severity: &str, message: String, path: Option<String>, fix: Option<DoctorFix>) {
+    self.findings.push(DoctorFinding { code: code.to_string(), severity: severity.to_string(), message, path, fix });
+  }
+}
+
+fn fix(action: &str, target: Option<String>) -> Option<DoctorFix> {
+  Some(DoctorFix { action: action.to_string(), target })
+}
+
+fn rel_of(root: &std::path::Path, path: &std::path::Path) -> String {
+  path.strip_prefix(root).unwrap_or(path).to_string_lossy().replace('\\', "/")
+}
+
+fn dir_size(dir: &std::path::Path) -> (usize, u64) {
+  let mut count = 0;
+  let mut bytes = 0;
+  if let Ok(entries) = std::fs::read_dir(dir) {
+    for entry in entries.flatten() {
+      match entry.metadata() {
+        Ok(m) if m.is_dir() => {
+          let (c, b) = dir_size(&entry.path());
+          count += c;
+          bytes += b;
+        }
+        Ok(m) => {
+          count += 1;
+          bytes += m.len();
+        }
+        Err(_) => {}
+      }
+    }
+  }
+  (count, bytes)
+}
+
+/// Temp files left by an interrupted `atomic_write`, a paired commit
+/// (`.prev`) or a WebDAV upload (`.uploading.`).
+fn is_leftover_name(name: &str) -> bool {
+  name.ends_with(".tmp") || name.ends_with(".prev") || name.contains(".uploading.")
+}
+
+fn find_leftovers(dir: &std::path::Path, out: &mut Vec<std::path::PathBuf>) {
+  if let Ok(entries) = std::fs::read_dir(dir) {
+    for entry in entries.flatten() {
+      let path = entry.path();
+      let name = entry.file_name().to_string_lossy().to_string();
+      if path.is_dir() {
+        // Backups are ZIPs we wrote ourselves; nothing to clean in there.
+        if name != super::WORKSPACE_BACKUPS_DIR {
+          find_leftovers(&path, out);
+        }
+      } else if is_leftover_name(&name) {
+        out.push(path);
+      }
+    }
+  }
+}
+
+/// `database.sqlite.tmp` / `workspace.json.tmp` may hold the only good copy;
+/// `workspace_recovery_resolve` decides what happens to them, never the doctor.
+fn is_recoverable_temp(rel: &str) -> bool {
+  rel == format!("{}.tmp", super::WORKSPACE_DB_NAME) || rel == format!("{}.tmp", super::WORKSPACE_JSON_NAME)
+}
+
+/// Leftovers the doctor may delete, relative to `root`.
+fn deletable_leftovers(root: &std::path::Path) -> Vec<String> {
+  let mut leftovers = vec![];
+  find_leftovers(root, &mut leftovers);
+  leftovers.iter().map(|p| rel_of(root, p)).filter(|rel| !is_recoverable_temp(rel)).collect()
+}
+
+fn check_workspace_json(root: &std::path::Path, report: &mut DoctorReport) {
+  let wjson = root.join(super::WORKSPACE_JSON_NAME);
+  let path = Some(super::WORKSPACE_JSON_NAME.to_string());
+  let repair = || fix("repair_workspace_json", None);
+  let txt = match std::fs::read_to_string(&wjson) {
+    Ok(t) => t,
+    Err(_) => return report.add("workspace_json_missing", "error", "workspace.json is missing".to_string(), path, repair()),
+  };
+  let meta: serde_json::Value = match serde_json::from_str(&txt) {
+    Ok(v) => v,
+    Err(e) => return report.add("workspace_json_invalid", "error", format!("workspace.json is not valid JSON: {e}"), path, repair()),
+  };
+  let obj = match meta.as_object() {
+    Some(o) => o,
+    None => return report.add("workspace_json_invalid", "error", "workspace.json is not a JSON object".to_string(), path, repair()),
+  };
+
+  match obj.get("schema").and_then(|v| v.as_u64()) {
+    Some(s) if s as u32 > super::workspace_migrations::supported_version() => report.add(
+      "workspace_too_new",
+      "error",
+      format!("Workspace schema {s} is newer than this app supports ({})", super::workspace_migrations::supported_version()),
+      path.clone(),
+      None,
+    ),
+    Some(s) if s >= 1 => {}
+    _ => report.add("workspace_json_field", "warning", "\"schema\" must be a positive integer".to_string(), path.clone(), repair()),
+  }
+  if obj.get("kind").and_then(|v| v.as_str()) != Some("workspace") {
+    report.add("workspace_json_field", "warning", "\"kind\" should be \"workspace\"".to_string(), path.clone(), repair());
+  }
+  if obj.get("id").and_then(|v| v.as_str()).map(|s| s.trim().is_empty()).unwrap_or(true) {
+    report.add("workspace_json_field", "warning", "\"id\" is missing; sync cannot identify this workspace".to_string(), path.clone(), repair());
+  }
+  if obj.get("dbFile").and_then(|v| v.as_str()) != Some(super::WORKSPACE_DB_NAME) {
+    report.add("workspace_json_field", "warning", format!("\"dbFile\" should be \"{}\"", super::WORKSPACE_DB_NAME), path.clone(), repair());
+  }
+  for key in ["createdAt", "updatedAt"] {
+    if !obj.get(key).map(|v| v.is_i64() || v.is_u64()).unwrap_or(false) {
+      report.add("workspace_json_field", "info", format!("\"{key}\" should be a timestamp in milliseconds"), path.clone(), repair());
+    }
+  }
+}
+
+fn check_database(root: &std::path::Path, report: &mut DoctorReport) -> Option<Vec<u8>> {
+  let (_wjson, db, _backups, _media) = super::workspace_paths(root);
+  let path = Some(super::WORKSPACE_DB_NAME.to_string());
+  let disk = match std::fs::read(&db) {
+    Ok(b) => b,
+    Err(_) => {
+      report.add("db_missing", "error", format!("{} is missing", super::WORKSPACE_DB_NAME), path, None);
+      return None;
+    }
+  };
+  report.db_bytes = disk.len() as u64;
+  if !super::is_workspace_db_bytes(&disk) {
+    report.add("db_not_sqlite", "error", format!("{} is not a SQLite database", super::WORKSPACE_DB_NAME), path, None);
+    return Some(disk);
+  }
+  let plain = match super::plain_db_bytes(root, disk.clone()) {
+    Ok(p) => p,
+    Err(_) => {
+      report.add("db_locked", "info", "Database is encrypted and locked; unlock it to check its contents".to_string(), path, None);
+      return Some(disk);
+    }
+  };
+  let conn = match super::workspace_db::open_in_memory(&plain, true) {
+    Ok(c) => c,
+    Err(e) => {
+      report.add("db_unreadable", "error", e, path, None);
+      return Some(disk);
+    }
+  };
+
+  let integrity: Vec<String> = conn
+    .prepare("PRAGMA integrity_check")
+    .and_then(|mut stmt| stmt.query_map([], |r| r.get::<_, String>(0))?.collect())
+    .unwrap_or_else(|e| vec![format!("integrity_check failed: {e}")]);
+  if integrity != ["ok"] {
+    report.add(
+      "db_integrity",
+      "error",
+      format!("Database is corrupt ({}); restore a backup", integrity.join("; ")),
+      path.clone(),
+      None,
+    );
+  }
+
+  let violations: Vec<(String, Option<i64>, String)> = conn
+    .prepare("PRAGMA foreign_key_check")
+    .and_then(|mut stmt| stmt.query_map([], |r| Ok((r.get::<_, String>(0)?, r.get::<_, Option<i64>>(1)?, r.get::<_, String>(2)?)))?.collect())
+    .unwrap_or_default();
+  for (table, rowid, parent) in violations.iter().take(MAX_FK_FINDINGS) {
+    let row = rowid.map(|r| format!(" row {r}")).unwrap_or_default();
+    report.add("db_foreign_key", "warning", format!("{table}{row} points to a missing {parent} row"), path.clone(), None);
+  }
+  if violations.len() > MAX_FK_FINDINGS {
+    report.add(
+      "db_foreign_key",
+      "warning",
+      format!("{} more foreign key violations", violations.len() - MAX_FK_FINDINGS),
+      path,
+      None,
+    );
+  }
+  Some(disk)
+}
+
+fn check_sync_state(root: &std::path::Path, disk_db: Option<&[u8]>, report: &mut DoctorReport) {
+  let state_path = root.join("sync").join("state.json");
+  let path = Some("sync/state.json".to_string());
+  let txt = match std::fs::read_to_string(&state_path) {
+    Ok(t) => t,
+    Err(_) => return,
+  };
+  let state: super::SyncStateV1 = match serde_json::from_str(&txt) {
+    Ok(s) => s,
+    Err(e) => {
+      return report.add("sync_state_invalid", "warning", format!("sync/state.json is not valid: {e}"), path, fix("reset_sync_state", None));
+    }
+  };
+  if state.workspace_kind != "workspace" || state.db_file != super::WORKSPACE_DB_NAME {
+    report.add(
+      "sync_state_layout",
+      "warning",
+      format!("sync/state.json describes a {} ({}), not this workspace", state.workspace_kind, state.db_file),
+      path.clone(),
+      fix("reset_sync_state", None),
+    );
+  }
+  if let Some(bytes) = disk_db {
+    if super::sha256_hex(bytes) != state.sha256 {
+      report.add("sync_pending", "info", "Local database changed since the last sync".to_string(), path, None);
+    }
+  }
+}
+
+fn run_checks(path: &str) -> Result<DoctorReport, String> {
+  let root = std::path::PathBuf::from(path);
+  if !root.is_dir() {
+    return Err("Workspace folder does not exist".to_string());
+  }
+  let (_wjson, _db, backups, _media) = super::workspace_paths(&root);
+  let mut report = DoctorReport { checked_at: chrono::Utc::now().timestamp_millis(), ..Default::default() };
+
+  check_workspace_json(&root, &mut report);
+  let disk_db = check_database(&root, &mut report);
+  check_sync_state(&root, disk_db.as_deref(), &mut report);
+
+  for rel in [super::WORKSPACE_DB_NAME, super::WORKSPACE_JSON_NAME].map(|n| format!("{n}.tmp")) {
+    if root.join(&rel).is_file() {
+      report.add("recoverable_temp_file", "warning", format!("Interrupted write left {rel}; review it in workspace recovery"), Some(rel), None);
+    }
+  }
+  for rel in deletable_leftovers(&root) {
+    report.add("leftover_temp_file", "warning", format!("Leftover temporary file {rel}"), Some(rel.clone()), fix("delete_file", Some(rel)));
+  }
+
+  let (backups_count, backups_bytes) = dir_size(&backups);
+  report.backups_count = backups_count;
+  report.backups_bytes = backups_bytes;
+  if backups_count > MAX_BACKUPS {
+    report.add(
+      "backups_many",
+      "info",
+      format!("{backups_count} backups use {} MB; the oldest can be pruned", backups_bytes / (1024 * 1024)),
+      Some(super::WORKSPACE_BACKUPS_DIR.to_string()),
+      fix("prune_backups", None),
+    );
+  }
+
+  let (conflicts_count, conflicts_bytes) = dir_size(&root.join("sync").join("conflicts"));
+  report.conflicts_bytes = conflicts_bytes;
+  if conflicts_count > 0 {
+    report.add(
+      "sync_conflict_copies",
+      "info",
+      format!("{conflicts_count} conflict copies use {} MB; they can be moved to sync/{TRASH_DIR}", conflicts_bytes / (1024 * 1024)),
+      Some("sync/conflicts".to_string()),
+      fix("clear_conflicts", None),
+    );
+  }
+
+  report.ok = !report.findings.iter().any(|f| f.severity == "error");
+  Ok(report)
+}
+
+/// Runs every check and returns the findings; changes nothing on disk.
+/// Decrypting and checking a large database takes a while, so it runs off
+/// the main thread.
+#[tauri::command]
+pub async fn workspace_doctor(path: String) -> Result<DoctorReport, String> {
+  tauri::async_runtime::spawn_blocking(move || run_checks(&path))
+    .await
+    .map_err(|e| format!("Doctor task failed: {e}"))?
+}
+
+fn repair_workspace_json(root: &std::path::Path) -> Result<(), String> {
+  let wjson = root.join(super::WORKSPACE_JSON_NAME);
+  let mut meta = super::default_workspace_json();
+  let existing = super::read_json_file::<serde_json::Value>(&wjson);
+  let obj = meta.as_object_mut().unwrap();
+  if let Some(old) = existing.as_ref().and_then(|v| v.as_object()) {
+    for (k, v) in old {
+      obj.insert(k.clone(), v.clone());
+    }
+  }
+  let schema_ok = obj.get("schema").and_then(|v| v.as_u64()).map(|s| s >= 1).unwrap_or(false);
+  if !schema_ok {
+    obj.insert("schema".to_string(), serde_json::json!(1));
+  }
+  if obj.get("id").and_then(|v| v.as_str()).map(|s| s.trim().is_empty()).unwrap_or(true) {
+    obj.insert("id".to_string(), serde_json::json!(format!("ws_{}", chrono::Utc::now().timestamp_millis())));
+  }
+  let now = chrono::Utc::now().timestamp_millis();
+  for key in ["createdAt", "updatedAt"] {
+    if !obj.get(key).map(|v| v.is_i64() || v.is_u64()).unwrap_or(false) {
+      obj.insert(key.to_string(), serde_json::json!(now));
+    }
+  }
+  obj.insert("kind".to_string(), serde_json::json!("workspace"));
+  obj.insert("dbFile".to_string(), serde_json::json!(super::WORKSPACE_DB_NAME));
+  let txt = serde_json::to_vec_pretty(&meta).map_err(|e| format!("JSON encode failed: {e}"))?;
+  super::atomic_write(&wjson, &txt)
+}
+
+fn prune_backups(backups: &std::path::Path) -> Result<(), String> {
+  let mut files: Vec<(std::time::SystemTime, std::path::PathBuf)> = std::fs::read_dir(backups)
+    .map_err(|e| format!("Failed listing backups: {e}"))?
+    .flatten()
+    .map(|e| e.path())
+    .filter(|p| p.is_file())
+    .filter(|p| matches!(p.extension().and_then(|x| x.to_str()), Some("rentikpro") | Some("zip")))
+    .map(|p| (std::fs::metadata(&p).and_then(|m| m.modified()).unwrap_or(std::time::UNIX_EPOCH), p))
+    .collect();
+  files.sort_by_key(|f| std::cmp::Reverse(f.0));
+  for (_, p) in files.into_iter().skip(MAX_BACKUPS) {
+    std::fs::remove_file(&p).map_err(|e| format!("Failed removing {}: {e}", p.display()))?;
+  }
+  Ok(())
+}
+
+fn apply_fix(root: &std::path::Path, f: &DoctorFix) -> Result<(), String> {
+  match f.action.as_str() {
+    "delete_file" => {
+      let rel = f.target.as_deref().ok_or_else(|| "Missing target".to_string())?;
+      // Only ever delete what a fresh scan reports: a plain relative path
+      // (an absolute one would make `join` drop the root), never one of the
+      // recoverable temp files.
+      let relative = std::path::Path::new(rel).components().all(|c| matches!(c, std::path::Component::Normal(_)));
+      let rel = rel.replace('\\', "/");
+      if !relative || is_recoverable_temp(&rel) || !deletable_leftovers(root).contains(&rel) {
+        return Err(format!("Refusing to delete {rel}"));
+      }
+      let p = root.join(&rel);
+      std::fs::remove_file(&p).map_err(|e| format!("Failed removing {rel}: {e}"))
+    }
+    "repair_workspace_json" => repair_workspace_json(root),
+    "reset_sync_state" => {
+      let p = root.join("sync").join("state.json");
+      match std::fs::remove_file(&p) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(format!("Failed removing sync/state.json: {e}")),
+        _ => Ok(()),
+      }
+    }
+    "prune_backups" => prune_backups(&root.join(super::WORKSPACE_BACKUPS_DIR)),
+    "clear_conflicts" => {
+      // Conflict copies may be the only copy of someone's edits, so they go
+      // to a dated folder the user can still open.
+      let dir = root.join("sync").join("conflicts");
+      if dir.exists() {
+        let trash = root.join("sync").join(TRASH_DIR);
+        super::ensure_dir(&trash)?;
+        let target = trash.join(format!("conflicts-{}", chrono::Utc::now().format("%Y%m%d-%H%M%S")));
+        std::fs::rename(&dir, &target).map_err(|e| format!("Failed moving sync/conflicts to {}: {e}", rel_of(root, &target)))?;
+      }
+      super::ensure_dir(&dir)
+    }
+    other => Err(format!("Unknown fix action: {other}")),
+  }
+}
+
+fn run_fixes(path: String, fixes: Vec<DoctorFix>) -> Result<DoctorReport, String> {
+  let root = std::path::PathBuf::from(&path);
+  if !root.is_dir() {
+    return Err("Workspace folder does not exist".to_string());
+  }
+  let mut failures = vec![];
+  for f in &fixes {
+    if let Err(e) = apply_fix(&root, f) {
+      failures.push((f.clone(), e));
+    }
+  }
+  let mut report = run_checks(&path)?;
+  for (f, e) in failures {
+    report.add("fix_failed", "error", format!("{}: {e}", f.action), f.target.clone(), None);
+  }
+  report.ok = !report.findings.iter().any(|f| f.severity == "error");
+  Ok(report)
+}
+
+/// Applies fixes taken from a previous report and returns a fresh report.
+/// Failed fixes show up as `fix_failed` findings.
+#[tauri::command]
+pub async fn workspace_doctor_fix(path: String, fixes: Vec<DoctorFix>) -> Result<DoctorReport, String> {
+  tauri::async_runtime::spawn_blocking(move || run_fixes(path, fixes))
+    .await
+    .map_err(|e| format!("Doctor task failed: {e}"))?
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn codes(report: &DoctorReport) -> Vec<&str> {
+    report.findings.iter().map(|f| f.code.as_str()).collect()
+  }
+
+  fn delete(target: &str) -> DoctorFix {
+    DoctorFix { action: "delete_file".to_string(), target: Some(target.to_string()) }
+  }
+
+  #[test]
+  fn workspace_json_checks() {
+    let dir = tempfile::tempdir().unwrap();
+    let wjson = dir.path().join(super::super::WORKSPACE_JSON_NAME);
+
+    let mut report = DoctorReport::default();
+    check_workspace_json(dir.path(), &mut report);
+    assert_eq!(codes(&report), vec!["workspace_json_missing"]);
+
+    std::fs::write(&wjson, b"[1]").unwrap();
+    let mut report = DoctorReport::default();
+    check_workspace_json(dir.path(), &mut report);
+    assert_eq!(codes(&report), vec!["workspace_json_invalid"]);
+
+    std::fs::write(&wjson, br#"{"schema": 999, "kind": "workspace", "id": "ws_1", "dbFile": "database.sqlite", "createdAt": 1, "updatedAt": 2}"#).unwrap();
+    let mut report = DoctorReport::default();
+    check_workspace_json(dir.path(), &mut report);
+    assert_eq!(codes(&report), vec!["workspace_too_new"]);
+
+    std::fs::write(&wjson, br#"{"schema": 1, "kind": "project"}"#).unwrap();
+    repair_workspace_json(dir.path()).unwrap();
+    let mut report = DoctorReport::default();
+    check_workspace_json(dir.path(), &mut report);
+    assert!(report.findings.is_empty(), "{:?}", codes(&report));
+  }
+
+  #[test]
+  fn leftovers_skip_backups_and_recoverable_temps() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    std::fs::create_dir_all(root.join("sync")).unwrap();
+    std::fs::create_dir_all(root.join(super::super::WORKSPACE_BACKUPS_DIR)).unwrap();
+    std::fs::write(root.join("sync").join("state.json.tmp"), b"x").unwrap();
+    std::fs::write(root.join("database.sqlite.uploading.c1.5"), b"x").unwrap();
+    std::fs::write(root.join(super::super::WORKSPACE_BACKUPS_DIR).join("a.zip.tmp"), b"x").unwrap();
+    std::fs::write(root.join("database.sqlite.tmp"), b"x").unwrap();
+
+    let mut found = deletable_leftovers(root);
+    found.sort();
+    assert_eq!(found, vec!["database.sqlite.uploading.c1.5", "sync/state.json.tmp"]);
+  }
+
+  #[test]
+  fn delete_file_only_removes_reported_leftovers() {
+    let dir = tempfile::tempdir().unwrap();
+    let outside = tempfile::tempdir().unwrap();
+    let root = dir.path().join("ws");
+    std::fs::create_dir_all(root.join("sync")).unwrap();
+    let stray = outside.path().join("elsewhere.tmp");
+    std::fs::write(&stray, b"x").unwrap();
+    std::fs::write(root.join("database.sqlite.tmp"), b"x").unwrap();
+    std::fs::write(root.join("notes.txt"), b"x").unwrap();
+    std::fs::write(root.join("sync").join("state.json.tmp"), b"x").unwrap();
+
+    assert!(apply_fix(&root, &delete(&stray.to_string_lossy())).is_err());
+    assert!(apply_fix(&root, &delete("../ws/sync/state.json.tmp")).is_err());
+    assert!(apply_fix(&root, &delete("database.sqlite.tmp")).is_err());
+    assert!(apply_fix(&root, &delete("notes.txt")).is_err());
+    assert!(stray.exists() && root.join("database.sqlite.tmp").exists() && root.join("notes.txt").exists());
+
+    apply_fix(&root, &delete("sync/state.json.tmp")).unwrap();
+    assert!(!root.join("sync").join("state.json.tmp").exists());
+  }
+}
@@ -0,0 +1,203 @@
+// Versioned schema migrations for `database.sqlite`, run by `open_workspace`.
+//
+// The webview still creates its tables with `CREATE TABLE IF NOT EXISTS` in
+// `sqliteStore.ts`; these migrations are the changes that must happen exactly
+// once, in order, before it sees the database. Applied versions are recorded
+// in `schema_migrations` and mirrored in the `schema` field of
+// `workspace.json`, so a workspace from a newer app is refused instead of
+// being silently downgraded on the next save.
+
+pub const MIGRATIONS_TABLE: &str = "schema_migrations";
+
+/// Prefix of the `WORKSPACE_TOO_NEW` error, for the UI to match on.
+pub const TOO_NEW_ERROR: &str = "WORKSPACE_TOO_NEW";
+
+struct Migration {
+  version: u32,
+  name: &'static str,
+  up: fn(&rusqlite::Transaction) -> rusqlite::Result<()>,
+}
+
+// Append only. Never renumber or edit a migration that has shipped.
+const MIGRATIONS: &[Migration] = &[
+  Migration { version: 1, name: "baseline", up: |_| Ok(()) },
+  Migration { version: 2, name: "bookings_apartment_index", up: bookings_apartment_index },
+];
+
+pub fn supported_version() -> u32 {
+  MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+fn bookings_apartment_index(tx: &rusqlite::Transaction) -> rusqlite::Result<()> {
+  // Older databases may predate the table; the webview creates it with the
+  // index-free DDL later, so only index what is already there.
+  if super::workspace_db::has_column(tx, "bookings", "apartment_id") && super::workspace_db::has_column(tx, "bookings", "check_in") {
+    tx.execute_batch("CREATE INDEX IF NOT EXISTS idx_bookings_apartment_checkin ON bookings(apartment_id, check_in);")?;
+  }
+  Ok(())
+}
+
+#[derive(serde::Serialize, Clone, Default)]
+pub struct MigrationReport {
+  pub schema_version: u32,
+  pub applied: Vec<String>,
+  pub backup: Option<String>,
+}
+
+fn ensure_table(conn: &rusqlite::Connection) -> Result<(), String> {
+  conn
+    .execute_batch(&format!(
+      "CREATE TABLE IF NOT EXISTS {MIGRATIONS_TABLE} (version INTEGER PRIMARY KEY, name TEXT NOT NULL, applied_at INTEGER NOT NULL);"
+    ))
+    .map_err(|e| format!("Failed creating {MIGRATIONS_TABLE}: {e}"))
+}
+
+fn current_version(conn: &rusqlite::Connection) -> Result<u32, String> {
+  conn
+    .query_row(&format!("SELECT COALESCE(MAX(version), 0) FROM {MIGRATIONS_TABLE}"), [], |r| r.get::<_, u32>(0))
+    .map_err(|e| format!("Failed reading {MIGRATIONS_TABLE}: {e}"))
+}
+
+fn too_new(found: u32) -> String {
+  format!(
+    "{TOO_NEW_ERROR}: workspace schema {found} is newer than this app supports ({}); update RentikPro to open it",
+    supported_version()
+  )
+}
+
+/// Fails when `workspace.json` says the workspace was written by a newer app.
+/// Cheap enough to call before touching the database.
+pub fn check_workspace_json(root: &std::path::Path) -> Result<(), String> {
+  let meta = super::read_json_file::<serde_json::Value>(&root.join(super::WORKSPACE_JSON_NAME));
+  let schema = meta.as_ref().and_then(|m| m.get("schema")).and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+  if schema > supported_version() {
+    return Err(too_new(schema));
+  }
+  Ok(())
+}
+
+fn record_schema_in_workspace_json(root: &std::path::Path, version: u32) -> Result<(), String> {
+  let wjson = root.join(super::WORKSPACE_JSON_NAME);
+  let mut meta = super::read_json_file::<serde_json::Value>(&wjson).unwrap_or_else(super::default_workspace_json);
+  if let Some(obj) = meta.as_object_mut() {
+    obj.insert("schema".to_string(), serde_json::json!(version));
+    obj.insert("updatedAt".to_string(), serde_json::json!(chrono::Utc::now().timestamp_millis()));
+  }
+  let txt = serde_json::to_vec_pretty(&meta).map_err(|e| format!("JSON encode failed: {e}"))?;
+  super::atomic_write(&wjson, &txt)
+}
+
+/// Brings the plain database `plain` up to date. When migrations run, the
+/// workspace is backed up first, the result is written back to disk
+/// (re-encrypted if the file was encrypted) and the new bytes returned.
+pub fn migrate_workspace(root: &std::path::Path, plain: Vec<u8>) -> Result<(Vec<u8>, MigrationReport), String> {
+  check_workspace_json(root)?;
+
+  let mut conn = super::workspace_db::open_in_memory(&plain, false)?;
+  ensure_table(&conn)?;
+  let current = current_version(&conn)?;
+  if current > supported_version() {
+    return Err(too_new(current));
+  }
+  let pending: Vec<&Migration> = MIGRATIONS.iter().filter(|m| m.version > current).collect();
+  if pending.is_empty() {
+    return Ok((plain, MigrationReport { schema_version: current, ..Default::default() }));
+  }
+
+  let backup = super::create_backup_internal(root, "autobackup_before_migration_")?;
+
+  let tx = conn.transaction().map_err(|e| format!("Migration transaction failed: {e}"))?;
+  let now = chrono::Utc::now().timestamp_millis();
+  let mut applied = vec![];
+  for m in &pending {
+    (m.up)(&tx).map_err(|e| format!("Migration {} ({}) failed: {e}", m.version, m.name))?;
+    tx.execute(
+      &format!("INSERT INTO {MIGRATIONS_TABLE} (version, name, applied_at) VALUES (?1, ?2, ?3)"),
+      rusqlite::params![m.version, m.name, now],
+    )
+    .map_err(|e| format!("Failed recording migration {}: {e}", m.version))?;
+    applied.push(format!("{:04}_{}", m.version, m.name));
+  }
+  tx.commit().map_err(|e| format!("Migration commit failed: {e}"))?;
+
+  let migrated = super::workspace_db::to_bytes(&conn)?;
+  let (_wjson, db, _backups, _media) = super::workspace_paths(root);
+  let on_disk = match super::db_crypto::session_key(root) {
+    Some(key) => super::db_crypto::encrypt_db(&migrated, &key)?,
+    None if super::db_crypto::file_is_encrypted(&db) => return Err(super::db_crypto::LOCKED_ERROR.to_string()),
+    None => migrated.clone(),
+  };
+  super::atomic_write(&db, &on_disk)?;
+
+  let version = supported_version();
+  record_schema_in_workspace_json(root, version)?;
+  Ok((migrated, MigrationReport { schema_version: version, applied, backup: Some(backup) }))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn workspace(db_sql: &str) -> (tempfile::TempDir, Vec<u8>) {
+    let dir = tempfile::tempdir().unwrap();
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    conn.execute_batch(db_sql).unwrap();
+    let plain = super::super::workspace_db::to_bytes(&conn).unwrap();
+    let (_wjson, db, _backups, _media) = super::super::workspace_paths(dir.path());
+    std::fs::write(&db, &plain).unwrap();
+    (dir, plain)
+  }
+
+  fn schema_in_json(root: &std::path::Path) -> u64 {
+    let meta = super::super::read_json_file::<serde_json::Value>(&root.join(super::super::WORKSPACE_JSON_NAME)).unwrap();
+    meta["schema"].as_u64().unwrap()
+  }
+
+  #[test]
+  fn applies_pending_migrations_once() {
+    let (dir, plain) = workspace("CREATE TABLE bookings (id TEXT PRIMARY KEY, apartment_id TEXT, check_in TEXT);");
+    let (migrated, report) = migrate_workspace(dir.path(), plain).unwrap();
+    assert_eq!(report.applied, vec!["0001_baseline", "0002_bookings_apartment_index"]);
+    assert_eq!(report.schema_version, supported_version());
+    assert!(dir.path().join("backups").join(report.backup.unwrap()).is_file());
+    assert_eq!(schema_in_json(dir.path()), supported_version() as u64);
+
+    let (_wjson, db, _backups, _media) = super::super::workspace_paths(dir.path());
+    assert_eq!(std::fs::read(&db).unwrap(), migrated);
+    let conn = super::super::workspace_db::open_in_memory(&migrated, true).unwrap();
+    let indexed: i64 = conn
+      .query_row("SELECT COUNT(*) FROM sqlite_master WHERE name = 'idx_bookings_apartment_checkin'", [], |r| r.get(0))
+      .unwrap();
+    assert_eq!(indexed, 1);
+
+    let (again, report) = migrate_workspace(dir.path(), migrated.clone()).unwrap();
+    assert!(report.applied.is_empty() && report.backup.is_none());
+    assert_eq!(again, migrated);
+  }
+
+  #[test]
+  fn skips_index_when_bookings_table_is_missing() {
+    let (dir, plain) = workspace("CREATE TABLE apartments (id TEXT PRIMARY KEY);");
+    let (_migrated, report) = migrate_workspace(dir.path(), plain).unwrap();
+    assert_eq!(report.applied.len(), MIGRATIONS.len());
+  }
+
+  #[test]
+  fn refuses_newer_database() {
+    let (dir, plain) = workspace("CREATE TABLE schema_migrations (version INTEGER PRIMARY KEY, name TEXT NOT NULL, applied_at INTEGER NOT NULL); INSERT INTO schema_migrations VALUES (999, 'future', 0);");
+    let err = migrate_workspace(dir.path(), plain.clone()).err().unwrap();
+    assert!(err.starts_with(TOO_NEW_ERROR), "{err}");
+    // Nothing was written or backed up.
+    let (_wjson, db, backups, _media) = super::super::workspace_paths(dir.path());
+    assert_eq!(std::fs::read(&db).unwrap(), plain);
+    assert!(!backups.exists());
+  }
+
+  #[test]
+  fn refuses_newer_workspace_json() {
+    let (dir, plain) = workspace("CREATE TABLE apartments (id TEXT PRIMARY KEY);");
+    std::fs::write(dir.path().join(super::super::WORKSPACE_JSON_NAME), br#"{"schema": 999}"#).unwrap();
+    assert!(check_workspace_json(dir.path()).err().unwrap().starts_with(TOO_NEW_ERROR));
+    assert!(migrate_workspace(dir.path(), plain).is_err());
+  }
+}
@@ -0,0 +1,273 @@
+// `move_workspace`: relocates a whole workspace folder, including `media/`,
+// `backups/` and `sync/`, possibly to another disk.
+//
+// Same-volume moves are a single rename. Otherwise every file is copied while
+// hashing the source, re-read from the destination and compared, and the
+// source is deleted only once the whole copy has verified.
+
+use sha2::Digest;
+use tauri::{Emitter, Manager};
+
+pub const PROGRESS_EVENT: &str = "workspace-move:progress";
+const COPY_BUFFER_BYTES: usize = 1024 * 1024;
+
+#[derive(serde::Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+struct MoveProgressEvent {
+  /// "scanning" | "copying" | "verifying" | "removing_source" | "done"
+  phase: String,
+  from: String,
+  to: String,
+  files_done: usize,
+  files_total: usize,
+  bytes_done: u64,
+  bytes_total: u64,
+  current: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MoveWorkspaceResult {
+  to: String,
+  /// "rename" | "copy"
+  method: String,
+  files: usize,
+  bytes: u64,
+  registry_updated: bool,
+  /// The sync scheduler / iCal server were running on the old folder and now
+  /// run on the new one.
+  sync_scheduler_moved: bool,
+  ical_server_moved: bool,
+  source_removed: bool,
+  /// Set when the copy succeeded but the source could not be fully deleted.
+  warning: Option<String>,
+}
+
+fn collect_files(root: &std::path::Path, dir: &std::path::Path, out: &mut Vec<(std::path::PathBuf, u64)>) -> Result<(), String> {
+  let entries = std::fs::read_dir(dir).map_err(|e| format!("Failed reading {}: {e}", dir.display()))?;
+  for entry in entries {
+    let entry = entry.map_err(|e| format!("Failed reading entry in {}: {e}", dir.display()))?;
+    let ft = entry.file_type().map_err(|e| format!("Failed reading {}: {e}", entry.path().display()))?;
+    if ft.is_dir() {
+      collect_files(root, &entry.path(), out)?;
+    } else if ft.is_file() {
+      let len = entry.metadata().map(|m| m.len()).unwrap_or(0);
+      let rel = entry.path().strip_prefix(root).map(|p| p.to_path_buf()).unwrap_or_default();
+      out.push((rel, len));
+    } else {
+      // Symlinks and special files cannot be verified byte for byte.
+      return Err(format!("Unsupported file type at {}; move it out of the workspace first", entry.path().display()));
+    }
+  }
+  Ok(())
+}
+
+/// Copies `from` to `to`, returning the sha256 of what was read.
+fn copy_hashing(from: &std::path::Path, to: &std::path::Path) -> Result<String, String> {
+  use std::io::{Read, Write};
+  if let Some(parent) = to.parent() {
+    super::ensure_dir(parent)?;
+  }
+  let mut src = std::fs::File::open(from).map_err(|e| format!("Failed opening {}: {e}", from.display()))?;
+  let mut dst = std::fs::File::create(to).map_err(|e| format!("Failed creating {}: {e}", to.display()))?;
+  let mut hasher = sha2::Sha256::new();
+  let mut buf = vec![0u8; COPY_BUFFER_BYTES];
+  loop {
+    let n = src.read(&mut buf).map_err(|e| format!("Failed reading {}: {e}", from.display()))?;
+    if n == 0 {
+      break;
+    }
+    hasher.update(&buf[..n]);
+    dst.write_all(&buf[..n]).map_err(|e| format!("Failed writing {}: {e}", to.display()))?;
+  }
+  dst.sync_all().map_err(|e| format!("Failed flushing {}: {e}", to.display()))?;
+  Ok(hex::encode(hasher.finalize()))
+}
+
+fn hash_file(path: &std::path::Path) -> Result<String, String> {
+  use std::io::Read;
+  let mut f = std::fs::File::open(path).map_err(|e| format!("Failed opening {}: {e}", path.display()))?;
+  let mut hasher = sha2::Sha256::new();
+  let mut buf = vec![0u8; COPY_BUFFER_BYTES];
+  loop {
+    let n = f.read(&mut buf).map_err(|e| format!("Failed reading {}: {e}", path.display()))?;
+    if n == 0 {
+      break;
+    }
+    hasher.update(&buf[..n]);
+  }
+  Ok(hex::encode(hasher.finalize()))
+}
+
+fn copy_tree(app: &tauri::AppHandle, from: &std::path::Path, to: &std::path::Path, base: &MoveProgressEvent) -> Result<(usize, u64), String> {
+  let _ = app.emit(PROGRESS_EVENT, MoveProgressEvent { phase: "scanning".to_string(), ..base.clone() });
+  let mut files = vec![];
+  collect_files(from, from, &mut files)?;
+  let bytes_total: u64 = files.iter().map(|(_, len)| len).sum();
+  let mut progress = MoveProgressEvent { files_total: files.len(), bytes_total, ..base.clone() };
+
+  let mut hashes = Vec::with_capacity(files.len());
+  for (rel, len) in &files {
+    progress.phase = "copying".to_string();
+    progress.current = Some(rel.to_string_lossy().replace('\\', "/"));
+    let _ = app.emit(PROGRESS_EVENT, progress.clone());
+    hashes.push(copy_hashing(&from.join(rel), &to.join(rel))?);
+    progress.files_done += 1;
+    progress.bytes_done += len;
+  }
+
+  // Re-read everything only after the whole tree is written, so the check
+  // does not just hit the page cache of the file written a moment ago.
+  progress.files_done = 0;
+  progress.bytes_done = 0;
+  for ((rel, len), expected) in files.iter().zip(&hashes) {
+    progress.phase = "verifying".to_string();
+    progress.current = Some(rel.to_string_lossy().replace('\\', "/"));
+    let _ = app.emit(PROGRESS_EVENT, progress.clone());
+    if &hash_file(&to.join(rel))? != expected {
+      return Err(format!("Verification failed for {}", rel.display()));
+    }
+    // The source changing under us means the copy is already stale.
+    if &hash_file(&from.join(rel))? != expected {
+      return Err(format!("{} changed during the move; close other apps using the workspace and retry", rel.display()));
+    }
+    progress.files_done += 1;
+    progress.bytes_done += len;
+  }
+  Ok((files.len(), bytes_total))
+}
+
+fn is_same_or_inside(a: &std::path::Path, b: &std::path::Path) -> bool {
+  a == b || a.starts_with(b)
+}
+
+fn move_blocking(app: &tauri::AppHandle, from: std::path::PathBuf, to: std::path::PathBuf) -> Result<MoveWorkspaceResult, String> {
+  // The old folder disappearing is not an external change.
+  super::workspace_watcher::unwatch(&from);
+  // Looked up while `from` still exists, so its path can be canonicalized.
+  let registered = super::workspace_registry::find(app, &from.to_string_lossy()).ok().flatten();
+  let base = MoveProgressEvent {
+    from: from.to_string_lossy().to_string(),
+    to: to.to_string_lossy().to_string(),
+    ..Default::default()
+  };
+
+  // Same volume: one atomic rename, nothing to verify.
+  let renamed = std::fs::rename(&from, &to).is_ok();
+  let (method, files, bytes, source_removed, warning) = if renamed {
+    ("rename", 0, 0, true, None)
+  } else {
+    super::ensure_dir(&to)?;
+    let (files, bytes) = match copy_tree(app, &from, &to, &base) {
+      Ok(v) => v,
+      Err(e) => {
+        let _ = std::fs::remove_dir_all(&to);
+        return Err(format!("Move aborted, workspace left at its original location: {e}"));
+      }
+    };
+    super::sync_dir(&to);
+    let _ = app.emit(PROGRESS_EVENT, MoveProgressEvent { phase: "removing_source".to_string(), ..base.clone() });
+    let (removed, warning) = match std::fs::remove_dir_all(&from) {
+      Ok(()) => (true, None),
+      Err(e) => (false, Some(format!("Workspace copied, but the old folder could not be removed: {e}"))),
+    };
+    ("copy", files, bytes, removed, warning)
+  };
+
+  if let Some(key) = super::db_crypto::session_key(&from) {
+    super::db_crypto::remember_key(&to, key);
+    super::db_crypto::forget_key(&from);
+  }
+  let registry_updated = match registered {
+    Some(entry) => super::workspace_registry::relocate(app, &entry.path, &base.to).unwrap_or(false),
+    None => false,
+  };
+  let _ = app.emit(PROGRESS_EVENT, MoveProgressEvent { phase: "done".to_string(), files_done: files, files_total: files, bytes_done: bytes, bytes_total: bytes, ..base.clone() });
+
+  Ok(MoveWorkspaceResult {
+    to: base.to,
+    method: method.to_string(),
+    files,
+    bytes,
+    registry_updated,
+    sync_scheduler_moved: false,
+    ical_server_moved: false,
+    source_removed,
+    warning,
+  })
+}
+
+/// Moves the workspace at `from` to the folder `to`, which must not exist yet
+/// (or be empty). The UI should close the workspace first and reopen it at
+/// the returned path. A sync scheduler or iCal server running on the folder
+/// is stopped for the move and restarted on wherever the workspace ends up.
+#[tauri::command]
+pub async fn move_workspace(app: tauri::AppHandle, from: String, to: String) -> Result<MoveWorkspaceResult, String> {
+  let from = std::fs::canonicalize(&from).map_err(|e| format!("Workspace folder not found: {e}"))?;
+  let (wjson, db, _backups, _media) = super::workspace_paths(&from);
+  if !wjson.exists() && !db.exists() {
+    return Err("Not a workspace folder: missing workspace.json and database.sqlite".to_string());
+  }
+
+  let to = std::path::PathBuf::from(&to);
+  let parent = to.parent().ok_or_else(|| "Invalid destination".to_string())?;
+  let parent = std::fs::canonicalize(parent).map_err(|e| format!("Destination parent folder not found: {e}"))?;
+  let to = match to.file_name() {
+    Some(name) => parent.join(name),
+    None => return Err("Invalid destination".to_string()),
+  };
+  if is_same_or_inside(&to, &from) || is_same_or_inside(&from, &to) {
+    return Err("Destination cannot be the workspace itself or inside it".to_string());
+  }
+  if to.exists() {
+    let empty = std::fs::read_dir(&to).map(|mut d| d.next().is_none()).unwrap_or(false);
+    if !empty {
+      return Err("Destination already exists and is not empty".to_string());
+    }
+    std::fs::remove_dir(&to).map_err(|e| format!("Failed preparing destination: {e}"))?;
+  }
+
+  // The scheduler's current run needs the guard to finish, so stop it first;
+  // then no sync may read or write the folder while it moves.
+  let scheduler = app.state::<super::sync_scheduler::SyncSchedulerState>().take_for(&from).await;
+  let _guard = super::sync_scheduler::LOCAL_SYNC_GUARD.lock().await;
+  let ical = app.state::<super::ical_server::IcalServerState>().take_for(&from);
+
+  let moved = {
+    let (app, from, to) = (app.clone(), from.clone(), to.clone());
+    tauri::async_runtime::spawn_blocking(move || move_blocking(&app, from, to))
+      .await
+      .map_err(|e| format!("Move task failed: {e}"))
+      .and_then(|r| r)
+  };
+  let now_at = match &moved {
+    Ok(_) => to,
+    Err(_) => from,
+  };
+  let now_at = now_at.to_string_lossy().to_string();
+
+  let sync_scheduler_moved = match scheduler {
+    Some(mut config) => {
+      config.project_path = now_at.clone();
+      app.state::<super::sync_scheduler::SyncSchedulerState>().start(app.clone(), config).await;
+      true
+    }
+    None => false,
+  };
+  let ical_restart = ical.map(|(port, lan)| app.state::<super::ical_server::IcalServerState>().start(now_at, port, lan));
+
+  let mut result = moved?;
+  result.sync_scheduler_moved = sync_scheduler_moved;
+  match ical_restart {
+    Some(Ok(_)) => result.ical_server_moved = true,
+    Some(Err(e)) => {
+      let msg = format!("iCal server could not be restarted on the new folder: {e}");
+      result.warning = Some(match result.warning.take() {
+        Some(w) => format!("{w}; {msg}"),
+        None => msg,
+      });
+    }
+    None => {}
+  }
+  Ok(result)
+}
@@ -0,0 +1,225 @@
+// Recovery of `database.sqlite.tmp` / `workspace.json.tmp` left behind when
+// the app dies in the middle of `atomic_write` or `restore_backup`.
+//
+// `open_workspace` reports what it finds; nothing is promoted or discarded
+// until the user picks an action through `workspace_recovery_resolve`.
+
+/// Prefix of the `open_workspace` error when a primary file is missing but a
+/// usable temp copy exists.
+pub const RECOVERY_AVAILABLE_ERROR: &str = "RECOVERY_AVAILABLE";
+
+#[derive(serde::Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OrphanTemp {
+  /// Temp file name, relative to the workspace root.
+  file: String,
+  /// File it was meant to replace.
+  target: String,
+  size: u64,
+  modified_at: i64,
+  valid: bool,
+  target_exists: bool,
+  target_valid: bool,
+  /// "promote" | "discard"
+  recommended: String,
+  reason: String,
+}
+
+fn modified_ms(path: &std::path::Path) -> i64 {
+  std::fs::metadata(path)
+    .and_then(|m| m.modified())
+    .ok()
+    .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+    .map(|d| d.as_millis() as i64)
+    .unwrap_or(0)
+}
+
+/// A complete database: right magic and, when we can read it, a passing
+/// `quick_check`. A truncated write fails one or the other.
+fn db_is_valid(root: &std::path::Path, bytes: &[u8]) -> bool {
+  if !super::is_workspace_db_bytes(bytes) {
+    return false;
+  }
+  let plain = match super::plain_db_bytes(root, bytes.to_vec()) {
+    Ok(p) => p,
+    // Locked: without the key only the header can be checked.
+    Err(_) => return super::db_crypto::is_encrypted_db(bytes),
+  };
+  match super::workspace_db::open_in_memory(&plain, true) {
+    Ok(conn) => conn
+      .query_row("PRAGMA quick_check", [], |r| r.get::<_, String>(0))
+      .map(|s| s == "ok")
+      .unwrap_or(false),
+    Err(_) => false,
+  }
+}
+
+fn json_is_valid(bytes: &[u8]) -> bool {
+  serde_json::from_slice::<serde_json::Value>(bytes).map(|v| v.is_object()).unwrap_or(false)
+}
+
+fn is_valid(root: &std::path::Path, target: &str, bytes: &[u8]) -> bool {
+  if target == super::WORKSPACE_DB_NAME {
+    db_is_valid(root, bytes)
+  } else {
+    json_is_valid(bytes)
+  }
+}
+
+/// Orphaned temp files for the workspace's two primary files.
+pub fn scan(root: &std::path::Path) -> Vec<OrphanTemp> {
+  let mut out = vec![];
+  for target in [super::WORKSPACE_DB_NAME, super::WORKSPACE_JSON_NAME] {
+    let file = format!("{target}.tmp");
+    let tmp_path = root.join(&file);
+    let bytes = match std::fs::read(&tmp_path) {
+      Ok(b) => b,
+      Err(_) => continue,
+    };
+    let target_path = root.join(target);
+    let valid = is_valid(root, target, &bytes);
+    let target_bytes = std::fs::read(&target_path).ok();
+    let target_exists = target_bytes.is_some();
+    let target_valid = target_bytes.map(|b| is_valid(root, target, &b)).unwrap_or(false);
+    let modified_at = modified_ms(&tmp_path);
+
+    let (recommended, reason) = if !valid {
+      ("discard", "Temp file is incomplete or corrupt")
+    } else if !target_valid {
+      ("promote", if target_exists { "Current file is damaged; the temp copy is intact" } else { "Current file is missing; the temp copy is intact" })
+    } else if modified_at > modified_ms(&target_path) {
+      ("promote", "Temp copy is a complete, newer save that was never renamed into place")
+    } else {
+      ("discard", "Current file is intact and at least as new")
+    };
+    out.push(OrphanTemp {
+      file,
+      target: target.to_string(),
+      size: bytes.len() as u64,
+      modified_at,
+      valid,
+      target_exists,
+      target_valid,
+      recommended: recommended.to_string(),
+      reason: reason.to_string(),
+    });
+  }
+  out
+}
+
+/// Error for `open_workspace` when a primary file is gone but its temp copy
+/// is intact; opening anyway would recreate it empty.
+pub fn missing_file_error(root: &std::path::Path) -> Option<String> {
+  scan(root)
+    .into_iter()
+    .find(|o| !o.target_exists && o.valid)
+    .map(|o| format!("{RECOVERY_AVAILABLE_ERROR}: {} is missing but {} can be recovered", o.target, o.file))
+}
+
+#[tauri::command]
+pub fn workspace_recovery_scan(path: String) -> Result<Vec<OrphanTemp>, String> {
+  let root = std::path::PathBuf::from(&path);
+  if !root.is_dir() {
+    return Err("Workspace folder does not exist".to_string());
+  }
+  Ok(scan(&root))
+}
+
+/// Promotes (`action` = "promote") or deletes ("discard") one orphaned temp
+/// file from `workspace_recovery_scan`. The file being replaced by a promote
+/// is backed up first when it is still a readable database.
+#[tauri::command]
+pub fn workspace_recovery_resolve(path: String, file: String, action: String) -> Result<Vec<OrphanTemp>, String> {
+  let root = std::path::PathBuf::from(&path);
+  if !root.is_dir() {
+    return Err("Workspace folder does not exist".to_string());
+  }
+  let orphan = scan(&root)
+    .into_iter()
+    .find(|o| o.file == file)
+    .ok_or_else(|| format!("No recoverable temp file named {file}"))?;
+  let tmp_path = root.join(&orphan.file);
+  let target_path = root.join(&orphan.target);
+
+  match action.as_str() {
+    "promote" => {
+      if !orphan.valid {
+        return Err(format!("Refusing to promote {}: it is incomplete or corrupt", orphan.file));
+      }
+      if orphan.target == super::WORKSPACE_DB_NAME && orphan.target_valid {
+        super::create_backup_internal(&root, "autobackup_before_recovery_")?;
+      }
+      let promoted = std::fs::read(&tmp_path).map_err(|e| format!("Failed reading {}: {e}", orphan.file))?;
+      std::fs::rename(&tmp_path, &target_path).map_err(|e| format!("Failed promoting {}: {e}", orphan.file))?;
+      super::sync_dir(&root);
+      if orphan.target == super::WORKSPACE_DB_NAME {
+        super::workspace_watcher::record(&target_path, &promoted);
+      }
+    }
+    "discard" => {
+      std::fs::remove_file(&tmp_path).map_err(|e| format!("Failed removing {}: {e}", orphan.file))?;
+    }
+    other => return Err(format!("Unknown recovery action: {other}")),
+  }
+  Ok(scan(&root))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn sqlite_bytes() -> Vec<u8> {
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    conn.execute_batch("CREATE TABLE t (id INTEGER PRIMARY KEY); INSERT INTO t VALUES (1);").unwrap();
+    super::super::workspace_db::to_bytes(&conn).unwrap()
+  }
+
+  fn set_modified(path: &std::path::Path, secs_ago: u64) {
+    let f = std::fs::File::options().write(true).open(path).unwrap();
+    f.set_modified(std::time::SystemTime::now() - std::time::Duration::from_secs(secs_ago)).unwrap();
+  }
+
+  fn recommendation(root: &std::path::Path, target: &str) -> (String, bool) {
+    let o = scan(root).into_iter().find(|o| o.target == target).unwrap();
+    (o.recommended, o.valid)
+  }
+
+  #[test]
+  fn discards_incomplete_temp() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = sqlite_bytes();
+    std::fs::write(dir.path().join(super::super::WORKSPACE_DB_NAME), &db).unwrap();
+    std::fs::write(dir.path().join("database.sqlite.tmp"), &db[..db.len() / 2]).unwrap();
+    std::fs::write(dir.path().join("workspace.json.tmp"), b"{\"id\": ").unwrap();
+    assert_eq!(recommendation(dir.path(), super::super::WORKSPACE_DB_NAME), ("discard".to_string(), false));
+    assert_eq!(recommendation(dir.path(), super::super::WORKSPACE_JSON_NAME), ("discard".to_string(), false));
+  }
+
+  #[test]
+  fn promotes_when_target_is_missing_or_damaged() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("database.sqlite.tmp"), sqlite_bytes()).unwrap();
+    assert_eq!(recommendation(dir.path(), super::super::WORKSPACE_DB_NAME), ("promote".to_string(), true));
+    assert!(missing_file_error(dir.path()).unwrap().starts_with(RECOVERY_AVAILABLE_ERROR));
+
+    std::fs::write(dir.path().join(super::super::WORKSPACE_DB_NAME), b"SQLite format 3\0truncated").unwrap();
+    assert_eq!(recommendation(dir.path(), super::super::WORKSPACE_DB_NAME), ("promote".to_string(), true));
+    assert!(missing_file_error(dir.path()).is_none());
+  }
+
+  #[test]
+  fn compares_age_when_both_are_intact() {
+    let dir = tempfile::tempdir().unwrap();
+    let target = dir.path().join(super::super::WORKSPACE_JSON_NAME);
+    let tmp = dir.path().join("workspace.json.tmp");
+    std::fs::write(&target, b"{\"id\": \"a\"}").unwrap();
+    std::fs::write(&tmp, b"{\"id\": \"b\"}").unwrap();
+
+    set_modified(&target, 60);
+    set_modified(&tmp, 0);
+    assert_eq!(recommendation(dir.path(), super::super::WORKSPACE_JSON_NAME).0, "promote");
+
+    set_modified(&tmp, 120);
+    assert_eq!(recommendation(dir.path(), super::super::WORKSPACE_JSON_NAME).0, "discard");
+  }
+}
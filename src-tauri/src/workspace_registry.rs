@@ -0,0 +1,339 @@
+// Per-user list of known workspaces, stored as `recent-workspaces.json` in the
+// app data directory (not in any workspace, since it spans all of them).
+//
+// `open_workspace` records every workspace it opens; the startup screen lists
+// them with `recent_workspaces_list`, which also tells a deleted folder apart
+// from one on a drive that is just not mounted right now.
+
+use tauri::Manager;
+
+const REGISTRY_FILE_NAME: &str = "recent-workspaces.json";
+/// Unpinned entries kept, most recently opened first.
+const MAX_RECENT: usize = 20;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct RecentWorkspaceV1 {
+  pub path: String,
+  pub id: Option<String>,
+  pub name: Option<String>,
+  pub last_opened_at: Option<i64>,
+  pub pinned: bool,
+  /// WebDAV slug last used for this workspace; inferred from the folder name
+  /// when never set, like `webdavSync.ts` does.
+  pub sync_slug: Option<String>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct RegistryFileV1 {
+  pub version: u32,
+  pub workspaces: Vec<RecentWorkspaceV1>,
+}
+
+// Registry writes are read-modify-write from several commands.
+static REGISTRY_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+fn registry_path(app: &tauri::AppHandle) -> Result<std::path::PathBuf, String> {
+  let dir = app.path().app_data_dir().map_err(|e| format!("No app data dir: {e}"))?;
+  Ok(dir.join(REGISTRY_FILE_NAME))
+}
+
+/// Same folder regardless of trailing separators or case-insensitive
+/// filesystems; falls back to the literal path when it no longer exists.
+pub fn same_path(a: &str, b: &str) -> bool {
+  let norm = |p: &str| {
+    std::fs::canonicalize(p)
+      .map(|c| c.to_string_lossy().to_string())
+      .unwrap_or_else(|_| p.trim_end_matches(['/', '\\']).to_string())
+  };
+  let (a, b) = (norm(a), norm(b));
+  if cfg!(any(target_os = "windows", target_os = "macos")) {
+    a.eq_ignore_ascii_case(&b)
+  } else {
+    a == b
+  }
+}
+
+/// Runs `f` on the registry and saves the result.
+pub fn update<T>(app: &tauri::AppHandle, f: impl FnOnce(&mut RegistryFileV1) -> T) -> Result<T, String> {
+  let _guard = REGISTRY_LOCK.lock().unwrap();
+  let path = registry_path(app)?;
+  let mut file: RegistryFileV1 = super::read_json_file(&path).unwrap_or_default();
+  let out = f(&mut file);
+  file.version = 1;
+  let txt = serde_json::to_vec_pretty(&file).map_err(|e| format!("Registry encode failed: {e}"))?;
+  super::atomic_write(&path, &txt)?;
+  Ok(out)
+}
+
+/// Reads the registry without saving it.
+fn read(app: &tauri::AppHandle) -> Result<RegistryFileV1, String> {
+  let _guard = REGISTRY_LOCK.lock().unwrap();
+  Ok(super::read_json_file(&registry_path(app)?).unwrap_or_default())
+}
+
+/// The entry for the workspace at `path`: the one registered under that
+/// folder, else the one carrying the folder's workspace id. Paths only match
+/// reliably while the folder exists, so call this before moving it.
+pub fn find(app: &tauri::AppHandle, path: &str) -> Result<Option<RecentWorkspaceV1>, String> {
+  let (id, _name) = read_identity(path);
+  let file = read(app)?;
+  let by_path = file.workspaces.iter().find(|w| same_path(&w.path, path));
+  let by_id = || id.as_ref().and_then(|id| file.workspaces.iter().find(|w| w.id.as_ref() == Some(id)));
+  Ok(by_path.or_else(by_id).cloned())
+}
+
+/// Points the entry for `from` at `to` after a workspace moved. Returns
+/// whether an entry was found.
+pub fn relocate(app: &tauri::AppHandle, from: &str, to: &str) -> Result<bool, String> {
+  update(app, |file| {
+    file.workspaces.retain(|w| same_path(&w.path, from) || !same_path(&w.path, to));
+    match file.workspaces.iter_mut().find(|w| same_path(&w.path, from)) {
+      Some(w) => {
+        w.path = to.to_string();
+        true
+      }
+      None => false,
+    }
+  })
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentWorkspaceInfo {
+  #[serde(flatten)]
+  entry: RecentWorkspaceV1,
+  sync_slug: String,
+  /// "available" | "missing" | "offline" | "not_workspace"
+  availability: String,
+}
+
+/// Mirrors `sanitizeSlug(inferSlugFromPath(path))` in `webdavSync.ts`.
+fn infer_slug(path: &str) -> String {
+  let last = path.split(['/', '\\']).rfind(|p| !p.is_empty()).unwrap_or("default");
+  let mut slug = String::new();
+  for c in last.trim().to_lowercase().chars() {
+    let c = if c.is_whitespace() || !(c.is_ascii_alphanumeric() || c == '-' || c == '_') { '-' } else { c };
+    if !(c == '-' && slug.ends_with('-')) {
+      slug.push(c);
+    }
+  }
+  let slug: String = slug.trim_matches(['-', '_']).chars().take(60).collect();
+  if slug.is_empty() {
+    "default".to_string()
+  } else {
+    slug
+  }
+}
+
+/// Root of the removable or network volume `path` lives on, if any.
+fn volume_root(path: &std::path::Path) -> Option<std::path::PathBuf> {
+  let mut comps = path.components();
+  if cfg!(target_os = "windows") {
+    let prefix = comps.next()?;
+    return match prefix {
+      std::path::Component::Prefix(_) => Some(std::path::PathBuf::from(format!("{}\\", prefix.as_os_str().to_string_lossy()))),
+      _ => None,
+    };
+  }
+  let parts: Vec<String> = comps.map(|c| c.as_os_str().to_string_lossy().to_string()).collect();
+  let depth = match parts.get(1).map(String::as_str) {
+    Some("Volumes") | Some("mnt") => 3,
+    Some("media") | Some("run") => 4,
+    _ => return None,
+  };
+  (parts.len() >= depth).then(|| parts[..depth].iter().collect())
+}
+
+fn availability(path: &str) -> String {
+  let p = std::path::Path::new(path);
+  if p.is_dir() {
+    let (wjson, db, _backups, _media) = super::workspace_paths(p);
+    return if wjson.exists() || db.exists() { "available" } else { "not_workspace" }.to_string();
+  }
+  match volume_root(p) {
+    Some(root) if !root.exists() => "offline".to_string(),
+    _ => "missing".to_string(),
+  }
+}
+
+fn read_identity(path: &str) -> (Option<String>, Option<String>) {
+  let meta = super::read_json_file::<serde_json::Value>(&std::path::Path::new(path).join(super::WORKSPACE_JSON_NAME));
+  let field = |k: &str| meta.as_ref().and_then(|m| m.get(k)).and_then(|v| v.as_str()).map(String::from).filter(|s| !s.trim().is_empty());
+  (field("id"), field("name"))
+}
+
+fn trim_recent(file: &mut RegistryFileV1) {
+  file.workspaces.sort_by_key(|w| (!w.pinned, std::cmp::Reverse(w.last_opened_at.unwrap_or(0))));
+  let mut unpinned = 0;
+  file.workspaces.retain(|w| {
+    if w.pinned {
+      return true;
+    }
+    unpinned += 1;
+    unpinned <= MAX_RECENT
+  });
+}
+
+/// Records that `path` was just opened.
+pub fn touch(app: &tauri::AppHandle, path: &str, sync_slug: Option<String>) -> Result<(), String> {
+  let (id, name) = read_identity(path);
+  update(app, |file| {
+    let idx = match file.workspaces.iter().position(|w| same_path(&w.path, path)) {
+      Some(i) => i,
+      None => {
+        file.workspaces.push(RecentWorkspaceV1 { path: path.to_string(), ..Default::default() });
+        file.workspaces.len() - 1
+      }
+    };
+    let w = &mut file.workspaces[idx];
+    w.path = path.to_string();
+    w.last_opened_at = Some(chrono::Utc::now().timestamp_millis());
+    if id.is_some() {
+      w.id = id;
+    }
+    if name.is_some() {
+      w.name = name;
+    }
+    if let Some(slug) = sync_slug.filter(|s| !s.trim().is_empty()) {
+      w.sync_slug = Some(slug);
+    }
+    trim_recent(file);
+  })
+}
+
+/// Remembers the slug a successful sync of `path` used. Only workspaces
+/// already in the list are updated, and the file is left alone when the slug
+/// is unchanged.
+pub fn record_sync_slug(app: &tauri::AppHandle, path: &str, slug: &str) -> Result<(), String> {
+  let slug = slug.trim();
+  if slug.is_empty() {
+    return Ok(());
+  }
+  let changed = read(app)?.workspaces.iter().any(|w| same_path(&w.path, path) && w.sync_slug.as_deref() != Some(slug));
+  if !changed {
+    return Ok(());
+  }
+  update(app, |file| {
+    if let Some(w) = file.workspaces.iter_mut().find(|w| same_path(&w.path, path)) {
+      w.sync_slug = Some(slug.to_string());
+    }
+  })
+}
+
+/// How long one folder may take to answer before it is listed as offline; a
+/// hung network mount would otherwise block the whole list.
+const PROBE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(3);
+
+/// Availability plus, when reachable, the id and name in `workspace.json`.
+fn probe(path: &str) -> (String, Option<String>, Option<String>) {
+  let availability = availability(path);
+  let (id, name) = if availability == "available" { read_identity(path) } else { (None, None) };
+  (availability, id, name)
+}
+
+/// Probes every entry on its own thread, so one slow folder costs at most
+/// `PROBE_TIMEOUT` and never holds up the others.
+fn describe(workspaces: Vec<RecentWorkspaceV1>) -> Vec<RecentWorkspaceInfo> {
+  let pending: Vec<_> = workspaces
+    .into_iter()
+    .map(|w| {
+      let (tx, rx) = std::sync::mpsc::channel();
+      let path = w.path.clone();
+      std::thread::spawn(move || {
+        let _ = tx.send(probe(&path));
+      });
+      (w, rx)
+    })
+    .collect();
+  let deadline = std::time::Instant::now() + PROBE_TIMEOUT;
+  pending
+    .into_iter()
+    .map(|(mut w, rx)| {
+      let wait = deadline.saturating_duration_since(std::time::Instant::now());
+      let (availability, id, name) = rx.recv_timeout(wait).unwrap_or_else(|_| ("offline".to_string(), None, None));
+      w.id = id.or(w.id.take());
+      w.name = name.or(w.name.take());
+      let sync_slug = w.sync_slug.clone().unwrap_or_else(|| infer_slug(&w.path));
+      RecentWorkspaceInfo { entry: w, sync_slug, availability }
+    })
+    .collect()
+}
+
+/// Known workspaces, pinned first, then most recently opened. Names and ids
+/// shown are read from the folders that are reachable; the file itself is
+/// only updated when a workspace is opened.
+#[tauri::command]
+pub async fn recent_workspaces_list(app: tauri::AppHandle) -> Result<Vec<RecentWorkspaceInfo>, String> {
+  let mut file = read(&app)?;
+  trim_recent(&mut file);
+  tauri::async_runtime::spawn_blocking(move || describe(file.workspaces))
+    .await
+    .map_err(|e| format!("Recent workspaces task failed: {e}"))
+}
+
+#[tauri::command]
+pub fn recent_workspaces_add(app: tauri::AppHandle, path: String, sync_slug: Option<String>) -> Result<(), String> {
+  if !std::path::Path::new(&path).is_dir() {
+    return Err("Workspace folder does not exist".to_string());
+  }
+  touch(&app, &path, sync_slug)
+}
+
+/// Forgets `path`; the folder itself is not touched.
+#[tauri::command]
+pub fn recent_workspaces_remove(app: tauri::AppHandle, path: String) -> Result<(), String> {
+  update(&app, |file| file.workspaces.retain(|w| !same_path(&w.path, &path)))
+}
+
+#[tauri::command]
+pub fn recent_workspaces_pin(app: tauri::AppHandle, path: String, pinned: bool) -> Result<(), String> {
+  let found = update(&app, |file| match file.workspaces.iter_mut().find(|w| same_path(&w.path, &path)) {
+    Some(w) => {
+      w.pinned = pinned;
+      trim_recent(file);
+      true
+    }
+    None => false,
+  })?;
+  if !found {
+    return Err("Workspace is not in the recent list".to_string());
+  }
+  Ok(())
+}
+
+/// Points an entry at the folder's new location, e.g. after the user moved
+/// it in Finder. Refuses a folder holding a different workspace.
+#[tauri::command]
+pub fn recent_workspaces_relocate(app: tauri::AppHandle, from: String, to: String) -> Result<(), String> {
+  if availability(&to) != "available" {
+    return Err("The new location is not a workspace folder".to_string());
+  }
+  let (new_id, _) = read_identity(&to);
+  let known_id = update(&app, |file| file.workspaces.iter().find(|w| same_path(&w.path, &from)).map(|w| w.id.clone()))?;
+  match known_id {
+    None => Err("Workspace is not in the recent list".to_string()),
+    Some(Some(old)) if new_id.as_deref().is_some_and(|n| n != old) => {
+      Err("That folder contains a different workspace".to_string())
+    }
+    Some(_) => relocate(&app, &from, &to).map(|_| ()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn infers_slug_like_webdav_sync() {
+    assert_eq!(infer_slug("/Users/ana/Documents/Casa Rural  Ñora/"), "casa-rural-ora");
+    assert_eq!(infer_slug("C:\\Workspaces\\Villa_Sol-2024"), "villa_sol-2024");
+    assert_eq!(infer_slug("/data/--My Place__"), "my-place");
+    assert_eq!(infer_slug("/"), "default");
+    assert_eq!(infer_slug("/tmp/!!!"), "default");
+    assert_eq!(infer_slug(&format!("/tmp/{}", "a".repeat(80))).len(), 60);
+  }
+}
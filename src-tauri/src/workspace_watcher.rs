@@ -0,0 +1,198 @@
+// Watches the open workspace folder for changes to `database.sqlite` made by
+// someone else (Dropbox, iCloud, a second machine on a network share).
+//
+// Every write the app makes records the sha256 of what it wrote; a change on
+// disk with any other hash is reported to the UI as `external-change`, before
+// the next `save_workspace` would overwrite it.
+
+use tauri::Emitter;
+
+pub const EXTERNAL_CHANGE_EVENT: &str = "external-change";
+/// Sync clients write in bursts; wait for this much quiet before hashing.
+const SETTLE: std::time::Duration = std::time::Duration::from_millis(750);
+
+#[derive(serde::Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ExternalChangeEvent {
+  path: String,
+  known_sha256: Option<String>,
+  /// `None` when the file was removed.
+  disk_sha256: Option<String>,
+  disk_modified_at: Option<i64>,
+  /// "reload" | "keep_local"
+  recommendation: String,
+  reason: String,
+}
+
+struct Watch {
+  _watcher: notify::RecommendedWatcher,
+}
+
+// Keyed by `database.sqlite` path (see `key`): hash of the bytes the app last
+// read or wrote.
+static KNOWN: std::sync::Mutex<Option<std::collections::HashMap<std::path::PathBuf, String>>> = std::sync::Mutex::new(None);
+// Keyed by workspace root (see `key`).
+static WATCHES: std::sync::Mutex<Option<std::collections::HashMap<std::path::PathBuf, Watch>>> = std::sync::Mutex::new(None);
+
+/// Map key for `path`, so callers holding a canonicalized path and callers
+/// holding the one the UI passed agree. Resolves symlinks and drops the
+/// Windows `\\?\` prefix; a file that does not exist is resolved through its
+/// folder, and a folder that does not exist is used as given.
+fn key(path: &std::path::Path) -> std::path::PathBuf {
+  let resolved = match std::fs::canonicalize(path) {
+    Ok(p) => p,
+    Err(_) => match (path.parent().and_then(|d| std::fs::canonicalize(d).ok()), path.file_name()) {
+      (Some(dir), Some(name)) => dir.join(name),
+      _ => return std::path::PathBuf::from(path.to_string_lossy().trim_end_matches(['/', '\\'])),
+    },
+  };
+  let s = resolved.to_string_lossy().to_string();
+  match s.strip_prefix(r"\\?\UNC\") {
+    Some(rest) => std::path::PathBuf::from(format!(r"\\{rest}")),
+    None => match s.strip_prefix(r"\\?\") {
+      Some(rest) => std::path::PathBuf::from(rest),
+      None => resolved,
+    },
+  }
+}
+
+/// Records `bytes` as the app's own copy of the database at `db`.
+pub fn record(db: &std::path::Path, bytes: &[u8]) {
+  let mut guard = KNOWN.lock().unwrap();
+  guard.get_or_insert_with(Default::default).insert(key(db), super::sha256_hex(bytes));
+}
+
+pub fn known_hash(db: &std::path::Path) -> Option<String> {
+  KNOWN.lock().unwrap().as_ref().and_then(|m| m.get(&key(db)).cloned())
+}
+
+fn modified_ms(path: &std::path::Path) -> Option<i64> {
+  std::fs::metadata(path)
+    .and_then(|m| m.modified())
+    .ok()
+    .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+    .map(|d| d.as_millis() as i64)
+}
+
+/// Compares the file on disk with the recorded hash. `None` when they match.
+fn check(root: &std::path::Path) -> Option<ExternalChangeEvent> {
+  let db = root.join(super::WORKSPACE_DB_NAME);
+  let known = known_hash(&db);
+  let bytes = std::fs::read(&db).ok();
+  let disk = bytes.as_deref().map(super::sha256_hex);
+  if disk.is_some() && disk == known {
+    return None;
+  }
+
+  let (recommendation, reason) = match bytes {
+    None => ("keep_local", "The database file was removed; saving will write it again"),
+    Some(b) if !super::is_workspace_db_bytes(&b) => {
+      ("keep_local", "The file on disk is not a complete database (possibly still syncing)")
+    }
+    Some(b) => match super::plain_db_bytes(root, b) {
+      Ok(plain) if super::workspace_db::open_in_memory(&plain, true).is_ok() => {
+        ("reload", "The database was changed by another program")
+      }
+      Ok(_) => ("keep_local", "The file on disk cannot be opened as a database"),
+      Err(_) => ("keep_local", "The file on disk cannot be decrypted with the current key"),
+    },
+  };
+  Some(ExternalChangeEvent {
+    path: root.to_string_lossy().to_string(),
+    known_sha256: known,
+    disk_sha256: disk,
+    disk_modified_at: modified_ms(&db),
+    recommendation: recommendation.to_string(),
+    reason: reason.to_string(),
+  })
+}
+
+fn run_checker(app: tauri::AppHandle, root: std::path::PathBuf, rx: std::sync::mpsc::Receiver<()>) {
+  let mut last_reported: Option<Option<String>> = None;
+  // Ends once the watcher (and with it the sender) is dropped.
+  while rx.recv().is_ok() {
+    loop {
+      match rx.recv_timeout(SETTLE) {
+        Ok(()) => continue,
+        Err(std::sync::mpsc::RecvTimeoutError::Timeout) => break,
+        Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => return,
+      }
+    }
+    match check(&root) {
+      // Each distinct disk state is reported once.
+      Some(ev) if last_reported.as_ref() != Some(&ev.disk_sha256) => {
+        last_reported = Some(ev.disk_sha256.clone());
+        let _ = app.emit(EXTERNAL_CHANGE_EVENT, ev);
+      }
+      Some(_) => {}
+      None => last_reported = None,
+    }
+  }
+}
+
+/// Starts watching `root`. Only one workspace is open at a time, so every
+/// other watch and the hashes recorded for other folders are dropped.
+pub fn watch(app: &tauri::AppHandle, root: &std::path::Path) -> Result<(), String> {
+  use notify::Watcher;
+  let (tx, rx) = std::sync::mpsc::channel();
+  let db_name = std::ffi::OsStr::new(super::WORKSPACE_DB_NAME);
+  let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+    if let Ok(ev) = res {
+      if ev.paths.iter().any(|p| p.file_name() == Some(db_name)) {
+        let _ = tx.send(());
+      }
+    }
+  })
+  .map_err(|e| format!("Failed creating file watcher: {e}"))?;
+  // Non-recursive on the folder, so replace-by-rename is seen as well.
+  watcher
+    .watch(root, notify::RecursiveMode::NonRecursive)
+    .map_err(|e| format!("Failed watching {}: {e}", root.display()))?;
+
+  let app = app.clone();
+  let checker_root = root.to_path_buf();
+  std::thread::spawn(move || run_checker(app, checker_root, rx));
+
+  let db_key = key(&root.join(super::WORKSPACE_DB_NAME));
+  if let Some(m) = KNOWN.lock().unwrap().as_mut() {
+    m.retain(|k, _| *k == db_key);
+  }
+  let mut guard = WATCHES.lock().unwrap();
+  let watches = guard.get_or_insert_with(Default::default);
+  watches.clear();
+  watches.insert(key(root), Watch { _watcher: watcher });
+  Ok(())
+}
+
+pub fn unwatch(root: &std::path::Path) {
+  if let Some(m) = WATCHES.lock().unwrap().as_mut() {
+    m.remove(&key(root));
+  }
+}
+
+/// Stops watching `path`, e.g. when the UI closes the workspace.
+#[tauri::command]
+pub fn workspace_watch_stop(path: String) -> Result<(), String> {
+  unwatch(std::path::Path::new(&path));
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn keys_agree_across_spellings() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("ws");
+    std::fs::create_dir(&root).unwrap();
+    let canonical = std::fs::canonicalize(&root).unwrap();
+    let db_name = super::super::WORKSPACE_DB_NAME;
+    assert_eq!(key(&root), key(&canonical));
+    // The database need not exist yet.
+    assert_eq!(key(&root.join(".").join(db_name)), key(&canonical.join(db_name)));
+
+    record(&root.join(db_name), b"one");
+    assert_eq!(known_hash(&canonical.join(db_name)), Some(super::super::sha256_hex(b"one")));
+  }
+}